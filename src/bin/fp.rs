@@ -1,318 +1,7893 @@
 #![allow(unused)]
 
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::{CommandFactory, Parser};
 use crossterm::{
     ExecutableCommand,
-    event::{self, Event, KeyCode, KeyEventKind},
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
     terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
 };
 use ratatui::{
     prelude::*,
-    widgets::{Block, Borders, Paragraph, Scrollbar, ScrollbarOrientation, ScrollbarState},
+    widgets::{
+        Block, Borders, Clear, List, ListItem, ListState, Paragraph, Scrollbar,
+        ScrollbarOrientation, ScrollbarState,
+    },
 };
 // use ratatui_themes::{Color as ThemesColor, Theme, ThemeName};
-use std::{io::stdout, path::PathBuf};
+use encoding_rs::{Encoding, UTF_8};
+#[cfg(feature = "syntect")]
+use file_peek::GrammarHighlighter;
+#[cfg(feature = "tree-sitter")]
+use file_peek::TreeHighlighter;
+use file_peek::{
+    ColorDepth, Language, Theme, contains_word, content_language, detect_language, display_width,
+    find_matches, find_urls, first_identifier, group_digits, has_ansi_colors, highlight,
+    highlight_with_comments, human_size, mark_matches, mark_ranges, max_scroll, rainbow_brackets,
+    render_ansi, scrollbar, to_ansi, to_html, word_columns,
+};
+use regex::Regex;
+#[cfg(feature = "syntect")]
+use std::cell::RefCell;
+use std::{
+    borrow::Cow,
+    cell::OnceCell,
+    collections::HashMap,
+    fs::File,
+    io::{IsTerminal, Read, Seek, SeekFrom, Write, stdout},
+    ops::{Range, RangeInclusive},
+    path::{Path, PathBuf},
+    process::{Command, ExitCode},
+    rc::Rc,
+    sync::{
+        OnceLock,
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, Sender, TryRecvError},
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Palettes fp comes with, by the name `:theme` knows them by.
+const BUILT_IN_THEMES: [(&str, Theme); 6] = [
+    ("dracula", Theme::DRACULA),
+    ("gruvbox", Theme::GRUVBOX),
+    ("monokai", Theme::MONOKAI),
+    ("nord", Theme::NORD),
+    ("solarized-dark", Theme::SOLARIZED_DARK),
+    ("solarized-light", Theme::SOLARIZED_LIGHT),
+];
+
+/// The built-in palettes and any from `peek/themes`, set once at startup.
+static THEMES: OnceLock<Vec<(String, Theme)>> = OnceLock::new();
+
+/// Index into `themes()` of the palette in use: picked at startup from
+/// `--theme`, `--light`/`--dark` or the terminal, and switched with
+/// `:theme` or `T`.
+static THEME: AtomicUsize = AtomicUsize::new(0);
+
+fn themes() -> &'static [(String, Theme)] {
+    THEMES.get_or_init(built_in_themes)
+}
+
+fn built_in_themes() -> Vec<(String, Theme)> {
+    BUILT_IN_THEMES
+        .iter()
+        .map(|(name, theme)| (name.to_string(), *theme))
+        .collect()
+}
+
+fn theme() -> &'static Theme {
+    &themes()[THEME.load(Ordering::Relaxed)].1
+}
+
+/// Colors the terminal can show, set once at startup from `--color-depth`
+/// or the environment.
+static COLOR_DEPTH: OnceLock<ColorDepth> = OnceLock::new();
+
+/// Swaps every RGB color drawn this frame for the nearest one the terminal
+/// has, so themes degrade instead of showing garbage on older terminals.
+fn fit_colors(frame: &mut Frame) {
+    let depth = COLOR_DEPTH.get().copied().unwrap_or(ColorDepth::TrueColor);
+    if depth == ColorDepth::TrueColor {
+        return;
+    }
+    for cell in &mut frame.buffer_mut().content {
+        cell.fg = depth.quantize(cell.fg);
+        cell.bg = depth.quantize(cell.bg);
+        cell.underline_color = depth.quantize(cell.underline_color);
+    }
+}
+
+/// Switches to the palette called `name`; `false` if there is none.
+fn set_theme(name: &str) -> bool {
+    match themes().iter().position(|(known, _)| known == name) {
+        Some(index) => {
+            THEME.store(index, Ordering::Relaxed);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Exit status when the `+/pattern` the viewer opened with matched nothing.
+const EXIT_NO_MATCH: u8 = 3;
+
+const EXIT_STATUS_HELP: &str = "Exit status: 0 after quitting normally, 1 when a file can't be \
+read, a start line is past the end when printing, or another error stops fp, 2 for a bad \
+command line, 3 when a +/pattern start search matched nothing";
+
+/// The `--help` account of `config.toml`, read by `Config::read_settings`.
+const CONFIG_HELP: &str = "Configuration: $XDG_CONFIG_HOME/peek/config.toml (or \
+~/.config/peek/config.toml) sets any flag by its long name, e.g. `line-numbers = true`, \
+`theme = \"gruvbox\"` or `ruler = [80, 100]`; `false` gives the flag's --no- form. A [keys] \
+table binds actions to keys, e.g. `quit = [\"q\", \"ctrl-c\"]`, and [files.\"*.log\"] \
+tables hold settings for matching file names, the first match applying. Flags typed on the \
+command line win over both.
+
+The file is a plain subset of TOML, shared by theme files in peek/themes: one `name = value` \
+per line, where a value is a quoted string, a bare number or boolean, or a one-line array of \
+them; `#` starts a comment outside quotes. Multi-line strings and arrays, inline tables, \
+dotted names and dates are not supported.";
 
 #[derive(Parser, Debug)]
 #[command(author, version, about = "Peek at file contents with smooth scrolling")]
+#[command(after_help = EXIT_STATUS_HELP)]
+#[command(after_long_help = format!("{CONFIG_HELP}\n\n{EXIT_STATUS_HELP}"))]
+// Config defaults come first on the command line, so a repeated flag must
+// take the later (typed) value instead of being an error
+#[command(args_override_self = true)]
 struct Args {
-    /// File to view
-    filename: PathBuf,
+    /// File(s) to view, `-` (once) for standard input. More than one opens
+    /// as side-by-side panes and needs --split, stdin included: `fp --split
+    /// a.rs - b.rs` shows piped input between the two files. With none, read
+    /// piped standard input, or else pick from recently viewed files
+    files: Vec<PathBuf>,
 
-    /// Fixed number of lines to display (default: 70)
-    #[arg(short, long, default_value = "70")]
-    lines: Option<usize>,
+    /// Show the files (or two views of one file) in side-by-side panes
+    #[arg(long)]
+    split: bool,
+
+    /// Compare exactly two files side by side (L unlinks scrolling)
+    #[arg(long, conflicts_with = "split")]
+    diff: bool,
+
+    /// Show only this many lines per pane, leaving the rest of the screen
+    /// blank (default: fill the terminal)
+    #[arg(short, long, value_parser = clap::value_parser!(u16).range(1..))]
+    lines: Option<u16>,
 
     /// Start displaying with this line at the top (1-based)
     #[arg(short = 's', long)]
     start_line: Option<usize>,
+
+    /// Start displaying at this percentage of the file (0-100)
+    #[arg(long, conflicts_with = "start_line", value_parser = clap::value_parser!(u8).range(..=100))]
+    start_percent: Option<u8>,
+
+    /// Start displaying at the line containing this byte offset (0-based)
+    #[arg(long, conflicts_with_all = ["start_line", "start_percent"])]
+    byte_offset: Option<usize>,
+
+    /// Show a condensed minimap of the whole file (toggle with M)
+    #[arg(long)]
+    minimap: bool,
+
+    /// Draw vertical guides through leading indentation (toggle with I)
+    #[arg(long)]
+    indent_guides: bool,
+
+    /// Tint the indented block the top line belongs to (toggle with S)
+    #[arg(long)]
+    scope: bool,
+
+    /// Open with the file statistics popup shown (toggle with i)
+    #[arg(long)]
+    stats: bool,
+
+    /// Show the file's mode, owner, size and modification time above it,
+    /// like `ls -l`
+    #[arg(long)]
+    info: bool,
+
+    /// Render Markdown formatting (on by default for .md files)
+    #[arg(long)]
+    markdown: bool,
+
+    /// Make searches match whole words only (toggle with :set word)
+    #[arg(short, long)]
+    word: bool,
+
+    /// Take search queries as regular expressions (toggle with Ctrl-r at
+    /// the / prompt, or :set regex)
+    #[arg(long)]
+    regex: bool,
+
+    /// Keep the first line pinned above the rest as a header row
+    /// (toggle with H)
+    #[arg(long)]
+    freeze_header: bool,
+
+    /// Number lines in a gutter on the left (# cycles through plain,
+    /// relative and no numbers)
+    #[arg(long)]
+    line_numbers: bool,
+
+    /// Number lines by their distance from the top line, as counts for
+    /// j, k and friends; the top line keeps its own number
+    #[arg(long)]
+    relative_numbers: bool,
+
+    /// Show each run of blank lines as a single blank line, like `cat -s`
+    /// (toggle with s)
+    #[arg(long)]
+    squeeze: bool,
+
+    /// Keep reading lines appended to the file, like `tail -f`, staying at
+    /// the bottom unless scrolled up (toggle with f)
+    #[arg(short, long)]
+    follow: bool,
+
+    /// Soft-wrap long lines onto the rows below instead of cutting them
+    /// off at the right edge (toggle with w)
+    #[arg(long)]
+    wrap: bool,
+
+    /// Show file sizes as exact byte counts instead of KiB/MiB (toggle
+    /// with :set bytes)
+    #[arg(long)]
+    bytes: bool,
+
+    /// Show comma-separated fields lined up in columns
+    #[arg(long, overrides_with = "tsv")]
+    csv: bool,
+
+    /// Show tab-separated fields lined up in columns
+    #[arg(long)]
+    tsv: bool,
+
+    /// Highlight with full language grammars: more languages and exact
+    /// multi-line strings and comments. The default when fp is built with
+    /// the `syntect` feature, as it is unless default features are off
+    #[arg(long, overrides_with = "fast")]
+    accurate: bool,
+
+    /// Highlight with the quick built-in tokenizer instead of grammars
+    #[arg(long)]
+    fast: bool,
+
+    /// Highlight Rust, Python and JavaScript from a full syntax tree of the
+    /// file. Needs fp built with the `tree-sitter` feature; other languages
+    /// keep their usual highlighting
+    #[arg(long)]
+    tree_sitter: bool,
+
+    /// Color brackets by nesting depth
+    #[arg(long)]
+    rainbow: bool,
+
+    /// Start line comments at this prefix instead of the built-in `//`, `#`
+    /// and `/*`, e.g. `;` or `--`; repeat for several
+    #[arg(long, value_name = "PREFIX", allow_hyphen_values = true)]
+    comment_prefix: Vec<String>,
+
+    /// Use the light theme (default: from COLORFGBG, else dark)
+    #[arg(long, overrides_with = "dark", overrides_with = "theme")]
+    light: bool,
+
+    /// Use the dark theme even on a terminal that reports a light background
+    #[arg(long, overrides_with = "theme")]
+    dark: bool,
+
+    /// Use the theme with this name: a built-in one such as nord or gruvbox,
+    /// or NAME.toml from the themes directory next to the config
+    #[arg(long, value_name = "NAME", overrides_with_all = ["light", "dark"])]
+    theme: Option<String>,
+
+    /// Print the files to stdout instead of paging them, highlighted when
+    /// stdout is a terminal; the default when it isn't one
+    #[arg(short, long, visible_alias = "plain")]
+    print: bool,
+
+    /// Write the first file as a standalone page of highlighted HTML to
+    /// this path and exit, numbering lines with --line-numbers
+    #[arg(long, value_name = "PATH", visible_alias = "export-html")]
+    export: Option<PathBuf>,
+
+    /// Fail on a missing file instead of offering similarly named ones
+    #[arg(long)]
+    no_picker: bool,
+
+    /// Ask for a second q before quitting
+    #[arg(long)]
+    confirm_quit: bool,
+
+    /// Keep Esc for cancelling and clearing; only q quits
+    #[arg(long)]
+    no_esc_quit: bool,
+
+    /// Colors the terminal supports: truecolor, 256, 16 or ansi (the basic
+    /// 8). Default: from COLORTERM and TERM
+    #[arg(long, value_name = "DEPTH")]
+    color_depth: Option<ColorDepth>,
+
+    /// Render plain text without syntax colors (also implied by
+    /// `--color never`)
+    #[arg(long)]
+    no_highlight: bool,
+
+    /// When to use color: auto (unless NO_COLOR is set or TERM is dumb),
+    /// always or never
+    #[arg(long, value_name = "WHEN", default_value = "auto")]
+    color: ColorWhen,
+
+    /// Show escape codes literally even when most lines carry ANSI colors,
+    /// rather than rendering them in place of highlighting
+    #[arg(long)]
+    no_auto_ansi: bool,
+
+    /// Lines of context kept above a line jumped to (search, :N, counted G)
+    /// instead of centering it, and on both sides of the V cursor as j and
+    /// k move it
+    #[arg(long)]
+    scrolloff: Option<usize>,
+
+    /// Lines from the previous page kept in view by PageDown/PageUp
+    #[arg(long, default_value = "2")]
+    page_overlap: usize,
+
+    /// Tint these text columns as line-length guides, e.g. 80 or 80,120
+    #[arg(long, value_delimiter = ',', value_parser = clap::value_parser!(u16).range(1..))]
+    ruler: Vec<u16>,
+
+    /// Lines longer than this many bytes are shown without syntax colors
+    #[arg(long, default_value = "5000")]
+    max_highlight_len: usize,
+
+    /// Number of columns a tab stop spans
+    #[arg(long, default_value = "4")]
+    tab_width: usize,
+
+    /// Animate page, search and gg/G jumps instead of snapping
+    #[arg(long)]
+    smooth: bool,
+
+    /// Length of a --smooth scroll animation in milliseconds
+    #[arg(long, default_value = "150", requires = "smooth")]
+    smooth_ms: u64,
+
+    // Off switches for the flags above, so that a flag config.toml turns
+    // on can be turned off again from the command line
+    /// Undo --minimap
+    #[arg(long, overrides_with = "minimap", hide = true)]
+    no_minimap: bool,
+    /// Undo --indent-guides
+    #[arg(long, overrides_with = "indent_guides", hide = true)]
+    no_indent_guides: bool,
+    /// Undo --scope
+    #[arg(long, overrides_with = "scope", hide = true)]
+    no_scope: bool,
+    /// Undo --stats
+    #[arg(long, overrides_with = "stats", hide = true)]
+    no_stats: bool,
+    /// Undo --info
+    #[arg(long, overrides_with = "info", hide = true)]
+    no_info: bool,
+    /// Undo --markdown
+    #[arg(long, overrides_with = "markdown", hide = true)]
+    no_markdown: bool,
+    /// Undo --word
+    #[arg(long, overrides_with = "word", hide = true)]
+    no_word: bool,
+    /// Undo --regex
+    #[arg(long, overrides_with = "regex", hide = true)]
+    no_regex: bool,
+    /// Undo --freeze-header
+    #[arg(long, overrides_with = "freeze_header", hide = true)]
+    no_freeze_header: bool,
+    /// Undo --line-numbers
+    #[arg(long, overrides_with = "line_numbers", hide = true)]
+    no_line_numbers: bool,
+    /// Undo --relative-numbers
+    #[arg(long, overrides_with = "relative_numbers", hide = true)]
+    no_relative_numbers: bool,
+    /// Undo --squeeze
+    #[arg(long, overrides_with = "squeeze", hide = true)]
+    no_squeeze: bool,
+    /// Undo --follow
+    #[arg(long, overrides_with = "follow", hide = true)]
+    no_follow: bool,
+    /// Undo --wrap
+    #[arg(long, overrides_with = "wrap", hide = true)]
+    no_wrap: bool,
+    /// Undo --bytes
+    #[arg(long, overrides_with = "bytes", hide = true)]
+    no_bytes: bool,
+    /// Undo --csv
+    #[arg(long, overrides_with = "csv", hide = true)]
+    no_csv: bool,
+    /// Undo --tsv
+    #[arg(long, overrides_with = "tsv", hide = true)]
+    no_tsv: bool,
+    /// Undo --tree-sitter
+    #[arg(long, overrides_with = "tree_sitter", hide = true)]
+    no_tree_sitter: bool,
+    /// Undo --rainbow
+    #[arg(long, overrides_with = "rainbow", hide = true)]
+    no_rainbow: bool,
+    /// Undo --confirm-quit
+    #[arg(long, overrides_with = "confirm_quit", hide = true)]
+    no_confirm_quit: bool,
+    /// Undo --smooth
+    #[arg(long, overrides_with = "smooth", hide = true)]
+    no_smooth: bool,
 }
 
-// Dracula palette (official hex → RGB)
-const DRACULA_BG: Color = Color::Rgb(40, 42, 54); // #282A36
-const DRACULA_FG: Color = Color::Rgb(248, 248, 242); // #F8F8F2
-const DRACULA_COMMENT: Color = Color::Rgb(98, 114, 164); // #6272A4
-const DRACULA_PURPLE: Color = Color::Rgb(189, 147, 249); // #BD93F9 (keywords)
-const DRACULA_CYAN: Color = Color::Rgb(139, 233, 253); // #8BE9FD (functions, types)
-const DRACULA_GREEN: Color = Color::Rgb(80, 250, 123); // #50FA7B (strings)
-const DRACULA_ORANGE: Color = Color::Rgb(255, 184, 108); // #FFB86C (numbers/constants)
-const DRACULA_RED: Color = Color::Rgb(255, 85, 85); // #FF5555 (errors/warnings)
-const DRACULA_PINK: Color = Color::Rgb(255, 121, 198); // #FF79C6 (special)
-const DRACULA_YELLOW: Color = Color::Rgb(241, 250, 140); // #F1FA8C (warnings/numbers alt)
-const DRACULA_CURRENT_LINE: Color = Color::Rgb(68, 71, 90); // #44475A (subtle highlight)
-
-fn highlight_line(line: &str) -> Line<'_> {
-    let mut spans = Vec::new();
-    let chars: Vec<char> = line.chars().collect();
-    let mut i = 0;
+/// `--color`: whether to color output at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColorWhen {
+    Auto,
+    Always,
+    Never,
+}
 
-    while i < chars.len() {
-        let c = chars[i];
+impl std::str::FromStr for ColorWhen {
+    type Err = String;
 
-        // --- Comments ---
-        if (c == '/' && i + 1 < chars.len() && chars[i + 1] == '/')
-            || c == '#'
-            || (c == '/' && i + 1 < chars.len() && chars[i + 1] == '*')
-        {
-            let comment = &line[i..];
-            spans.push(Span::styled(
-                comment.to_string(),
-                Style::default().fg(DRACULA_COMMENT).italic(),
-            ));
-            break;
+    fn from_str(when: &str) -> Result<Self, Self::Err> {
+        match when {
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            _ => Err(format!(
+                "unknown color choice `{when}` (expected auto, always or never)"
+            )),
         }
+    }
+}
 
-        if c.is_whitespace() {
-            spans.push(Span::raw(c.to_string()));
-            i += 1;
-            continue;
-        }
+/// Display toggles that can be flipped at runtime.
+#[derive(Debug, Clone)]
+struct ViewOptions {
+    /// Syntax and UI colors; off means genuinely monochrome output
+    highlight: bool,
+    /// Render every buffer as Markdown, not just `.md` files
+    markdown: bool,
+    /// Field separator when showing delimited data in columns
+    delimiter: Option<char>,
+    /// Line 0 stays at the top of every pane while the rest scrolls
+    freeze_header: bool,
+    /// Line numbers in a gutter before the text
+    line_numbers: bool,
+    /// Gutter numbers count rows from the top line instead
+    relative_numbers: bool,
+    /// Runs of blank lines show as one, as in `cat -s`
+    squeeze: bool,
+    /// Files are watched for appended lines, as with `tail -f`
+    follow: bool,
+    /// Long lines carry on in the rows below rather than past the edge
+    wrap: bool,
+    minimap: bool,
+    indent_guides: bool,
+    scope: bool,
+    stats: bool,
+    /// `ls -l` line for the focused file above the panes
+    info: bool,
+    rainbow: bool,
+    /// New searches match whole words only
+    whole_word: bool,
+    /// New searches take the query as a regular expression
+    regex: bool,
+    /// Lines not matching the active search are dimmed
+    focus: bool,
+    /// Grammar-based highlighting (`--accurate`)
+    accurate: bool,
+    /// Syntax-tree highlighting where a grammar is bundled (`--tree-sitter`)
+    tree_sitter: bool,
+    tab_width: usize,
+    max_highlight_len: usize,
+    /// 1-based text columns marked with a faint vertical line
+    rulers: Vec<u16>,
+    /// Line comment starts replacing the built-in ones (`--comment-prefix`)
+    comment_prefixes: Vec<String>,
+    /// File sizes in bytes rather than KiB/MiB
+    exact_bytes: bool,
+    /// Render ANSI colors in files that carry them (off with `--no-auto-ansi`)
+    auto_ansi: bool,
+}
 
-        if c.is_alphabetic() || c == '_' || c.is_ascii_digit() {
-            let start = i;
-            i += 1;
+impl ViewOptions {
+    fn search_mode(&self) -> SearchMode {
+        SearchMode {
+            whole_word: self.whole_word,
+            regex: self.regex,
+        }
+    }
+}
 
-            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
-                i += 1;
+impl From<&Args> for ViewOptions {
+    fn from(args: &Args) -> Self {
+        let color = match args.color {
+            ColorWhen::Always => true,
+            ColorWhen::Never => false,
+            // https://no-color.org: any non-empty value disables color
+            ColorWhen::Auto => {
+                let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+                let dumb = std::env::var("TERM").is_ok_and(|term| term == "dumb");
+                !no_color && !dumb
             }
+        };
+        Self {
+            highlight: !args.no_highlight && color,
+            markdown: args.markdown,
+            delimiter: if args.csv {
+                Some(',')
+            } else if args.tsv {
+                Some('\t')
+            } else {
+                None
+            },
+            freeze_header: args.freeze_header,
+            line_numbers: args.line_numbers,
+            relative_numbers: args.relative_numbers,
+            squeeze: args.squeeze,
+            follow: args.follow,
+            wrap: args.wrap,
+            minimap: args.minimap,
+            indent_guides: args.indent_guides,
+            scope: args.scope,
+            stats: args.stats,
+            info: args.info,
+            rainbow: args.rainbow,
+            whole_word: args.word,
+            regex: args.regex,
+            focus: false,
+            accurate: args.accurate || (cfg!(feature = "syntect") && !args.fast),
+            tree_sitter: args.tree_sitter,
+            tab_width: args.tab_width.max(1),
+            max_highlight_len: args.max_highlight_len,
+            rulers: args.ruler.clone(),
+            comment_prefixes: args.comment_prefix.clone(),
+            exact_bytes: args.bytes,
+            auto_ansi: !args.no_auto_ansi,
+        }
+    }
+}
 
-            let word: String = chars[start..i].iter().collect();
+/// Columns of leading whitespace with tabs expanded to `tab_width` stops,
+/// or `None` for a blank line, which belongs to whatever block surrounds it.
+fn indent_width(line: &str, tab_width: usize) -> Option<usize> {
+    let mut width = 0;
+    for c in line.chars() {
+        match c {
+            ' ' => width += 1,
+            '\t' => width += tab_width - width % tab_width,
+            c if c.is_whitespace() => {}
+            _ => return Some(width),
+        }
+    }
+    None
+}
 
-            let mut style = Style::default().fg(DRACULA_FG);
+/// First and last line of the indentation block `line` is in. A line that
+/// opens a block (the next line is indented deeper) scopes to its body;
+/// otherwise the block runs up and down until a less indented line.
+/// Top-level lines outside any body have no block.
+fn indent_block(lines: &[String], line: usize, tab_width: usize) -> Option<(usize, usize)> {
+    let indent = |index: usize| indent_width(&lines[index], tab_width);
+    let own = indent(line)?;
+    let next = (line + 1..lines.len()).find_map(indent);
+    let (start, depth) = match next {
+        Some(next) if next > own => (line, own + 1),
+        _ if own == 0 => return None,
+        _ => {
+            let start = (0..line)
+                .rev()
+                .find(|&index| indent(index).is_some_and(|width| width < own))
+                .map_or(0, |outer| outer + 1);
+            (start, own)
+        }
+    };
+    let mut end = line;
+    for index in line + 1..lines.len() {
+        match indent(index) {
+            Some(width) if width < depth => break,
+            Some(_) => end = index,
+            None => {}
+        }
+    }
+    // Skip blank lines at the top of the block too
+    let start = (start..=line)
+        .find(|&index| indent(index).is_some())
+        .unwrap_or(line);
+    Some((start, end))
+}
 
-            if is_keyword(&word) {
-                style = style.fg(DRACULA_PURPLE).bold();
+/// Splits one CSV/TSV record into fields. Double-quoted fields may contain
+/// the delimiter, with `""` standing for a literal quote.
+fn split_fields(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
             }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            c if c == delimiter && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
 
-            if is_type(&word) {
-                style = style.fg(DRACULA_CYAN);
+/// Lays delimited `lines` out in columns, each as wide as its widest field
+/// among these lines and colored in turn from the palette.
+fn align_fields<'a>(lines: impl Iterator<Item = &'a str>, delimiter: char) -> Vec<Line<'static>> {
+    let rows: Vec<Vec<String>> = lines.map(|line| split_fields(line, delimiter)).collect();
+    let mut widths: Vec<usize> = Vec::new();
+    for row in &rows {
+        for (column, field) in row.iter().enumerate() {
+            if column == widths.len() {
+                widths.push(0);
             }
+            widths[column] = widths[column].max(display_width(field));
+        }
+    }
 
-            if word.parse::<f64>().is_ok() {
-                style = style.fg(DRACULA_ORANGE);
+    let palette = [
+        theme().keyword,
+        theme().types,
+        theme().string,
+        theme().number,
+        theme().special,
+    ];
+    let separator_style = Style::default().fg(theme().comment);
+    rows.into_iter()
+        .map(|row| {
+            let last = row.len() - 1;
+            let mut spans = Vec::new();
+            for (column, field) in row.into_iter().enumerate() {
+                let style = Style::default().fg(palette[column % palette.len()]);
+                if column == last {
+                    spans.push(Span::styled(field, style));
+                    break;
+                }
+                let padding = widths[column] - display_width(&field);
+                spans.push(Span::styled(field + &" ".repeat(padding), style));
+                spans.push(Span::styled(" │ ", separator_style));
             }
+            Line::from(spans)
+        })
+        .collect()
+}
 
-            spans.push(Span::styled(word, style));
-            continue;
+/// Highlights `line` with the built-in comment syntax, unless
+/// `--comment-prefix` gave the prefixes to use instead.
+fn highlight_code(line: &str, language: Language, comment_prefixes: &[String]) -> Line<'static> {
+    if comment_prefixes.is_empty() {
+        highlight(line, language, theme())
+    } else {
+        highlight_with_comments(line, language, theme(), comment_prefixes)
+    }
+}
+
+/// Highlights `line`, replacing its leading indentation with `│` guides at
+/// every tab stop. Tabs expand to the next stop so content columns are kept.
+fn highlight_with_guides<'a>(
+    line: &'a str,
+    language: Language,
+    tab_width: usize,
+    comment_prefixes: &[String],
+) -> Line<'a> {
+    let tab_width = tab_width.max(1);
+    let indent_len = line.len() - line.trim_start().len();
+
+    let mut guides = String::new();
+    let mut column = 0;
+    for c in line[..indent_len].chars() {
+        let width = if c == '\t' {
+            tab_width - column % tab_width
+        } else {
+            1
+        };
+        for _ in 0..width {
+            guides.push(if column % tab_width == 0 { '│' } else { ' ' });
+            column += 1;
         }
+    }
+
+    let rest = highlight_code(&line[indent_len..], language, comment_prefixes);
+    if guides.is_empty() {
+        return rest;
+    }
+
+    let mut spans = vec![Span::styled(
+        guides,
+        Style::default().fg(theme().comment).dim(),
+    )];
+    spans.extend(rest.spans);
+    Line::from(spans)
+}
+
+/// Gives every whole-word `word` in the line a reference background.
+/// Grammar highlighting leaves identifiers inside wider spans such as
+/// `" count "`, so spans are cut at the word's edges.
+fn highlight_references<'a>(line: Line<'a>, word: &str) -> Line<'a> {
+    let style = Style::default().bg(theme().selection).bold();
+    mark_matches(line, word, true, style)
+}
+
+/// Where a Markdown line sits relative to fenced code blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MarkdownBlock {
+    Prose,
+    /// An opening or closing ``` / ~~~ line
+    Fence,
+    /// Inside a fence, highlighted as the language named on the opening line
+    Code(Language),
+}
+
+/// Classifies every line so fenced code can be highlighted as code even
+/// though rendering is otherwise line-by-line.
+fn markdown_blocks(lines: &[String]) -> Vec<MarkdownBlock> {
+    let mut open: Option<Language> = None;
+    lines
+        .iter()
+        .map(|line| {
+            let trimmed = line.trim_start();
+            if let Some(info) = trimmed
+                .strip_prefix("```")
+                .or_else(|| trimmed.strip_prefix("~~~"))
+            {
+                open = match open {
+                    Some(_) => None,
+                    None => Some(fence_language(info)),
+                };
+                MarkdownBlock::Fence
+            } else {
+                open.map_or(MarkdownBlock::Prose, MarkdownBlock::Code)
+            }
+        })
+        .collect()
+}
+
+/// Language named by a fence info string such as `rust` or `py`.
+fn fence_language(info: &str) -> Language {
+    let name = info.split_whitespace().next().unwrap_or("");
+    match name.to_ascii_lowercase().as_str() {
+        "rust" => Language::Rust,
+        "python" => Language::Python,
+        "bash" | "shell" | "console" => Language::Shell,
+        "javascript" => Language::JavaScript,
+        "typescript" => Language::TypeScript,
+        "" => Language::Plain,
+        ext => detect_language(Path::new(&format!("fence.{ext}"))),
+    }
+}
+
+/// Renders one line of Markdown prose: headings, quotes, list markers and
+/// inline `**bold**`, `*italic*`, `` `code` `` and `[links](...)`.
+fn highlight_markdown_line(line: &str) -> Line<'static> {
+    let trimmed = line.trim_start();
+    let indent = &line[..line.len() - trimmed.len()];
 
-        // --- Symbols / punctuation ---
-        spans.push(Span::styled(c.to_string(), Style::default().fg(DRACULA_FG)));
-        i += 1;
+    let level = trimmed.chars().take_while(|&c| c == '#').count();
+    if (1..=6).contains(&level) && (trimmed.len() == level || trimmed[level..].starts_with(' ')) {
+        let colors = [
+            theme().keyword,
+            theme().special,
+            theme().types,
+            theme().string,
+            theme().number,
+            theme().warning,
+        ];
+        return Line::from(Span::styled(
+            line.to_string(),
+            Style::default().fg(colors[level - 1]).bold(),
+        ));
     }
 
-    if spans.is_empty() {
-        Line::from(line)
+    let marker_style = Style::default().fg(theme().special);
+    let rule: String = trimmed.chars().filter(|c| !c.is_whitespace()).collect();
+    if rule.len() >= 3
+        && ["-", "*", "_"]
+            .iter()
+            .any(|c| rule.replace(c, "").is_empty())
+    {
+        return Line::from(Span::styled(
+            line.to_string(),
+            Style::default().fg(theme().comment),
+        ));
+    }
+
+    let mut spans = vec![Span::raw(indent.to_string())];
+    if let Some(rest) = trimmed.strip_prefix('>') {
+        spans.push(Span::styled("▎", Style::default().fg(theme().comment)));
+        let quote_style = Style::default().fg(theme().comment).italic();
+        spans.extend(markdown_inline(rest, quote_style));
+        return Line::from(spans);
+    }
+
+    let text = if let Some(rest) = ["- ", "* ", "+ "]
+        .iter()
+        .find_map(|marker| trimmed.strip_prefix(marker))
+    {
+        // Same width as the source marker so nested items stay aligned
+        spans.push(Span::styled("• ", marker_style));
+        rest
+    } else if let Some(dot) = trimmed
+        .find(". ")
+        .filter(|&dot| dot > 0 && trimmed[..dot].chars().all(|c| c.is_ascii_digit()))
+    {
+        spans.push(Span::styled(trimmed[..dot + 2].to_string(), marker_style));
+        &trimmed[dot + 2..]
     } else {
-        Line::from(spans)
+        trimmed
+    };
+
+    spans.extend(markdown_inline(
+        text,
+        Style::default().fg(theme().foreground),
+    ));
+    Line::from(spans)
+}
+
+/// Index of the first occurrence of `closing` in `chars` at or after `from`.
+fn find_closing(chars: &[char], from: usize, closing: &[char]) -> Option<usize> {
+    (from..chars.len()).find(|&i| chars[i..].starts_with(closing))
+}
+
+fn markdown_inline(text: &str, base: Style) -> Vec<Span<'static>> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut spans = Vec::new();
+    let mut plain = String::new();
+    let mut i = 0;
+
+    let flush = |plain: &mut String, spans: &mut Vec<Span<'static>>| {
+        if !plain.is_empty() {
+            spans.push(Span::styled(std::mem::take(plain), base));
+        }
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        let styled = match c {
+            '`' => find_closing(&chars, i + 1, &['`']).map(|end| {
+                let code: String = chars[i + 1..end].iter().collect();
+                (
+                    end + 1,
+                    Span::styled(code, Style::default().fg(theme().string)),
+                )
+            }),
+            '*' | '_' if chars.get(i + 1) == Some(&c) => {
+                find_closing(&chars, i + 2, &[c, c]).map(|end| {
+                    let bold: String = chars[i + 2..end].iter().collect();
+                    (end + 2, Span::styled(bold, base.bold()))
+                })
+            }
+            '*' | '_' if chars.get(i + 1).is_some_and(|next| !next.is_whitespace()) => {
+                find_closing(&chars, i + 1, &[c]).map(|end| {
+                    let italic: String = chars[i + 1..end].iter().collect();
+                    (end + 1, Span::styled(italic, base.italic()))
+                })
+            }
+            '[' => find_closing(&chars, i + 1, &[']', '(']).and_then(|mid| {
+                let end = find_closing(&chars, mid + 2, &[')'])?;
+                let label: String = chars[i + 1..mid].iter().collect();
+                Some((end + 1, Span::styled(label, theme().url_style())))
+            }),
+            _ => None,
+        };
+
+        match styled {
+            Some((next, span)) => {
+                flush(&mut plain, &mut spans);
+                spans.push(span);
+                i = next;
+            }
+            None => {
+                plain.push(c);
+                i += 1;
+            }
+        }
     }
+
+    flush(&mut plain, &mut spans);
+    spans
 }
 
-fn is_keyword(word: &str) -> bool {
-    matches!(
-        word,
-        "fn" | "let"
-            | "mut"
-            | "const"
-            | "struct"
-            | "enum"
-            | "impl"
-            | "trait"
-            | "pub"
-            | "use"
-            | "if"
-            | "else"
-            | "match"
-            | "for"
-            | "while"
-            | "loop"
-            | "return"
-            | "break"
-            | "continue"
-            | "true"
-            | "false"
-            | "None"
-            | "Some"
-            | "Ok"
-            | "Err"
-            | "self"
-            | "macro_rules"
-    )
+// Minimap geometry: each cell covers MINIMAP_COLS_PER_CELL source columns
+const MINIMAP_WIDTH: u16 = 8;
+const MINIMAP_COLS_PER_CELL: usize = 10;
+const MINIMAP_SHADES: [char; 5] = [' ', '░', '▒', '▓', '█'];
+
+/// Downsampled view of the whole file, one row per `lines_per_row` lines.
+/// Built once per viewport height so redraws only restyle the cached rows.
+struct Minimap {
+    height: usize,
+    lines_per_row: usize,
+    rows: Vec<String>,
 }
 
-fn is_type(word: &str) -> bool {
-    matches!(
-        word,
-        "String"
-            | "Vec"
-            | "Option"
-            | "Result"
-            | "i32"
-            | "u64"
-            | "f64"
-            | "bool"
-            | "char"
-            | "usize"
-            | "PathBuf"
-            | "Result"
-            | "Option"
-            | "Cell"
-            | "Arc"
-            | "Rc"
-    )
+impl Minimap {
+    fn build(file_lines: &[String], height: usize) -> Self {
+        let height = height.max(1);
+        let lines_per_row = file_lines.len().div_ceil(height).max(1);
+        let rows = file_lines
+            .chunks(lines_per_row)
+            .map(|bucket| {
+                (0..MINIMAP_WIDTH as usize)
+                    .map(|cell| {
+                        let start = cell * MINIMAP_COLS_PER_CELL;
+                        let filled: usize = bucket
+                            .iter()
+                            .map(|line| {
+                                line.chars()
+                                    .skip(start)
+                                    .take(MINIMAP_COLS_PER_CELL)
+                                    .filter(|c| !c.is_whitespace())
+                                    .count()
+                            })
+                            .sum();
+                        let capacity = bucket.len() * MINIMAP_COLS_PER_CELL;
+                        let level = (filled * (MINIMAP_SHADES.len() - 1)).div_ceil(capacity);
+                        MINIMAP_SHADES[level.min(MINIMAP_SHADES.len() - 1)]
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            height,
+            lines_per_row,
+            rows,
+        }
+    }
+
+    fn render(&self, scroll: usize, visible_lines: usize, color: bool) -> Vec<Line<'_>> {
+        let first = scroll / self.lines_per_row;
+        let last = (scroll + visible_lines.max(1) - 1) / self.lines_per_row;
+
+        self.rows
+            .iter()
+            .enumerate()
+            .map(|(i, row)| {
+                let in_view = (first..=last).contains(&i);
+                let style = match (in_view, color) {
+                    (true, true) => Style::default()
+                        .fg(theme().foreground)
+                        .bg(theme().selection),
+                    (false, true) => Style::default().fg(theme().comment),
+                    (true, false) => Style::default().reversed(),
+                    (false, false) => Style::default(),
+                };
+                Line::styled(row.as_str(), style)
+            })
+            .collect()
+    }
 }
 
-fn is_string_delim(c: char) -> bool {
-    c == '"' || c == '\''
+/// Match counts above this are shown as `>999` rather than exactly. A new
+/// search stops looking once it has found more, and goes on between
+/// keypresses or when a jump needs it.
+const MAX_MATCH_COUNT: usize = 999;
+
+/// Lines a search runs through between keypresses once past its first
+/// `MAX_MATCH_COUNT` matches.
+const SEARCH_CHUNK: usize = 100_000;
+
+/// How a query matches: literally or as a regex, anywhere or only as a
+/// whole word.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct SearchMode {
+    whole_word: bool,
+    regex: bool,
 }
 
-fn run_app<B: Backend>(
-    terminal: &mut Terminal<B>,
-    file_lines: Vec<String>,
-    fixed_height: Option<usize>,
-    start_line: Option<usize>,
-    file_name: PathBuf,
-) -> Result<()> {
-    let total_lines = file_lines.len();
-    let mut scroll = start_line.unwrap_or(1).saturating_sub(1); // 0-based
-    // let theme = Theme::new(ThemeName::Dracula);
-    // let palette = theme.palette();
-    // let mut app = App::new(file_lines, fixed_height, scroll, file_name);
+/// A committed search: the query plus the lines found to contain it.
+#[derive(Debug, Default)]
+struct Search {
+    query: String,
+    mode: SearchMode,
+    /// The query compiled, in regex mode
+    regex: Option<Regex>,
+    /// Why the query isn't a valid regex; nothing matches then
+    error: Option<String>,
+    /// Made with `?`: `n` goes up the file and `N` down
+    backward: bool,
+    /// Indices of matching lines, in file order, among the first `scanned`
+    matches: Vec<usize>,
+    /// Lines from the top looked through so far
+    scanned: usize,
+    /// Only lines in the `:range` count, when one is set
+    range: Option<RangeInclusive<usize>>,
+    /// Index into `matches` of the match last jumped to.
+    current: Option<usize>,
+    /// Scrollbar rows holding a match, cached with the track height and
+    /// row count they were computed for
+    marks: Option<(usize, usize, Vec<usize>)>,
+}
 
-    loop {
-        terminal.draw(|frame| {
-            let size = frame.area();
+impl Search {
+    /// A search for `query` that has looked at nothing yet.
+    fn new(query: String, mode: SearchMode) -> Self {
+        let compiled = mode.regex.then(|| {
+            let pattern = match mode.whole_word {
+                true => format!(r"\b(?:{query})\b"),
+                false => query.clone(),
+            };
+            // The last line of regex's report says what is wrong
+            Regex::new(&pattern).map_err(|err| {
+                let report = err.to_string();
+                let last = report.lines().last().unwrap_or_default().trim();
+                last.trim_start_matches("error: ").to_string()
+            })
+        });
+        let (regex, error) = match compiled {
+            Some(Ok(regex)) => (Some(regex), None),
+            Some(Err(error)) => (None, Some(error)),
+            None => (None, None),
+        };
+        Self {
+            query,
+            mode,
+            regex,
+            error,
+            ..Self::default()
+        }
+    }
+
+    /// Looks through the file until more than `MAX_MATCH_COUNT` matches
+    /// turn up, or to the end when there are fewer.
+    fn start(&mut self, file_lines: &[String]) {
+        while self.matches.len() <= MAX_MATCH_COUNT && !self.is_complete(file_lines) {
+            self.scan_to(file_lines, self.scanned + 1);
+        }
+    }
 
-            // Determine visible height (leave 2 lines for border + status)
-            let available_height = size.height.saturating_sub(2) as usize;
-            let visible_lines = fixed_height
-                .unwrap_or(available_height)
-                .min(available_height);
+    /// Every line matching `query` in `file_lines`.
+    fn all_matches(query: String, file_lines: &[String], mode: SearchMode) -> Vec<usize> {
+        let mut search = Self::new(query, mode);
+        search.finish(file_lines);
+        search.matches
+    }
 
-            // Clamp scroll
-            if total_lines <= visible_lines {
-                scroll = 0;
-            } else {
-                scroll = scroll.min(total_lines - visible_lines);
+    fn is_complete(&self, file_lines: &[String]) -> bool {
+        self.scanned >= file_lines.len()
+    }
+
+    /// Looks through the lines up to `end`.
+    fn scan_to(&mut self, file_lines: &[String], end: usize) {
+        let end = end.min(file_lines.len());
+        if self.error.is_none() {
+            let unscanned = file_lines.iter().enumerate().take(end).skip(self.scanned);
+            for (index, line) in unscanned {
+                let in_range = self
+                    .range
+                    .as_ref()
+                    .is_none_or(|range| range.contains(&index));
+                if in_range && self.is_match(line) {
+                    self.matches.push(index);
+                    self.marks = None;
+                }
             }
+        }
+        self.scanned = self.scanned.max(end);
+    }
+
+    fn finish(&mut self, file_lines: &[String]) {
+        self.scan_to(file_lines, file_lines.len());
+    }
+
+    /// Looks on until a match at or after `line` turns up, or the file ends.
+    fn scan_from(&mut self, file_lines: &[String], line: usize) {
+        while self.matches.last().is_none_or(|&found| found < line) && !self.is_complete(file_lines)
+        {
+            self.scan_to(file_lines, self.scanned.max(line) + 1);
+        }
+    }
+
+    fn is_match(&self, line: &str) -> bool {
+        match &self.regex {
+            Some(regex) => regex.is_match(line),
+            None if self.mode.whole_word => contains_word(line, &self.query),
+            None => line.contains(self.query.as_str()),
+        }
+    }
+
+    /// Byte ranges of the query's matches in `text`, for marking them.
+    fn ranges(&self, text: &str) -> Vec<Range<usize>> {
+        match &self.regex {
+            Some(regex) => regex
+                .find_iter(text)
+                .map(|found| found.range())
+                .filter(|range| !range.is_empty())
+                .collect(),
+            None => find_matches(text, &self.query, self.mode.whole_word),
+        }
+    }
+
+    /// Jumps to the first match at or after `line`, wrapping to the top.
+    fn first_from(&mut self, line: usize, file_lines: &[String]) -> Option<usize> {
+        self.scan_from(file_lines, line);
+        let index = self.matches.iter().position(|&m| m >= line).unwrap_or(0);
+        self.select(index)
+    }
+
+    /// Jumps to the first match at or after `line`, or with `backward` the
+    /// last one before it, wrapping around the file.
+    fn first_toward(
+        &mut self,
+        line: usize,
+        backward: bool,
+        file_lines: &[String],
+    ) -> Option<usize> {
+        match backward {
+            true => self.prev_before(line, file_lines),
+            false => self.first_from(line, file_lines),
+        }
+    }
+
+    /// Jumps to the first match on a line after `line`, wrapping to the top.
+    fn next_after(&mut self, line: usize, file_lines: &[String]) -> Option<usize> {
+        self.scan_from(file_lines, line + 1);
+        let index = self.matches.partition_point(|&m| m <= line);
+        self.select(index)
+    }
+
+    /// Jumps to the last match on a line before `line`, wrapping to the
+    /// bottom.
+    fn prev_before(&mut self, line: usize, file_lines: &[String]) -> Option<usize> {
+        self.scan_to(file_lines, line);
+        let mut index = self.matches.partition_point(|&m| m < line);
+        if index == 0 {
+            // Wrapping round to the bottom needs the last match of all
+            self.finish(file_lines);
+            index = self.matches.len();
+        }
+        self.select(index + self.matches.len().saturating_sub(1))
+    }
 
-            // let content_lines: Vec<Line<'_>> = file_lines
-            //     .iter()
-            //     .skip(scroll)
-            //     .take(visible_lines)
-            //     .map(|s| Line::from(s.as_str()))
-            //     .collect();
+    fn select(&mut self, index: usize) -> Option<usize> {
+        if self.matches.is_empty() {
+            return None;
+        }
+        let index = index % self.matches.len();
+        self.current = Some(index);
+        Some(self.matches[index])
+    }
 
-            let content_lines: Vec<Line<'_>> = file_lines
+    /// Offsets along a scrollbar track of `track` cells at which matches
+    /// fall, given each match's view row. Recomputed only when the track or
+    /// row count changes, or on a new search.
+    fn track_marks(
+        &mut self,
+        track: usize,
+        rows: usize,
+        row_of: impl Fn(usize) -> usize,
+    ) -> &[usize] {
+        if self
+            .marks
+            .as_ref()
+            .is_none_or(|(height, total, _)| (*height, *total) != (track, rows))
+        {
+            let mut marks: Vec<usize> = self
+                .matches
                 .iter()
-                .skip(scroll)
-                .take(visible_lines)
-                .map(|s| highlight_line(s))
+                .map(|&line| (row_of(line) * track / rows.max(1)).min(track.saturating_sub(1)))
                 .collect();
+            marks.dedup();
+            self.marks = Some((track, rows, marks));
+        }
+        self.marks.as_ref().map_or(&[], |(_, _, marks)| marks)
+    }
 
-            let paragraph = Paragraph::new(content_lines)
-                .style(
-                    Style::default().fg(Color::Rgb(248, 248, 242)), // .bg(Color::Rgb(40, 42, 54)),
-                )
-                .block(
-                    Block::default()
-                        .borders(Borders::ALL)
-                        .title(format!(" {} ", file_name.display())),
-                )
-                .scroll((0, 0)); // No horizontal scroll for now
-
-            let status = format!(
-                "Line {}-{} of {} | ↑↓/j k: line | PgUp/PgDn: page | g/G: top/bottom | q: quit",
-                scroll + 1,
-                (scroll + visible_lines).min(total_lines),
-                total_lines
-            );
+    /// The query as shown in the status line, after `/` or `?` and in
+    /// Vim's `\<word\>` form for whole-word searches.
+    fn label(&self) -> String {
+        let symbol = if self.backward { '?' } else { '/' };
+        match self.mode {
+            SearchMode { regex: true, .. } => format!("{symbol}{} (regex)", self.query),
+            SearchMode {
+                whole_word: true, ..
+            } => format!("{symbol}\\<{}\\>", self.query),
+            SearchMode { .. } => format!("{symbol}{}", self.query),
+        }
+    }
 
-            let status_line = Line::from(status).style(Style::default().fg(Color::Yellow));
+    /// Vim-style `[current/total]` indicator.
+    fn count_label(&self) -> String {
+        let total = if self.matches.len() > MAX_MATCH_COUNT {
+            format!(">{MAX_MATCH_COUNT}")
+        } else {
+            self.matches.len().to_string()
+        };
+        let current = self.current.map_or(0, |i| i + 1);
+        format!("[{current}/{total}]")
+    }
+}
 
-            // Layout: content + status
-            let chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([Constraint::Min(1), Constraint::Length(1)])
-                .split(size);
+/// How long a lone `g` waits for a second `g` before acting as "go to top".
+const PENDING_G_TIMEOUT: Duration = Duration::from_millis(500);
 
-            frame.render_widget(paragraph, chunks[0]);
-            frame.render_widget(Paragraph::new(status_line), chunks[1]);
+/// Redraw interval while a smooth scroll is in flight (~60 fps).
+const FRAME_INTERVAL: Duration = Duration::from_millis(16);
 
-            // Vertical scrollbar
-            let mut scrollbar_state =
-                ScrollbarState::new(total_lines.saturating_sub(visible_lines)).position(scroll);
-            frame.render_stateful_widget(
-                Scrollbar::default().orientation(ScrollbarOrientation::VerticalRight),
-                chunks[0],
-                &mut scrollbar_state,
-            );
-        })?;
+/// An eased transition of a pane's drawn scroll towards `Pane::scroll`.
+#[derive(Debug, Clone, Copy)]
+struct ScrollAnimation {
+    from: f64,
+    start: Instant,
+    duration: Duration,
+}
 
-        if let Event::Key(key) = event::read()? {
-            if key.kind != KeyEventKind::Press {
-                continue;
-            }
-            match key.code {
-                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
-                KeyCode::Char('j') | KeyCode::Down => {
-                    if scroll < total_lines.saturating_sub(1) {
-                        scroll += 1;
-                    }
-                }
-                KeyCode::Char('k') | KeyCode::Up => {
-                    scroll = scroll.saturating_sub(1);
-                }
-                KeyCode::PageDown => {
-                    let visible = fixed_height.unwrap_or(terminal.size()?.height as usize - 2);
-                    scroll = scroll
-                        .saturating_add(visible)
-                        .min(total_lines.saturating_sub(1));
-                }
-                KeyCode::PageUp => {
-                    let visible = fixed_height.unwrap_or(terminal.size()?.height as usize - 2);
-                    scroll = scroll.saturating_sub(visible);
-                }
-                KeyCode::Char('g') => scroll = 0,
-                KeyCode::Char('G') => {
-                    let visible = fixed_height.unwrap_or(terminal.size()?.height as usize - 2);
-                    scroll = total_lines.saturating_sub(visible);
-                }
-                _ => {}
-            }
+impl ScrollAnimation {
+    /// Drawn position for a scroll heading to `target`, or `None` once done.
+    fn position(&self, target: usize, now: Instant) -> Option<f64> {
+        let elapsed = now.duration_since(self.start);
+        if elapsed >= self.duration {
+            return None;
         }
+        let t = elapsed.as_secs_f64() / self.duration.as_secs_f64();
+        // Ease-out cubic: fast start, gentle landing
+        let eased = 1.0 - (1.0 - t).powi(3);
+        Some(self.from + (target as f64 - self.from) * eased)
     }
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+/// Vim-style numeric prefix and half-typed `gg`, carried between keypresses.
+#[derive(Debug, Default)]
+struct Motion {
+    count: Option<usize>,
+    pending_g: bool,
+}
 
-    let lines = std::fs::read_to_string(&args.filename)
-        .with_context(|| format!("Failed to read file: {}", args.filename.display()))?
-        .lines()
-        .map(|s| s.to_string())
-        .collect::<Vec<_>>();
+impl Motion {
+    fn push_digit(&mut self, digit: u32) {
+        let count = self.count.unwrap_or(0);
+        self.count = Some(count.saturating_mul(10).saturating_add(digit as usize));
+    }
 
-    if lines.is_empty() {
-        eprintln!("File is empty.");
-        return Ok(());
+    /// Whether `c` extends the count; `0` only counts once a count has started.
+    fn accepts_digit(&self, c: char) -> bool {
+        matches!(c, '1'..='9') || (c == '0' && self.count.is_some())
     }
+}
 
-    enable_raw_mode()?;
-    stdout().execute(EnterAlternateScreen)?;
-    let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
+/// What a key does away from prompts and panels. `[keys]` in
+/// `config.toml` binds keys to these by the names in `ACTIONS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Quit,
+    /// Esc: drops a count, the stats panel, `*`, a search or a filter,
+    /// quitting once there is nothing left to drop
+    Cancel,
+    Stats,
+    Search,
+    SearchBackward,
+    Filter,
+    Command,
+    Reference,
+    OpenUrl,
+    Export,
+    Edit,
+    Visual,
+    SetMark,
+    JumpToMark,
+    Marks,
+    JumpBack,
+    JumpForward,
+    Window,
+    Results,
+    NextMatch,
+    PrevMatch,
+    ParagraphDown,
+    ParagraphUp,
+    WordRight,
+    WordLeft,
+    Right,
+    Left,
+    LineStart,
+    LineEnd,
+    Down,
+    Up,
+    PageDown,
+    PageUp,
+    /// Pressed twice, or once and left, goes to the top
+    Top,
+    Bottom,
+    Link,
+    Minimap,
+    IndentGuides,
+    Scope,
+    Header,
+    Numbers,
+    Focus,
+    Squeeze,
+    Follow,
+    Wrap,
+    Theme,
+}
 
-    let res = run_app(
-        &mut terminal,
-        lines,
-        args.lines,
-        args.start_line,
-        args.filename,
-    );
+/// Every action by its name in `config.toml`, with the keys it has unless
+/// configured otherwise.
+const ACTIONS: &[(Action, &str, &[&str])] = &[
+    (Action::Quit, "quit", &["q"]),
+    (Action::Cancel, "cancel", &["esc"]),
+    (Action::Stats, "stats", &["i"]),
+    (Action::Search, "search", &["/"]),
+    (Action::SearchBackward, "search-backward", &["?"]),
+    (Action::Filter, "filter", &["&"]),
+    (Action::Command, "command", &[":"]),
+    (Action::Reference, "reference", &["*"]),
+    (Action::OpenUrl, "open-url", &["o"]),
+    (Action::Export, "export", &["e"]),
+    (Action::Edit, "edit", &["v"]),
+    (Action::Visual, "visual", &["V"]),
+    (Action::SetMark, "set-mark", &["m"]),
+    (Action::JumpToMark, "jump-to-mark", &["'"]),
+    (Action::Marks, "marks", &["`"]),
+    (Action::JumpBack, "jump-back", &["ctrl-o"]),
+    // Terminals deliver Ctrl-i as Tab
+    (Action::JumpForward, "jump-forward", &["ctrl-i", "tab"]),
+    (Action::Window, "window", &["ctrl-w"]),
+    (Action::Results, "results", &["R"]),
+    (Action::NextMatch, "next-match", &["n"]),
+    (Action::PrevMatch, "prev-match", &["N"]),
+    (Action::ParagraphDown, "paragraph-down", &["}"]),
+    (Action::ParagraphUp, "paragraph-up", &["{"]),
+    (Action::WordRight, "word-right", &["W", "shift-right"]),
+    (Action::WordLeft, "word-left", &["B", "shift-left"]),
+    (Action::Right, "right", &["l", "right"]),
+    (Action::Left, "left", &["h", "left"]),
+    (Action::LineStart, "line-start", &["0", "home"]),
+    (Action::LineEnd, "line-end", &["$", "end"]),
+    (Action::Down, "down", &["j", "down"]),
+    (Action::Up, "up", &["k", "up"]),
+    (Action::PageDown, "page-down", &["pagedown"]),
+    (Action::PageUp, "page-up", &["pageup"]),
+    (Action::Top, "top", &["g"]),
+    (Action::Bottom, "bottom", &["G"]),
+    (Action::Link, "link", &["L"]),
+    (Action::Minimap, "minimap", &["M"]),
+    (Action::IndentGuides, "indent-guides", &["I"]),
+    (Action::Scope, "scope", &["S"]),
+    (Action::Header, "header", &["H"]),
+    (Action::Numbers, "numbers", &["#"]),
+    (Action::Focus, "focus", &["F"]),
+    (Action::Squeeze, "squeeze", &["s"]),
+    (Action::Follow, "follow", &["f"]),
+    (Action::Wrap, "wrap", &["w"]),
+    (Action::Theme, "theme", &["T"]),
+];
 
-    disable_raw_mode()?;
-    stdout().execute(LeaveAlternateScreen)?;
+/// A key and the modifiers that tell it apart: Ctrl and Alt, and Shift on
+/// keys other than characters, which carry it in their case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct KeyBinding {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    fn of(key: KeyEvent) -> Self {
+        let mut modifiers =
+            key.modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        if let KeyCode::Char(_) = key.code {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        Self {
+            code: key.code,
+            modifiers,
+        }
+    }
+
+    /// The key called `name`: a character such as `q` or `G`, or a name such
+    /// as `esc` or `pagedown`, after any `ctrl-`, `alt-` or `shift-`.
+    fn parse(name: &str) -> Option<Self> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = name;
+        while let Some((prefix, key)) = rest.split_once('-').filter(|(_, key)| !key.is_empty()) {
+            modifiers |= match prefix.to_ascii_lowercase().as_str() {
+                "ctrl" | "c" => KeyModifiers::CONTROL,
+                "alt" | "a" | "meta" | "m" => KeyModifiers::ALT,
+                "shift" | "s" => KeyModifiers::SHIFT,
+                _ => break,
+            };
+            rest = key;
+        }
 
-    res
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            // Ctrl turns letters lowercase on the way in
+            (Some(c), None) if modifiers.contains(KeyModifiers::CONTROL) => {
+                KeyCode::Char(c.to_ascii_lowercase())
+            }
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match rest.to_ascii_lowercase().as_str() {
+                "esc" | "escape" => KeyCode::Esc,
+                "enter" | "return" => KeyCode::Enter,
+                "tab" => KeyCode::Tab,
+                "space" => KeyCode::Char(' '),
+                "backspace" => KeyCode::Backspace,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "pageup" | "pgup" => KeyCode::PageUp,
+                "pagedown" | "pgdn" => KeyCode::PageDown,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                _ => return None,
+            },
+        };
+        Some(Self::of(KeyEvent::new(code, modifiers)))
+    }
+}
+
+/// Which action each key runs: the defaults in `ACTIONS`, with any
+/// `[keys]` from `config.toml` in place of an action's own.
+#[derive(Debug, Clone)]
+struct Keymap(HashMap<KeyBinding, Action>);
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = ACTIONS.iter().flat_map(|&(action, _, keys)| {
+            keys.iter()
+                .filter_map(move |key| Some((KeyBinding::parse(key)?, action)))
+        });
+        Self(bindings.collect())
+    }
+}
+
+impl Keymap {
+    fn action(&self, key: KeyEvent) -> Option<Action> {
+        self.0.get(&KeyBinding::of(key)).copied()
+    }
+
+    /// Gives `action` these keys instead of its defaults, taking them from
+    /// any action that had them.
+    fn bind(&mut self, action: Action, keys: Vec<KeyBinding>) {
+        self.0.retain(|_, bound| *bound != action);
+        for key in keys {
+            self.0.insert(key, action);
+        }
+    }
+}
+
+/// Whether keystrokes drive navigation or are being typed into a prompt.
+#[derive(Debug)]
+enum Mode {
+    Normal,
+    Prompt(Prompt, String),
+}
+
+/// What the letter after `m` or `'` does with the mark it names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MarkAction {
+    Set,
+    Jump,
+}
+
+/// What a line typed at the prompt is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Prompt {
+    /// `/pattern` or `?pattern`: jump between matches, shown while typing;
+    /// `origin` is the scroll to return to on Esc
+    Search { origin: usize, backward: bool },
+    /// `&pattern`: hide every line that doesn't match
+    Filter,
+    /// `:command`, or `:N`, `:N%` or `:$` to go to a line, previewed while
+    /// typing; `origin` is the scroll to return to on Esc
+    Command { origin: usize },
+}
+
+impl Prompt {
+    fn symbol(self) -> char {
+        match self {
+            Prompt::Search {
+                backward: false, ..
+            } => '/',
+            Prompt::Search { backward: true, .. } => '?',
+            Prompt::Filter => '&',
+            Prompt::Command { .. } => ':',
+        }
+    }
+}
+
+/// 0-based line for a typed 1-based line number, `N%` of the way through
+/// a file of `total_lines`, or `$` for its last line. Numbers too large to
+/// parse still count, as "the end"; anything else is a command.
+fn goto_target(input: &str, total_lines: usize) -> Option<usize> {
+    let is_number = |text: &str| !text.is_empty() && text.bytes().all(|byte| byte.is_ascii_digit());
+    if input == "$" {
+        return Some(total_lines.saturating_sub(1));
+    }
+    if let Some(percent) = input.strip_suffix('%').filter(|percent| is_number(percent)) {
+        let percent = percent.parse().unwrap_or(100);
+        return Some(percent_to_line(percent, total_lines) - 1);
+    }
+    if !is_number(input) {
+        return None;
+    }
+    Some(
+        input
+            .parse::<usize>()
+            .unwrap_or(usize::MAX)
+            .saturating_sub(1),
+    )
+}
+
+/// Names `:` commands Tab-complete to.
+const COMMANDS: [&str; 6] = ["nohlsearch", "quit", "range", "set", "theme", "write"];
+
+/// Tab completion for a `:` command name: the one command `input` starts,
+/// or the longest prefix shared by every command it starts.
+fn complete_command(input: &str) -> Option<String> {
+    if input.contains(' ') {
+        return None;
+    }
+    let matching: Vec<&str> = COMMANDS
+        .into_iter()
+        .filter(|command| command.starts_with(input))
+        .collect();
+    match matching.as_slice() {
+        [] => None,
+        [only] => Some(format!("{only} ")),
+        [first, rest @ ..] => {
+            let shared = rest.iter().fold(first.len(), |len, command| {
+                first
+                    .bytes()
+                    .zip(command.bytes())
+                    .take(len)
+                    .take_while(|(a, b)| a == b)
+                    .count()
+            });
+            Some(first[..shared].to_string())
+        }
+    }
+}
+
+/// 1-based line found `percent` of the way through a file of `total_lines`.
+fn percent_to_line(percent: u8, total_lines: usize) -> usize {
+    total_lines.saturating_sub(1) * percent.min(100) as usize / 100 + 1
+}
+
+/// How many lines end in each style, counted as a file loads.
+#[derive(Clone, Copy, Debug, Default)]
+struct LineEndings {
+    lf: usize,
+    crlf: usize,
+    cr: usize,
+}
+
+impl LineEndings {
+    /// Tallies the line breaks in `bytes`. A lone `\r` counts as an old
+    /// Mac ending even though only `\n` splits lines.
+    fn count(bytes: &[u8]) -> Self {
+        let mut endings = Self::default();
+        let mut bytes = bytes.iter().peekable();
+        while let Some(&byte) = bytes.next() {
+            match byte {
+                b'\n' => endings.lf += 1,
+                b'\r' if bytes.next_if_eq(&&b'\n').is_some() => endings.crlf += 1,
+                b'\r' => endings.cr += 1,
+                _ => {}
+            }
+        }
+        endings
+    }
+
+    fn add(&mut self, other: Self) {
+        self.lf += other.lf;
+        self.crlf += other.crlf;
+        self.cr += other.cr;
+    }
+
+    /// `LF`, `CRLF` or `CR`, `mixed` when more than one style appears, or
+    /// `None` for a file without any line breaks.
+    fn label(self) -> Option<&'static str> {
+        match (self.lf > 0, self.crlf > 0, self.cr > 0) {
+            (false, false, false) => None,
+            (true, false, false) => Some("LF"),
+            (false, true, false) => Some("CRLF"),
+            (false, false, true) => Some("CR"),
+            _ => Some("mixed"),
+        }
+    }
+
+    /// Whether the status line should mention them; `LF` (or none at all)
+    /// is the unremarkable default.
+    fn is_notable(self) -> bool {
+        self.label().is_some_and(|label| label != "LF")
+    }
+}
+
+/// `count` with thousands separators, for line and word counts.
+fn grouped(count: usize) -> String {
+    group_digits(count as u64)
+}
+
+/// A file size as a byte count, or in binary units like `1.2 MiB`.
+fn format_size(bytes: u64, exact: bool) -> String {
+    if exact {
+        format!("{} B", group_digits(bytes))
+    } else {
+        human_size(bytes)
+    }
+}
+
+/// Summary shown by the `i` popup, computed the first time it is opened.
+#[derive(Debug)]
+struct FileStats {
+    lines: usize,
+    words: usize,
+    chars: usize,
+    bytes: u64,
+    longest_line: usize,
+    language: Language,
+    modified: Option<SystemTime>,
+    encoding: &'static Encoding,
+    endings: LineEndings,
+}
+
+impl FileStats {
+    fn compute(file_lines: &[String], path: &Path, language: Language) -> Self {
+        let metadata = std::fs::metadata(path).ok();
+        let bytes = metadata.as_ref().map_or_else(
+            || file_lines.iter().map(|line| line.len() as u64 + 1).sum(),
+            |metadata| metadata.len(),
+        );
+
+        Self {
+            lines: file_lines.len(),
+            words: file_lines
+                .iter()
+                .map(|line| line.split_whitespace().count())
+                .sum(),
+            chars: file_lines.iter().map(|line| line.chars().count()).sum(),
+            bytes,
+            longest_line: file_lines
+                .iter()
+                .map(|line| line.chars().count())
+                .max()
+                .unwrap_or(0),
+            language,
+            modified: metadata.and_then(|metadata| metadata.modified().ok()),
+            encoding: UTF_8,
+            endings: LineEndings::default(),
+        }
+    }
+
+    /// Popup rows; `exact_bytes` gives the size in bytes rather than KiB/MiB.
+    fn to_lines(&self, exact_bytes: bool) -> Vec<Line<'static>> {
+        let modified = self
+            .modified
+            .map_or_else(|| "—".to_string(), format_system_time);
+        let rows = [
+            ("Lines", grouped(self.lines)),
+            ("Words", grouped(self.words)),
+            ("Characters", grouped(self.chars)),
+            ("Size", format_size(self.bytes, exact_bytes)),
+            ("Language", self.language.name().to_string()),
+            ("Longest line", grouped(self.longest_line)),
+            ("Modified", modified),
+            ("Encoding", self.encoding.name().to_string()),
+            (
+                "Line endings",
+                self.endings.label().unwrap_or("—").to_string(),
+            ),
+        ];
+
+        rows.into_iter()
+            .map(|(label, value)| {
+                // Mixed endings usually mean something went wrong
+                let color = if value == "mixed" {
+                    theme().error
+                } else {
+                    theme().foreground
+                };
+                Line::from(vec![
+                    Span::styled(format!("{label:<14}"), Style::default().fg(theme().types)),
+                    Span::styled(value, Style::default().fg(color)),
+                ])
+            })
+            .collect()
+    }
+}
+
+/// What `ls -l` shows about a file, for the `--info` bar.
+#[derive(Debug)]
+struct FileInfo {
+    mode: String,
+    owner: String,
+    group: String,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+impl FileInfo {
+    #[cfg(unix)]
+    fn read(path: &Path) -> Option<Self> {
+        use std::os::unix::fs::MetadataExt;
+        let metadata = std::fs::metadata(path).ok()?;
+        let (uid, gid) = (metadata.uid(), metadata.gid());
+        Some(Self {
+            mode: mode_string(metadata.mode()),
+            owner: id_name("/etc/passwd", uid).unwrap_or_else(|| uid.to_string()),
+            group: id_name("/etc/group", gid).unwrap_or_else(|| gid.to_string()),
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+
+    /// Windows has no owner or permission bits to speak of; read-only is
+    /// the one attribute shown.
+    #[cfg(not(unix))]
+    fn read(path: &Path) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        let kind = if metadata.is_dir() { 'd' } else { '-' };
+        let write = if metadata.permissions().readonly() {
+            '-'
+        } else {
+            'w'
+        };
+        Some(Self {
+            mode: format!("{kind}r{write}-r{write}-r{write}-"),
+            owner: "-".to_string(),
+            group: "-".to_string(),
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+
+    fn to_line(&self, name: &Path, exact_bytes: bool) -> Line<'static> {
+        let modified = self
+            .modified
+            .map_or_else(|| "—".to_string(), format_system_time);
+        let fields = [
+            (self.mode.clone(), theme().keyword),
+            (format!("{} {}", self.owner, self.group), theme().types),
+            (format_size(self.size, exact_bytes), theme().number),
+            (modified, theme().comment),
+            (name.display().to_string(), theme().foreground),
+        ];
+        let mut spans = Vec::new();
+        for (text, color) in fields {
+            if !spans.is_empty() {
+                spans.push(Span::raw(" "));
+            }
+            spans.push(Span::styled(text, Style::default().fg(color)));
+        }
+        Line::from(spans)
+    }
+}
+
+/// `ls -l` permission string for a Unix `st_mode`, e.g. `-rw-r--r--`.
+#[cfg_attr(not(unix), allow(dead_code))]
+fn mode_string(mode: u32) -> String {
+    let kind = match mode & 0o170_000 {
+        0o040_000 => 'd',
+        0o120_000 => 'l',
+        0o010_000 => 'p',
+        0o020_000 => 'c',
+        0o060_000 => 'b',
+        0o140_000 => 's',
+        _ => '-',
+    };
+    let mut text = String::from(kind);
+    // Owner, group, other; the execute slot also shows setuid, setgid and
+    // sticky, lowercase when execute is set too
+    for (shift, special, special_char) in [(6, 0o4000, 's'), (3, 0o2000, 's'), (0, 0o1000, 't')] {
+        let bits = mode >> shift;
+        text.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        text.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        text.push(match (bits & 0o1 != 0, mode & special != 0) {
+            (true, true) => special_char,
+            (false, true) => special_char.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    text
+}
+
+/// Name given to `id` in a passwd-format file (`name:x:id:...`).
+#[cfg(unix)]
+fn id_name(database: &str, id: u32) -> Option<String> {
+    let text = std::fs::read_to_string(database).ok()?;
+    text.lines().find_map(|entry| {
+        let mut fields = entry.split(':');
+        let name = fields.next()?;
+        let entry_id = fields.nth(1)?.parse::<u32>().ok()?;
+        (entry_id == id).then(|| name.to_string())
+    })
+}
+
+/// Formats `time` as `YYYY-MM-DD HH:MM:SS UTC` without pulling in a date crate.
+fn format_system_time(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+
+    // Civil-from-days (Howard Hinnant), valid for dates after the epoch
+    let z = days as i64 + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} UTC",
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
+
+/// Drawn in the last column of a line that is wider than the viewport.
+const TRUNCATION_MARKER: &str = "→";
+
+/// Drops the first `columns` display cells of `line` for horizontal scrolling.
+/// A wide character cut in half leaves blank cells in its place.
+fn skip_columns(mut line: Line<'_>, columns: usize) -> Line<'_> {
+    let mut skip = columns;
+    if skip == 0 {
+        return line;
+    }
+    let mut spans = Vec::new();
+    for span in std::mem::take(&mut line.spans) {
+        if skip == 0 {
+            spans.push(span);
+            continue;
+        }
+        let mut kept = String::new();
+        for cluster in span.content.graphemes(true) {
+            let width = cluster.width();
+            if skip == 0 {
+                kept.push_str(cluster);
+            } else if width > skip {
+                kept.extend(std::iter::repeat_n(' ', width - skip));
+                skip = 0;
+            } else {
+                skip -= width;
+            }
+        }
+        if !kept.is_empty() {
+            spans.push(Span::styled(kept, span.style));
+        }
+    }
+    line.spans = spans;
+    line
+}
+
+/// Splits `line` into rows of at most `width` display cells for `--wrap`.
+/// A wide character that would straddle the edge starts the next row.
+fn wrap_columns(line: Line<'_>, width: usize) -> Vec<Line<'_>> {
+    let mut rows = vec![Line::default().style(line.style)];
+    let mut used = 0;
+    for span in line.spans {
+        let mut kept = String::new();
+        for cluster in span.content.graphemes(true) {
+            let cluster_width = cluster.width();
+            if used > 0 && used + cluster_width > width {
+                if !kept.is_empty() {
+                    let row = rows.last_mut().expect("rows start with one");
+                    row.spans
+                        .push(Span::styled(std::mem::take(&mut kept), span.style));
+                }
+                rows.push(Line::default().style(line.style));
+                used = 0;
+            }
+            kept.push_str(cluster);
+            used += cluster_width;
+        }
+        if !kept.is_empty() {
+            let row = rows.last_mut().expect("rows start with one");
+            row.spans.push(Span::styled(kept, span.style));
+        }
+    }
+    rows
+}
+
+/// Truncates `text` to at most `width` display cells, keeping the start.
+/// Grapheme clusters are kept or dropped whole.
+fn fit_width(text: &str, width: usize) -> String {
+    let mut used = 0;
+    text.graphemes(true)
+        .take_while(|cluster| {
+            used += cluster.width();
+            used <= width
+        })
+        .collect()
+}
+
+/// Truncates `text` to at most `width` display cells, keeping the end.
+fn fit_width_end(text: &str, width: usize) -> String {
+    let mut used = 0;
+    let mut kept: Vec<&str> = text
+        .graphemes(true)
+        .rev()
+        .take_while(|cluster| {
+            used += cluster.width();
+            used <= width
+        })
+        .collect();
+    kept.reverse();
+    kept.concat()
+}
+
+/// A `width` x `height` rectangle centred in `area`, shrunk to fit.
+fn centered_rect(width: u16, height: u16, area: Rect) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+/// 1-based line holding the byte just after `bytes`, the start of a file
+/// as stored. They are decoded as `encoding` (or the one a BOM names), so
+/// a BOM, `\r\n` endings and multi-byte encodings all count as on disk.
+fn byte_offset_to_line(bytes: &[u8], encoding: &'static Encoding) -> usize {
+    let (text, _, _) = encoding.decode(bytes);
+    text.matches('\n').count() + 1
+}
+
+/// Where the viewer opens: a 1-based line, or the first match of a pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum StartCommand {
+    Line(usize),
+    Search(String),
+    /// `--start-percent`, resolved once the file's length is known
+    Percent(u8),
+    /// `--byte-offset`, resolved against the loaded lines
+    ByteOffset(usize),
+}
+
+impl StartCommand {
+    /// Parses a `less`-style `+N` or `+/pattern` argument.
+    fn parse(arg: &str) -> Result<Self> {
+        let command = arg.strip_prefix('+').unwrap_or(arg);
+        if let Some(pattern) = command.strip_prefix('/') {
+            if pattern.is_empty() {
+                anyhow::bail!("Invalid startup command `{arg}`: empty search pattern");
+            }
+            return Ok(StartCommand::Search(pattern.to_string()));
+        }
+        command.parse().map(StartCommand::Line).map_err(|_| {
+            anyhow::anyhow!("Invalid startup command `{arg}`: expected +N or +/pattern")
+        })
+    }
+}
+
+/// Pulls `+N` / `+/pattern` arguments out before clap sees them. Only
+/// positional slots count, so an option value like `--comment-prefix +` is
+/// passed through, and anything after a bare `--` is left alone so files
+/// named `+foo` stay reachable.
+fn split_start_command(
+    args: impl IntoIterator<Item = std::ffi::OsString>,
+) -> Result<(Vec<std::ffi::OsString>, Option<StartCommand>)> {
+    let cli = Args::command();
+    let mut rest = Vec::new();
+    let mut command = None;
+    let mut past_separator = false;
+    let mut value_expected = false;
+
+    for arg in args {
+        let is_value = std::mem::take(&mut value_expected);
+        match arg.to_str() {
+            _ if is_value || past_separator => {}
+            Some("--") => past_separator = true,
+            Some(text) if !rest.is_empty() && text.starts_with('+') => {
+                command = Some(StartCommand::parse(text)?);
+                continue;
+            }
+            Some(text) => value_expected = option_takes_next_arg(&cli, text),
+            None => {}
+        }
+        rest.push(arg);
+    }
+
+    Ok((rest, command))
+}
+
+/// Whether the option `text` leaves its value to the next argument, as
+/// `--theme NAME` or `-n 5` do and `--theme=NAME`, `-n5` or `--split` don't.
+fn option_takes_next_arg(cli: &clap::Command, text: &str) -> bool {
+    let takes_value = |arg: &clap::Arg| arg.get_action().takes_values();
+    if let Some(long) = text.strip_prefix("--") {
+        return !long.contains('=')
+            && cli
+                .get_arguments()
+                .find(|arg| {
+                    arg.get_long() == Some(long)
+                        || arg
+                            .get_all_aliases()
+                            .is_some_and(|aliases| aliases.contains(&long))
+                })
+                .is_some_and(takes_value);
+    }
+    let Some(shorts) = text.strip_prefix('-') else {
+        return false;
+    };
+    // In a cluster like `-sn`, the first option taking a value swallows the
+    // rest of the cluster, or the next argument when it ends the cluster
+    let mut chars = shorts.chars();
+    while let Some(short) = chars.next() {
+        let Some(arg) = cli
+            .get_arguments()
+            .find(|arg| arg.get_short() == Some(short))
+        else {
+            return false;
+        };
+        if takes_value(arg) {
+            return chars.as_str().is_empty();
+        }
+    }
+    false
+}
+
+/// Number of content rows shown in a pane with `rows` rows inside its border.
+/// Without `--lines` the pane is filled. With it the viewport is exactly that
+/// tall, except on a terminal too short to fit it, where it fills the pane.
+fn visible_height(rows: u16, fixed_height: Option<usize>) -> usize {
+    let available = rows as usize;
+    match fixed_height {
+        Some(lines) => lines.min(available),
+        None => available,
+    }
+}
+
+/// Scroll that brings `target` into view: centred, or with `scrolloff`
+/// lines of context above it when set. The margin shrinks when the viewport
+/// is too short to honour it both ways.
+fn scroll_to_target(target: usize, visible: usize, scrolloff: Option<usize>) -> usize {
+    match scrolloff {
+        Some(scrolloff) => {
+            let margin = scrolloff.min(visible.saturating_sub(1) / 2);
+            target.saturating_sub(margin)
+        }
+        None => center_on(target, visible),
+    }
+}
+
+/// Scroll that puts `target` on the middle row of a `visible`-row view.
+/// Near the top of the file it stays at 0; callers clamp the bottom.
+fn center_on(target: usize, visible: usize) -> usize {
+    target.saturating_sub(visible / 2)
+}
+
+fn is_blank(line: &str) -> bool {
+    line.trim().is_empty()
+}
+
+/// First blank line after the paragraph below `from`, like Vim's `}`. A run
+/// of blank lines `from` is on counts as one boundary, so repeated presses
+/// keep moving.
+fn paragraph_below(lines: &[String], from: usize) -> Option<usize> {
+    lines
+        .iter()
+        .enumerate()
+        .skip(from)
+        .skip_while(|(_, line)| is_blank(line))
+        .find(|(_, line)| is_blank(line))
+        .map(|(index, _)| index)
+}
+
+/// Last blank line before the paragraph above `from`, like Vim's `{`.
+fn paragraph_above(lines: &[String], from: usize) -> Option<usize> {
+    lines[..(from + 1).min(lines.len())]
+        .iter()
+        .enumerate()
+        .rev()
+        .skip_while(|(_, line)| is_blank(line))
+        .find(|(_, line)| is_blank(line))
+        .map(|(index, _)| index)
+}
+
+/// Blends `color` into the background so it can tint a row behind text.
+fn tint(color: Color) -> Color {
+    match (color, theme().background) {
+        (Color::Rgb(r, g, b), Color::Rgb(br, bg, bb)) => Color::Rgb(
+            ((r as u16 + 3 * br as u16) / 4) as u8,
+            ((g as u16 + 3 * bg as u16) / 4) as u8,
+            ((b as u16 + 3 * bb as u16) / 4) as u8,
+        ),
+        _ => color,
+    }
+}
+
+/// How a row of a side-by-side diff relates to the other side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DiffKind {
+    Equal,
+    Added,
+    Removed,
+    Changed,
+    /// Padding that keeps both sides aligned
+    Filler,
+}
+
+impl DiffKind {
+    fn background(self) -> Option<Color> {
+        match self {
+            DiffKind::Equal => None,
+            DiffKind::Added => Some(tint(theme().string)),
+            DiffKind::Removed => Some(tint(theme().error)),
+            DiffKind::Changed => Some(tint(theme().warning)),
+            DiffKind::Filler => Some(theme().selection),
+        }
+    }
+}
+
+/// One side of an aligned diff: its rows and how each one changed.
+type DiffSide = (Vec<String>, Vec<DiffKind>);
+
+/// Line-diffs `old` against `new` and pads both with filler rows so that
+/// unchanged lines land on the same row on either side.
+fn align_diff(old: &[String], new: &[String]) -> (DiffSide, DiffSide) {
+    let mut left: DiffSide = (Vec::new(), Vec::new());
+    let mut right: DiffSide = (Vec::new(), Vec::new());
+
+    for op in similar::capture_diff_slices(similar::Algorithm::Myers, old, new) {
+        let (tag, old_range, new_range) = op.as_tag_tuple();
+        let (old_kind, new_kind) = match tag {
+            similar::DiffTag::Equal => (DiffKind::Equal, DiffKind::Equal),
+            similar::DiffTag::Delete => (DiffKind::Removed, DiffKind::Filler),
+            similar::DiffTag::Insert => (DiffKind::Filler, DiffKind::Added),
+            similar::DiffTag::Replace => (DiffKind::Changed, DiffKind::Changed),
+        };
+
+        let rows = old_range.len().max(new_range.len());
+        for row in 0..rows {
+            for (side, lines, range, kind) in [
+                (&mut left, old, &old_range, old_kind),
+                (&mut right, new, &new_range, new_kind),
+            ] {
+                match lines.get(range.start + row).filter(|_| row < range.len()) {
+                    Some(line) => {
+                        side.0.push(line.clone());
+                        side.1.push(kind);
+                    }
+                    None => {
+                        side.0.push(String::new());
+                        side.1.push(DiffKind::Filler);
+                    }
+                }
+            }
+        }
+    }
+
+    (left, right)
+}
+
+/// Tells buffers apart for the loaders filling them. Every buffer gets a
+/// new one, so a reloaded file never takes lines meant for the old copy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BufferId(usize);
+
+impl BufferId {
+    fn next() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// A file loaded into memory, shared by every pane showing it.
+struct Buffer {
+    id: BufferId,
+    name: PathBuf,
+    lines: Vec<String>,
+    language: Language,
+    encoding: &'static Encoding,
+    endings: LineEndings,
+    stats: OnceCell<FileStats>,
+    /// Metadata for `--info`; `None` inside for stdin or a vanished file
+    info: OnceCell<Option<FileInfo>>,
+    /// Per-row change markers when this buffer is one side of a diff
+    diff: Option<Vec<DiffKind>>,
+    markdown: OnceCell<Vec<MarkdownBlock>>,
+    /// Percent read so far while a background load is still running
+    progress: Option<u8>,
+    /// Lines the file should have in all, going by those read so far;
+    /// the scrollbar's length until the load finishes
+    estimated_lines: Option<usize>,
+    /// Read from a named pipe, which has no size to measure progress by
+    pipe: bool,
+    /// Colored program output, shown with its own colors instead of
+    /// highlighting
+    ansi: bool,
+    /// `--accurate` highlighting so far; `None` inside when no grammar fits
+    #[cfg(feature = "syntect")]
+    grammar: OnceCell<Option<RefCell<GrammarCache>>>,
+    /// `--tree-sitter` highlighting of the whole file, with the palette it
+    /// was colored in; `None` until first drawn or after the text changes
+    #[cfg(feature = "tree-sitter")]
+    tree: RefCell<Option<(Theme, Vec<Line<'static>>)>>,
+}
+
+/// Lines a grammar has highlighted so far. Each depends on every line
+/// before it, so they are worked out in order and kept.
+#[cfg(feature = "syntect")]
+struct GrammarCache {
+    highlighter: GrammarHighlighter,
+    lines: Vec<Line<'static>>,
+    /// Palette the lines were colored with; `:theme` invalidates them
+    theme: Theme,
+}
+
+impl Buffer {
+    fn new(name: PathBuf, lines: Vec<String>) -> Self {
+        let mut buffer = Self {
+            id: BufferId::next(),
+            language: detect_language(&name),
+            encoding: UTF_8,
+            endings: LineEndings::default(),
+            name,
+            lines,
+            stats: OnceCell::new(),
+            info: OnceCell::new(),
+            diff: None,
+            markdown: OnceCell::new(),
+            progress: None,
+            estimated_lines: None,
+            pipe: false,
+            ansi: false,
+            #[cfg(feature = "syntect")]
+            grammar: OnceCell::new(),
+            #[cfg(feature = "tree-sitter")]
+            tree: RefCell::new(None),
+        };
+        buffer.detect_content_language();
+        buffer
+    }
+
+    /// Falls back to the first line (a `#!` line or the start of a diff)
+    /// when the file name gave no language, and checks for ANSI colors.
+    fn detect_content_language(&mut self) {
+        self.ansi = has_ansi_colors(&self.lines);
+        if self.language == Language::Plain
+            && let Some(language) = self.lines.first().and_then(|line| content_language(line))
+        {
+            self.language = language;
+        }
+    }
+
+    fn with_diff(name: PathBuf, (lines, diff): DiffSide) -> Self {
+        Self {
+            diff: Some(diff),
+            ..Self::new(name, lines)
+        }
+    }
+
+    fn markdown_blocks(&self) -> &[MarkdownBlock] {
+        self.markdown.get_or_init(|| markdown_blocks(&self.lines))
+    }
+
+    fn info(&self) -> Option<&FileInfo> {
+        self.info
+            .get_or_init(|| FileInfo::read(&self.name))
+            .as_ref()
+    }
+
+    fn stats(&self) -> &FileStats {
+        self.stats.get_or_init(|| FileStats {
+            encoding: self.encoding,
+            endings: self.endings,
+            ..FileStats::compute(&self.lines, &self.name, self.language)
+        })
+    }
+
+    /// Line `index` as displayed: syntax or Markdown highlighting plus
+    /// whichever decorations `options` turn on.
+    fn styled_line(
+        &self,
+        index: usize,
+        options: &ViewOptions,
+        reference: Option<&str>,
+    ) -> Line<'_> {
+        let s = &self.lines[index];
+        if !options.highlight {
+            return Line::from(s.as_str());
+        }
+        if self.shows_ansi(options) {
+            return render_ansi(s, theme());
+        }
+        // Tokenizing minified code or one-line JSON is slow enough to stall
+        // scrolling; show it in the comment color to flag it as unhighlighted
+        if s.len() > options.max_highlight_len {
+            return Line::styled(s.as_str(), Style::default().fg(theme().comment));
+        }
+        let block = if options.markdown || self.language == Language::Markdown {
+            self.markdown_blocks().get(index)
+        } else {
+            None
+        };
+        let mut language = self.language;
+        match block {
+            Some(MarkdownBlock::Prose) => return highlight_markdown_line(s),
+            Some(MarkdownBlock::Fence) => {
+                return Line::styled(s.as_str(), Style::default().fg(theme().comment));
+            }
+            Some(MarkdownBlock::Code(Language::Plain)) => return Line::from(s.as_str()),
+            Some(MarkdownBlock::Code(code)) => language = *code,
+            None => {}
+        }
+        let grammar_line = block.is_none().then(|| {
+            self.tree_line(index, options)
+                .or_else(|| self.grammar_line(index, options))
+        });
+        let mut line = if let Some(Some(line)) = grammar_line {
+            line
+        } else if options.indent_guides {
+            highlight_with_guides(s, language, options.tab_width, &options.comment_prefixes)
+        } else {
+            highlight_code(s, language, &options.comment_prefixes)
+        };
+        if options.rainbow {
+            line = rainbow_brackets(line, theme());
+        }
+        match reference {
+            Some(word) => highlight_references(line, word),
+            None => line,
+        }
+    }
+
+    /// Whether lines render their own ANSI colors rather than highlighting.
+    fn shows_ansi(&self, options: &ViewOptions) -> bool {
+        self.ansi && options.auto_ansi
+    }
+
+    /// A file `--follow` can watch for appended lines: not stdin, a pipe
+    /// or one side of a diff.
+    fn follows(&self) -> bool {
+        !self.pipe && self.diff.is_none() && self.name.as_os_str() != STDIN_ARG
+    }
+
+    /// The first `len` bytes of the file as stored, and their encoding.
+    /// Stdin and pipes can't be read twice, so their lines are put back
+    /// together as UTF-8 with the file's usual line ending instead.
+    fn raw_start(&self, len: usize) -> (Vec<u8>, &'static Encoding) {
+        let mut bytes = Vec::new();
+        if !self.pipe && self.name.as_os_str() != STDIN_ARG {
+            let read = File::open(&self.name)
+                .and_then(|file| file.take(len as u64).read_to_end(&mut bytes));
+            if read.is_ok() {
+                return (bytes, self.encoding);
+            }
+            bytes.clear();
+        }
+        let ending = match self.endings.crlf > self.endings.lf {
+            true => "\r\n",
+            false => "\n",
+        };
+        for line in &self.lines {
+            if bytes.len() >= len {
+                break;
+            }
+            bytes.extend_from_slice(line.as_bytes());
+            bytes.extend_from_slice(ending.as_bytes());
+        }
+        bytes.truncate(len);
+        (bytes, UTF_8)
+    }
+
+    fn reset_grammar(&mut self) {
+        #[cfg(feature = "syntect")]
+        {
+            self.grammar = OnceCell::new();
+        }
+        #[cfg(feature = "tree-sitter")]
+        {
+            self.tree = RefCell::new(None);
+        }
+    }
+
+    /// Drops grammar highlighting that has reached line `index`, which is
+    /// about to change. Lines before it all feed into it, so it starts over.
+    fn forget_grammar_from(&mut self, index: usize) {
+        #[cfg(feature = "syntect")]
+        if let Some(Some(cache)) = self.grammar.get()
+            && cache.borrow().lines.len() > index
+        {
+            self.reset_grammar();
+        }
+        #[cfg(feature = "tree-sitter")]
+        {
+            self.tree = RefCell::new(None);
+        }
+        #[cfg(not(feature = "syntect"))]
+        let _ = index;
+    }
+
+    /// Line `index` highlighted by the file's grammar under `--accurate`,
+    /// after every line before it.
+    #[cfg(feature = "syntect")]
+    fn grammar_line(&self, index: usize, options: &ViewOptions) -> Option<Line<'static>> {
+        if !options.accurate {
+            return None;
+        }
+        let start = || {
+            let first_line = self.lines.first().map_or("", String::as_str);
+            GrammarHighlighter::for_file(&self.name, first_line, theme())
+        };
+        let cache = self.grammar.get_or_init(|| {
+            start().map(|highlighter| {
+                RefCell::new(GrammarCache {
+                    highlighter,
+                    lines: Vec::new(),
+                    theme: *theme(),
+                })
+            })
+        });
+        let mut cache = cache.as_ref()?.borrow_mut();
+        if cache.theme != *theme() {
+            cache.highlighter = start()?;
+            cache.lines.clear();
+            cache.theme = *theme();
+        }
+        while cache.lines.len() <= index {
+            let next = &self.lines[cache.lines.len()];
+            let line = cache.highlighter.highlight_next(next);
+            cache.lines.push(line);
+        }
+        Some(cache.lines[index].clone())
+    }
+
+    #[cfg(not(feature = "syntect"))]
+    fn grammar_line(&self, _index: usize, _options: &ViewOptions) -> Option<Line<'static>> {
+        None
+    }
+
+    /// Line `index` from a syntax tree of the whole file under
+    /// `--tree-sitter`, or `None` for languages without a bundled grammar.
+    /// The file is parsed again whenever its lines or the theme change, so
+    /// a file still loading keeps its usual highlighting until it is done.
+    #[cfg(feature = "tree-sitter")]
+    fn tree_line(&self, index: usize, options: &ViewOptions) -> Option<Line<'static>> {
+        if !options.tree_sitter || self.progress.is_some() {
+            return None;
+        }
+        let mut tree = self.tree.borrow_mut();
+        let stale = tree
+            .as_ref()
+            .is_none_or(|(colors, lines)| colors != theme() || lines.len() != self.lines.len());
+        if stale {
+            let highlighter = TreeHighlighter::for_language(self.language, theme())?;
+            *tree = Some((*theme(), highlighter.highlight(&self.lines)));
+        }
+        tree.as_ref()?.1.get(index).cloned()
+    }
+
+    #[cfg(not(feature = "tree-sitter"))]
+    fn tree_line(&self, _index: usize, _options: &ViewOptions) -> Option<Line<'static>> {
+        None
+    }
+
+    /// Writes the whole buffer, highlighted as on screen, to `path` as a
+    /// standalone HTML page.
+    fn export_html(&self, path: &Path, options: &ViewOptions) -> Result<()> {
+        let lines: Vec<Line<'_>> = (0..self.lines.len())
+            .map(|index| self.styled_line(index, options, None))
+            .collect();
+        let title = self.name.display().to_string();
+        // Relative numbers mean nothing off screen, so they export plain
+        let numbered = options.line_numbers || options.relative_numbers;
+        std::fs::write(path, to_html(&title, &lines, theme(), numbered))
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Folds in an update from the background loader, dropping anything
+    /// derived from the old contents.
+    fn apply_loaded(&mut self, loaded: Loaded) {
+        match loaded {
+            Loaded::Lines(lines, endings, progress, estimate) => {
+                self.lines.extend(lines);
+                self.endings.add(endings);
+                self.progress = Some(progress);
+                self.estimated_lines = Some(estimate);
+            }
+            Loaded::Decoded(lines, encoding, endings) => {
+                self.lines = lines;
+                self.encoding = encoding;
+                self.endings = endings;
+                self.reset_grammar();
+            }
+            Loaded::Appended(lines, endings) => {
+                // The new text carries on the last line: empty after a final
+                // newline, or whatever part of a line the writer had got to
+                let tail = self.lines.pop().unwrap_or_default();
+                self.forget_grammar_from(self.lines.len());
+                let mut lines = lines.into_iter();
+                self.lines.push(tail + &lines.next().unwrap_or_default());
+                self.lines.extend(lines);
+                self.lines.push(String::new());
+                self.endings.add(endings);
+            }
+            Loaded::Done | Loaded::Failed(_) => {
+                self.progress = None;
+                self.estimated_lines = None;
+                // The grammar may have been picked before the first line
+                // (and any shebang) arrived
+                self.reset_grammar();
+            }
+        }
+        self.detect_content_language();
+        self.stats = OnceCell::new();
+        self.info = OnceCell::new();
+        self.markdown = OnceCell::new();
+    }
+}
+
+/// Spinner glyph for the current moment, advancing every 100ms.
+fn spinner_frame() -> char {
+    let ticks = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() / 100);
+    SPINNER[ticks as usize % SPINNER.len()]
+}
+
+/// Bytes read per message from a background loader.
+const LOAD_CHUNK_SIZE: usize = 64 * 1024;
+
+/// How often the screen refreshes while a file is still loading.
+const LOAD_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often `--follow` looks for lines appended to the files shown.
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(250);
+
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// Progress sent from a loader thread to the UI.
+enum Loaded {
+    /// More lines, how they ended, the percentage of the file read so far
+    /// and the line count the whole file is heading for
+    Lines(Vec<String>, LineEndings, u8, usize),
+    /// The file wasn't UTF-8: all of it, decoded as the detected encoding
+    Decoded(Vec<String>, &'static Encoding, LineEndings),
+    /// Lines written to the end of a followed file, and how they ended
+    Appended(Vec<String>, LineEndings),
+    Failed(String),
+    Done,
+}
+
+/// A running background load into the buffer `buffer`.
+struct Loader {
+    buffer: BufferId,
+    receiver: Receiver<Loaded>,
+}
+
+/// Oldest entries are dropped once the jump list grows past this.
+const MAX_JUMPS: usize = 100;
+
+/// A line-wise `V` selection: the line it started on and the line `j`/`k`
+/// have moved to, in either order.
+#[derive(Debug, Clone, Copy)]
+struct Visual {
+    anchor: usize,
+    cursor: usize,
+}
+
+impl Visual {
+    /// Buffer lines selected, first to last whichever way the cursor went.
+    fn lines(self) -> RangeInclusive<usize> {
+        self.anchor.min(self.cursor)..=self.anchor.max(self.cursor)
+    }
+}
+
+/// Standard base64, for handing text to the terminal's clipboard.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, &byte)| {
+            group | u32::from(byte) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Puts `text` on the system clipboard with an OSC 52 escape, which
+/// terminals honour even over SSH.
+fn copy_to_clipboard(text: &str) -> std::io::Result<()> {
+    let mut out = stdout();
+    write!(out, "\x1b]52;c;{}\x07", base64(text.as_bytes()))?;
+    out.flush()
+}
+
+/// Vim-style jump history walked with Ctrl-o / Ctrl-i. Only "big" moves
+/// (search matches, gg/G, results) are recorded, never single-line scrolls.
+#[derive(Debug, Default)]
+struct JumpList {
+    entries: Vec<usize>,
+    /// Position in `entries`; equal to `entries.len()` when not walking back
+    index: usize,
+}
+
+impl JumpList {
+    /// Remembers `from` before a jump, discarding any forward history.
+    fn record(&mut self, from: usize) {
+        self.entries.truncate(self.index);
+        if self.entries.last() != Some(&from) {
+            self.entries.push(from);
+        }
+        if self.entries.len() > MAX_JUMPS {
+            self.entries.remove(0);
+        }
+        self.index = self.entries.len();
+    }
+
+    fn back(&mut self, current: usize) -> Option<usize> {
+        if self.index == 0 {
+            return None;
+        }
+        // Leaving the newest position: keep it so Ctrl-i can return here
+        if self.index == self.entries.len() {
+            self.entries.push(current);
+        }
+        self.index -= 1;
+        Some(self.entries[self.index])
+    }
+
+    fn forward(&mut self) -> Option<usize> {
+        if self.index + 1 >= self.entries.len() {
+            return None;
+        }
+        self.index += 1;
+        Some(self.entries[self.index])
+    }
+}
+
+/// Lines left visible by an `&pattern` filter, `--squeeze` or both.
+#[derive(Debug)]
+struct Filter {
+    /// Empty when only squeezing blank lines
+    pattern: String,
+    /// Each run of blank lines shows as one
+    squeeze: bool,
+    /// Buffer indices of the matching lines, in file order
+    lines: Vec<usize>,
+}
+
+impl Filter {
+    fn new(pattern: String, squeeze: bool, file_lines: &[String]) -> Self {
+        let mut lines = if pattern.is_empty() {
+            (0..file_lines.len()).collect()
+        } else {
+            Search::all_matches(pattern.clone(), file_lines, SearchMode::default())
+        };
+        if squeeze {
+            let blank = |index: usize| file_lines[index].trim().is_empty();
+            lines.retain(|&index| !(index > 0 && blank(index) && blank(index - 1)));
+        }
+        Self {
+            pattern,
+            squeeze,
+            lines,
+        }
+    }
+
+    /// Whether an `&pattern` hides lines, rather than only squeezing.
+    fn has_pattern(&self) -> bool {
+        !self.pattern.is_empty()
+    }
+
+    /// Row showing buffer `line`, or the first matching line after it.
+    fn row_of(&self, line: usize) -> usize {
+        self.lines.partition_point(|&shown| shown < line)
+    }
+}
+
+/// An independent viewport onto a buffer.
+struct Pane {
+    buffer: Rc<Buffer>,
+    scroll: usize,
+    /// Content rows shown at the last layout; paging moves by this much.
+    visible: usize,
+    search: Option<Search>,
+    /// Query of a search dismissed with Esc; `n`/`N` bring it back
+    last_query: Option<String>,
+    /// Lines searches are confined to, set with `:range`
+    search_range: Option<RangeInclusive<usize>>,
+    minimap: Option<Minimap>,
+    /// Line and URL index last opened with `o`, so repeated presses cycle
+    last_url: Option<(usize, usize)>,
+    jumps: JumpList,
+    /// In-flight `--smooth` transition; `scroll` is always the destination
+    animation: Option<ScrollAnimation>,
+    /// When set, `scroll` counts rows of shown lines rather than buffer lines
+    filter: Option<Filter>,
+    /// Display columns scrolled off the left edge
+    h_scroll: usize,
+    /// Text columns shown at the last draw, after any line number gutter
+    text_width: usize,
+    /// Line 0 is pinned in a row of its own, outside the scrolling rows
+    header: bool,
+    /// Long lines wrap at `text_width`, so a line can take several rows
+    wrap: bool,
+    /// Lines marked with `m{letter}`, by letter
+    marks: HashMap<char, usize>,
+    /// Line-wise selection while in `V` mode
+    visual: Option<Visual>,
+}
+
+impl Pane {
+    fn new(buffer: Rc<Buffer>) -> Self {
+        Self {
+            buffer,
+            scroll: 0,
+            visible: 0,
+            search: None,
+            last_query: None,
+            search_range: None,
+            minimap: None,
+            last_url: None,
+            jumps: JumpList::default(),
+            animation: None,
+            filter: None,
+            h_scroll: 0,
+            text_width: 0,
+            header: false,
+            wrap: false,
+            marks: HashMap::new(),
+            visual: None,
+        }
+    }
+
+    /// A second view of the same buffer starting at the same place.
+    fn split(&self) -> Self {
+        Self {
+            scroll: self.scroll,
+            visible: self.visible,
+            h_scroll: self.h_scroll,
+            header: self.header,
+            wrap: self.wrap,
+            ..Self::new(Rc::clone(&self.buffer))
+        }
+    }
+
+    /// Rows there are to scroll through: every line, or only filter matches.
+    fn total_lines(&self) -> usize {
+        let total = match &self.filter {
+            Some(filter) => filter.lines.len(),
+            None => self.buffer.lines.len(),
+        };
+        total - self.header_rows()
+    }
+
+    /// Buffer line shown at view `row`.
+    fn view_line(&self, row: usize) -> Option<usize> {
+        let row = row + self.header_rows();
+        match &self.filter {
+            Some(filter) => filter.lines.get(row).copied(),
+            None => (row < self.buffer.lines.len()).then_some(row),
+        }
+    }
+
+    /// View row showing buffer `line`, or the first shown line after it.
+    fn row_of(&self, line: usize) -> usize {
+        let row = self
+            .filter
+            .as_ref()
+            .map_or(line, |filter| filter.row_of(line));
+        row.saturating_sub(self.header_rows())
+    }
+
+    /// Whether line 0 is pinned above the scrolling rows.
+    fn shows_header(&self) -> bool {
+        self.header && !self.buffer.lines.is_empty()
+    }
+
+    /// Leading rows taken out of the scrolling view by a frozen header: 1
+    /// when line 0 would otherwise be among them, so it isn't shown twice.
+    fn header_rows(&self) -> usize {
+        let pinned = match &self.filter {
+            Some(filter) => filter.lines.first() == Some(&0),
+            None => true,
+        };
+        usize::from(self.shows_header() && pinned)
+    }
+
+    /// Pins or unpins line 0, keeping the same line at the top.
+    fn set_header(&mut self, header: bool) {
+        if self.header != header {
+            let top = self.top_line();
+            self.header = header;
+            self.scroll = self.row_of(top);
+            self.clamp();
+        }
+    }
+
+    /// Buffer line at the top of the view.
+    fn top_line(&self) -> usize {
+        self.view_line(self.scroll).unwrap_or(self.scroll)
+    }
+
+    /// Shows only lines matching `pattern`, or every line again when it is
+    /// empty. The line at the top stays (or lands nearest) in place.
+    fn set_filter(&mut self, pattern: String) {
+        let squeeze = self.squeezes();
+        self.update_filter(pattern, squeeze);
+    }
+
+    fn squeezes(&self) -> bool {
+        self.filter.as_ref().is_some_and(|filter| filter.squeeze)
+    }
+
+    /// Turns `--squeeze` on or off, keeping any `&pattern`.
+    fn set_squeeze(&mut self, squeeze: bool) {
+        if self.squeezes() != squeeze {
+            let pattern = self.filter_pattern();
+            self.update_filter(pattern, squeeze);
+        }
+    }
+
+    fn filter_pattern(&self) -> String {
+        self.filter
+            .as_ref()
+            .map(|filter| filter.pattern.clone())
+            .unwrap_or_default()
+    }
+
+    fn update_filter(&mut self, pattern: String, squeeze: bool) {
+        let top = self.top_line();
+        self.filter = (!pattern.is_empty() || squeeze)
+            .then(|| Filter::new(pattern, squeeze, &self.buffer.lines));
+        self.scroll = self.row_of(top);
+        self.clamp();
+    }
+
+    /// Wraps long lines, or goes back to cutting them off. Scrolling
+    /// sideways has nothing to show while they wrap.
+    fn set_wrap(&mut self, wrap: bool) {
+        if self.wrap != wrap {
+            self.wrap = wrap;
+            self.h_scroll = 0;
+            self.clamp();
+        }
+    }
+
+    /// Screen rows view `row` takes: one, or as many as its line wraps
+    /// onto at the last drawn width. Rows past the end count as one.
+    fn line_height(&self, row: usize) -> usize {
+        match self.view_line(row) {
+            Some(line) if self.wrap && self.text_width > 0 => {
+                display_width(&self.buffer.lines[line])
+                    .div_ceil(self.text_width)
+                    .max(1)
+            }
+            _ => 1,
+        }
+    }
+
+    /// How many of the view `rows`, taken in turn, fit together in `height`
+    /// screen rows. A wrapped line taller than that still counts as one.
+    fn lines_fitting(&self, rows: impl Iterator<Item = usize>, height: usize) -> usize {
+        let mut used = 0;
+        let fitting = rows
+            .take_while(|&row| {
+                used += self.line_height(row);
+                used <= height
+            })
+            .count();
+        fitting.max(usize::from(height > 0 && used > 0))
+    }
+
+    /// View rows on screen when `from` is at the top: `visible`, or fewer
+    /// when wrapped lines take more than a screen row each.
+    fn lines_in_view(&self, from: usize) -> usize {
+        self.lines_fitting(from.., self.visible)
+    }
+
+    fn max_scroll(&self) -> usize {
+        let total_lines = self.total_lines();
+        total_lines.saturating_sub(self.lines_fitting((0..total_lines).rev(), self.visible))
+    }
+
+    /// Scroll that brings view `row` on screen as `scroll_to_target` does,
+    /// moved down if wrapped lines above it would push it off the bottom.
+    fn scroll_for(&self, row: usize, scrolloff: Option<usize>) -> usize {
+        let above = self.lines_fitting((0..=row).rev(), self.visible);
+        scroll_to_target(row, self.visible, scrolloff).max(row + 1 - above.max(1))
+    }
+
+    fn line_down(&mut self, count: usize) {
+        self.scroll = self.scroll.saturating_add(count).min(self.max_scroll());
+    }
+
+    /// Scrolls a screenful down, keeping the bottom `overlap` screen rows of
+    /// the old one, and stopping where the last line reaches the bottom.
+    fn page_down(&mut self, overlap: usize) {
+        let page = self.visible.saturating_sub(overlap).max(1);
+        self.line_down(self.lines_fitting(self.scroll.., page));
+    }
+
+    /// Scrolls a screenful up, keeping the top `overlap` screen rows of the
+    /// old one.
+    fn page_up(&mut self, overlap: usize) {
+        let page = self.visible.saturating_sub(overlap).max(1);
+        self.scroll -= self.lines_fitting((0..self.scroll).rev(), page);
+    }
+
+    /// Rows the scrollbar spans: while the file is still loading, as many as
+    /// it is expected to end up with, so the thumb doesn't race the loader.
+    fn scrollbar_rows(&self) -> usize {
+        match (&self.filter, self.buffer.estimated_lines) {
+            (None, Some(estimate)) => estimate
+                .saturating_sub(self.header_rows())
+                .max(self.total_lines()),
+            _ => self.total_lines(),
+        }
+    }
+
+    /// Fractional scroll currently on screen, part-way through any animation.
+    fn shown_scroll(&self, now: Instant) -> f64 {
+        self.animation
+            .and_then(|animation| animation.position(self.scroll, now))
+            .unwrap_or(self.scroll as f64)
+    }
+
+    fn is_animating(&self, now: Instant) -> bool {
+        self.animation
+            .is_some_and(|animation| animation.position(self.scroll, now).is_some())
+    }
+
+    /// Brings `line` into view, centred unless a scrolloff margin is set.
+    /// Counts as a jump, so the previous position goes onto the jump list.
+    fn jump_to(&mut self, line: usize, scrolloff: Option<usize>) {
+        self.jumps.record(self.top_line());
+        self.scroll = self.scroll_for(self.row_of(line), scrolloff);
+        self.clamp();
+    }
+
+    /// Scrolls the next (or previous) paragraph boundary to the top of the
+    /// view, stopping at the first or last line when there are no more.
+    fn paragraph_jump(&mut self, forward: bool, count: usize) {
+        let lines = &self.buffer.lines;
+        let mut line = self.top_line();
+        for _ in 0..count {
+            let next = if forward {
+                paragraph_below(lines, line).unwrap_or(lines.len().saturating_sub(1))
+            } else {
+                paragraph_above(lines, line).unwrap_or(0)
+            };
+            // Past the last boundary a huge count has nothing left to find
+            if next == line {
+                break;
+            }
+            line = next;
+        }
+        self.jumps.record(self.top_line());
+        self.scroll = self.row_of(line);
+        self.clamp();
+    }
+
+    /// Widest line on screen, the furthest `l` scrolls sideways. Wrapped
+    /// lines never run past the edge, so there is nowhere to go.
+    fn shown_width(&self) -> usize {
+        if self.wrap {
+            return 0;
+        }
+        (self.scroll..self.scroll + self.visible)
+            .map_while(|row| self.view_line(row))
+            .map(|line| display_width(&self.buffer.lines[line]))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Scrolls sideways just far enough to show the end of the widest line
+    /// on screen, or back to the first column if everything already fits.
+    fn scroll_to_line_end(&mut self) {
+        self.h_scroll = self.shown_width().saturating_sub(self.text_width);
+    }
+
+    /// A file of one or a few very long lines (minified code, a JSON blob)
+    /// has nothing to scroll vertically, so paging goes sideways instead.
+    fn pages_sideways(&self) -> bool {
+        self.text_width > 0
+            && self.total_lines() <= self.visible
+            && self.shown_width() > self.text_width
+    }
+
+    /// Scrolls `pages` screen widths right (or left), stopping where the end
+    /// of the widest line is in view.
+    fn page_sideways(&mut self, forward: bool, pages: usize) {
+        let distance = self.text_width.saturating_mul(pages);
+        self.h_scroll = if forward {
+            let end = self.shown_width().saturating_sub(self.text_width);
+            (self.h_scroll + distance).min(end.max(self.h_scroll))
+        } else {
+            self.h_scroll.saturating_sub(distance)
+        };
+    }
+
+    /// Scrolls sideways to the next (or previous) word start on the top
+    /// line, going no further right than the end of that line.
+    fn word_scroll(&mut self, forward: bool, count: usize) {
+        let Some(line) = self
+            .buffer
+            .lines
+            .get(self.top_line())
+            .filter(|_| !self.wrap)
+        else {
+            return;
+        };
+        let width = display_width(line);
+        let words = word_columns(line);
+        for _ in 0..count {
+            let column = if forward {
+                let next = words.iter().find(|&&column| column > self.h_scroll);
+                next.copied().unwrap_or(width.max(self.h_scroll))
+            } else {
+                let previous = words.iter().rev().find(|&&column| column < self.h_scroll);
+                previous.copied().unwrap_or(0)
+            };
+            if column == self.h_scroll {
+                break;
+            }
+            self.h_scroll = column;
+        }
+    }
+
+    /// Moves the `V` cursor `count` shown lines down (or up), scrolling
+    /// just enough to keep it in view with `scrolloff` lines of context
+    /// on either side, or fewer on a screen too short for both.
+    fn move_visual(&mut self, forward: bool, count: usize, scrolloff: Option<usize>) {
+        let Some(mut visual) = self.visual else {
+            return;
+        };
+        let row = self.row_of(visual.cursor);
+        let row = if forward {
+            (row + count).min(self.total_lines().saturating_sub(1))
+        } else {
+            row.saturating_sub(count)
+        };
+        visual.cursor = self.view_line(row).unwrap_or(visual.cursor);
+        self.visual = Some(visual);
+        let margin = scrolloff
+            .unwrap_or(0)
+            .min(self.visible.saturating_sub(1) / 2);
+        let last = self.total_lines().saturating_sub(1);
+        if row < self.scroll + margin {
+            self.scroll = row.saturating_sub(margin);
+        } else if (row + margin).min(last) >= self.scroll + self.lines_in_view(self.scroll) {
+            let bottom = (row + margin).min(last);
+            let above = self.lines_fitting((0..=bottom).rev(), self.visible);
+            self.scroll = bottom + 1 - above.max(1);
+        }
+        self.clamp();
+    }
+
+    /// The selected lines joined back into text, with a final newline.
+    fn selected_text(&self) -> Option<String> {
+        let lines = self.visual?.lines();
+        let selected = self.buffer.lines.get(lines)?;
+        Some(selected.iter().map(|line| format!("{line}\n")).collect())
+    }
+
+    fn jump_to_bottom(&mut self) {
+        self.jumps.record(self.top_line());
+        self.scroll = self.max_scroll();
+    }
+
+    fn clamp(&mut self) {
+        self.scroll = self.scroll.min(self.max_scroll());
+    }
+
+    /// A search for `query`, counting only matches inside the `:range`
+    /// when one is set.
+    fn search_for(&self, query: String, mode: SearchMode) -> Search {
+        let mut search = Search::new(query, mode);
+        search.range = self.search_range.clone();
+        search.start(&self.buffer.lines);
+        search
+    }
+
+    /// Limits searches to `range` (buffer lines), or lifts the limit, and
+    /// reruns the current search to match.
+    fn set_search_range(&mut self, range: Option<RangeInclusive<usize>>) {
+        self.search_range = range;
+        if let Some(search) = self.search.take() {
+            self.search = Some(self.search_for(search.query, search.mode));
+        }
+    }
+
+    /// Where `n`/`N` search from: the match last jumped to while it is
+    /// still on screen, else the top line, so they never land on the line
+    /// they start from.
+    fn search_origin(&self) -> usize {
+        let top = self.top_line();
+        let current = self
+            .search
+            .as_ref()
+            .and_then(|search| search.matches.get(search.current?).copied());
+        match current {
+            Some(line)
+                if (self.scroll..self.scroll + self.lines_in_view(self.scroll))
+                    .contains(&self.row_of(line)) =>
+            {
+                line
+            }
+            _ => top,
+        }
+    }
+
+    /// Reruns a search dismissed with Esc, placed at the top line so `n` and
+    /// `N` carry on from here rather than from the first match.
+    fn restore_search(&mut self, mode: SearchMode) {
+        if self.search.is_some() {
+            return;
+        }
+        if let Some(query) = self.last_query.take() {
+            let mut search = self.search_for(query, mode);
+            let top = self.top_line();
+            search.scan_to(&self.buffer.lines, top + 1);
+            search.current = search
+                .matches
+                .partition_point(|&line| line <= top)
+                .checked_sub(1);
+            self.search = Some(search);
+        }
+    }
+
+    /// Reruns the search and filter over the whole buffer once it has loaded.
+    fn refresh_matches(&mut self) {
+        if let Some(search) = self.search.take() {
+            let mode = search.mode;
+            self.search = Some(self.search_for(search.query, mode));
+        }
+        let squeeze = self.squeezes();
+        if let Some(pattern) = self.filter.as_ref().map(|filter| filter.pattern.clone()) {
+            self.update_filter(pattern, squeeze);
+        }
+    }
+
+    /// Scrolls to where `start` asks. A line past the end of the file lands
+    /// on the last one instead, with a note to say so.
+    fn apply_start(&mut self, start: StartCommand, mode: SearchMode) -> Option<String> {
+        let total_lines = self.buffer.lines.len();
+        let mut note = None;
+        let line = match start {
+            StartCommand::Line(line) if line > total_lines.max(1) => {
+                note = Some(format!(
+                    "Line {} is past the end; showing line {}",
+                    grouped(line),
+                    grouped(total_lines)
+                ));
+                total_lines.saturating_sub(1)
+            }
+            StartCommand::Line(line) => line.saturating_sub(1),
+            StartCommand::Search(query) => {
+                let mut search = self.search_for(query, mode);
+                if search.matches.is_empty() {
+                    note = Some(format!("Pattern not found: {}", search.query));
+                }
+                let line = search.first_from(0, &self.buffer.lines).unwrap_or(0);
+                self.search = Some(search);
+                line
+            }
+            StartCommand::Percent(percent) => {
+                percent_to_line(percent, self.buffer.lines.len()).saturating_sub(1)
+            }
+            StartCommand::ByteOffset(offset) => {
+                let (bytes, encoding) = self.buffer.raw_start(offset);
+                let line = byte_offset_to_line(&bytes, encoding);
+                // Offsets past the end land on the last line
+                line.min(total_lines.max(1)) - 1
+            }
+        };
+        self.scroll = self.row_of(line);
+        self.clamp();
+        note
+    }
+
+    /// Whether a search is active but matches no line.
+    fn finds_nothing(&self) -> bool {
+        self.search
+            .as_ref()
+            .is_some_and(|search| search.matches.is_empty())
+    }
+
+    fn position_label(&self, options: &ViewOptions) -> String {
+        if self.buffer.pipe && self.buffer.progress.is_some() {
+            return format!(
+                "{} Reading pipe… {} lines",
+                spinner_frame(),
+                grouped(self.buffer.lines.len())
+            );
+        }
+        if let Some(progress) = self.buffer.progress {
+            let estimate = self.buffer.estimated_lines.unwrap_or(0);
+            return format!(
+                "{} Loading… {} of ~{} lines ({progress}%)",
+                spinner_frame(),
+                grouped(self.buffer.lines.len()),
+                grouped(estimate.max(self.buffer.lines.len()))
+            );
+        }
+        let total_lines = self.buffer.lines.len();
+        let position = match &self.filter {
+            Some(filter) if filter.has_pattern() && filter.lines.is_empty() => {
+                format!("[no lines match &{}]", filter.pattern)
+            }
+            _ if total_lines == 0 => "[empty]".to_string(),
+            Some(filter) if !filter.has_pattern() => format!(
+                "Line {} of {} [squeezed: {} lines]",
+                grouped(self.top_line() + 1),
+                grouped(total_lines),
+                grouped(filter.lines.len())
+            ),
+            Some(filter) => format!(
+                "Line {} of {} [&{}: {} lines]",
+                grouped(self.top_line() + 1),
+                grouped(total_lines),
+                filter.pattern,
+                grouped(filter.lines.len())
+            ),
+            None => format!(
+                "Line {}-{} of {}",
+                grouped(self.top_line() + 1),
+                grouped((self.top_line() + self.lines_in_view(self.scroll)).min(total_lines)),
+                grouped(total_lines)
+            ),
+        };
+        let position = format!("{position}, col {}", self.h_scroll + 1);
+        let position = match &self.search_range {
+            Some(range) => format!(
+                "{position} [range {}-{}]",
+                range.start() + 1,
+                range.end() + 1
+            ),
+            None => position,
+        };
+        // UTF-8 and LF are the unremarkable defaults; only call out the rest
+        let mut format = Vec::new();
+        if self.buffer.encoding != UTF_8 {
+            format.push(self.buffer.encoding.name());
+        }
+        if self.buffer.endings.is_notable() {
+            format.extend(self.buffer.endings.label());
+        }
+        if options.highlight && self.buffer.shows_ansi(options) {
+            format.push("ANSI");
+        }
+        if options.follow && self.buffer.follows() {
+            format.push(if self.scroll >= self.max_scroll() {
+                "following"
+            } else {
+                "following: G to resume"
+            });
+        }
+        if format.is_empty() {
+            position
+        } else {
+            format!("{position} [{}]", format.join(", "))
+        }
+    }
+
+    /// Short `L{line}/{total} {percent}%` form for narrow terminals.
+    fn compact_position_label(&self) -> String {
+        if self.buffer.pipe && self.buffer.progress.is_some() {
+            return format!("{} {}", spinner_frame(), self.buffer.lines.len());
+        }
+        if let Some(progress) = self.buffer.progress {
+            return format!("{} {progress}%", spinner_frame());
+        }
+        match &self.filter {
+            Some(filter) if filter.has_pattern() && filter.lines.is_empty() => {
+                return "[no match]".to_string();
+            }
+            _ if self.buffer.lines.is_empty() => return "[empty]".to_string(),
+            _ => {}
+        }
+        let total_lines = self.total_lines();
+        let bottom = (self.scroll + self.lines_in_view(self.scroll)).min(total_lines);
+        format!(
+            "L{}/{} {}%",
+            self.top_line() + 1,
+            self.buffer.lines.len(),
+            // Nothing left to scroll under a frozen one-line file
+            (bottom * 100).checked_div(total_lines).unwrap_or(100)
+        )
+    }
+
+    fn draw(
+        &mut self,
+        frame: &mut Frame,
+        area: Rect,
+        focused: bool,
+        options: &ViewOptions,
+        reference: Option<&str>,
+    ) {
+        let border_style = if !options.highlight {
+            Style::default()
+        } else if focused {
+            Style::default().fg(theme().keyword)
+        } else {
+            Style::default()
+        };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(border_style)
+            .title(format!(" {} ", self.buffer.name.display()));
+        let inner = block.inner(area);
+
+        // Content | minimap, both inside the border; the scrollbar sits on the border
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Min(1),
+                Constraint::Length(if options.minimap { MINIMAP_WIDTH } else { 0 }),
+            ])
+            .split(inner);
+        let content = columns[0];
+
+        // Filtered views number their lines so the original positions show
+        let numbered = self.filter.is_some() || options.line_numbers || options.relative_numbers;
+        let gutter_width = match numbered {
+            true => self.buffer.lines.len().to_string().len(),
+            false => 0,
+        };
+        let gutter_cells = if gutter_width > 0 {
+            gutter_width + 1
+        } else {
+            0
+        };
+        let text_width = (content.width as usize).saturating_sub(gutter_cells);
+        self.text_width = text_width;
+        // How far the view scrolls depends on how many rows lines wrap onto
+        if self.wrap {
+            self.clamp();
+        }
+
+        let now = Instant::now();
+        let scroll = self.shown_scroll(now).round() as usize;
+        if !self.is_animating(now) {
+            self.animation = None;
+        }
+
+        // Buffer lines on screen; with a filter these skip non-matching lines.
+        // A wrapped line too long for the rows left shows as much as fits.
+        let shown_rows = self.lines_in_view(scroll) + usize::from(self.wrap);
+        let shown: Vec<usize> = (scroll..scroll + shown_rows)
+            .map_while(|row| self.view_line(row))
+            .collect();
+        // A frozen header goes in the first row, above the scrolled lines
+        let drawn: Vec<usize> = self
+            .shows_header()
+            .then_some(0)
+            .into_iter()
+            .chain(shown.iter().copied())
+            .collect();
+        // Matches are marked on screen, so they must be found that far
+        if let Some(search) = &mut self.search
+            && let Some(&last) = drawn.iter().max()
+        {
+            search.scan_to(&self.buffer.lines, last + 1);
+        }
+        let gutter_style = if options.highlight {
+            Style::default().fg(theme().comment)
+        } else {
+            Style::default()
+        };
+        // Relative numbers count rows below the top line, which keeps its own
+        let top_row = usize::from(self.shows_header());
+        let numbers: Vec<usize> = drawn
+            .iter()
+            .enumerate()
+            .map(
+                |(row, &index)| match options.relative_numbers && row > top_row {
+                    true => row - top_row,
+                    false => index + 1,
+                },
+            )
+            .collect();
+
+        // Column widths come from the rows on screen, so aligned data is
+        // styled a screenful at a time rather than line by line
+        let styled: Vec<Line<'_>> = match options.delimiter {
+            Some(delimiter) => {
+                let lines = drawn.iter().map(|&index| self.buffer.lines[index].as_str());
+                let aligned = align_fields(lines, delimiter);
+                if options.highlight {
+                    aligned
+                } else {
+                    aligned
+                        .into_iter()
+                        .map(|line| Line::from(line.to_string()))
+                        .collect()
+                }
+            }
+            None => drawn
+                .iter()
+                .map(|&index| self.buffer.styled_line(index, options, reference))
+                .collect(),
+        };
+        // Matches stand out in their lines, including while a query is typed
+        let styled: Vec<Line<'_>> = match &self.search {
+            Some(search) => {
+                let found_style = if options.highlight {
+                    Style::default().fg(theme().background).bg(theme().warning)
+                } else {
+                    Style::default().reversed()
+                };
+                styled
+                    .into_iter()
+                    .zip(&drawn)
+                    .map(|(line, index)| {
+                        if search.matches.binary_search(index).is_err() {
+                            return line;
+                        }
+                        let text: String = line
+                            .spans
+                            .iter()
+                            .map(|span| span.content.as_ref())
+                            .collect();
+                        mark_ranges(line, &search.ranges(&text), found_style)
+                    })
+                    .collect()
+            }
+            None => styled,
+        };
+        // Measured as drawn, since alignment and tab guides change the width
+        let widths: Vec<usize> = styled
+            .iter()
+            .map(|line| {
+                line.spans
+                    .iter()
+                    .map(|span| display_width(&span.content))
+                    .sum()
+            })
+            .collect();
+
+        // Each drawn line as the rows it takes: one, or with --wrap as many as
+        // it fills, up to a screenful. A frozen header keeps to its one row.
+        let wrapped: Vec<Vec<Line<'_>>> = styled
+            .into_iter()
+            .enumerate()
+            .map(|(row, line)| {
+                if self.wrap && !(row == 0 && self.shows_header()) {
+                    let mut rows = wrap_columns(line, text_width);
+                    rows.truncate(content.height.into());
+                    rows
+                } else {
+                    vec![skip_columns(line, self.h_scroll)]
+                }
+            })
+            .collect();
+        // Screen row each drawn line starts on, and how many it spans
+        let line_rows: Vec<(u16, u16)> = wrapped
+            .iter()
+            .scan(0, |top, rows| {
+                let height = rows.len() as u16;
+                *top += height;
+                Some((*top - height, height))
+            })
+            .collect();
+        let line_rect = |row: usize| {
+            let (top, height) = line_rows[row];
+            Rect::new(content.x, content.y + top, content.width, height).intersection(content)
+        };
+
+        let mut content_lines: Vec<Line<'_>> = wrapped
+            .into_iter()
+            .zip(&numbers)
+            .flat_map(|(rows, number)| {
+                rows.into_iter().enumerate().map(move |(row, mut line)| {
+                    if gutter_width > 0 {
+                        // Continuation rows leave the number to the first
+                        let number = match row {
+                            0 => format!("{number:>gutter_width$} "),
+                            _ => " ".repeat(gutter_width + 1),
+                        };
+                        line.spans.insert(0, Span::styled(number, gutter_style));
+                    }
+                    line
+                })
+            })
+            .collect();
+        if let Some(filter) = self
+            .filter
+            .as_ref()
+            .filter(|f| f.has_pattern() && f.lines.is_empty())
+        {
+            content_lines.push(Line::styled(
+                format!(
+                    "No lines match &{} (Esc or an empty & shows all)",
+                    filter.pattern
+                ),
+                gutter_style.italic(),
+            ));
+        }
+
+        let text_style = if options.highlight {
+            Style::default().fg(theme().foreground)
+        } else {
+            Style::default()
+        };
+        let paragraph = Paragraph::new(content_lines).style(text_style);
+
+        frame.render_widget(block, area);
+        frame.render_widget(paragraph, content);
+
+        // Mark lines that run past the right edge so hidden content isn't silent
+        let marker_style = if options.highlight {
+            Style::default().fg(theme().number)
+        } else {
+            Style::default()
+        };
+        for (row, &width) in widths.iter().enumerate() {
+            let (top, height) = line_rows[row];
+            if content.width > 0
+                && width > text_width + self.h_scroll
+                && height == 1
+                && top < content.height
+            {
+                let x = content.right() - 1;
+                let y = content.y + top;
+                if let Some(cell) = frame.buffer_mut().cell_mut((x, y)) {
+                    cell.set_symbol(TRUNCATION_MARKER).set_style(marker_style);
+                }
+            }
+        }
+
+        if self.shows_header() {
+            let header = line_rect(0);
+            let header_style = Style::default().bold().underlined();
+            frame.buffer_mut().set_style(header, header_style);
+        }
+
+        // Under the diff tints, so changed lines keep their color in scope
+        if options.scope
+            && options.highlight
+            && let Some((start, end)) =
+                indent_block(&self.buffer.lines, self.top_line(), options.tab_width)
+        {
+            let scope_style = Style::default().bg(tint(theme().special));
+            for (row, &index) in drawn.iter().enumerate() {
+                if (start..=end).contains(&index) {
+                    let row = line_rect(row);
+                    frame.buffer_mut().set_style(row, scope_style);
+                }
+            }
+        }
+
+        if let Some(diff) = self.buffer.diff.as_ref().filter(|_| options.highlight) {
+            for (row, kind) in drawn
+                .iter()
+                .filter_map(|&index| diff.get(index))
+                .enumerate()
+            {
+                if let Some(background) = kind.background() {
+                    let row = line_rect(row);
+                    frame
+                        .buffer_mut()
+                        .set_style(row, Style::default().bg(background));
+                }
+            }
+        }
+
+        // Before the selection tint, which should still read as selected
+        if options.focus
+            && let Some(search) = &self.search
+        {
+            let dim_style = if options.highlight {
+                Style::default()
+                    .fg(theme().comment)
+                    .remove_modifier(Modifier::BOLD)
+            } else {
+                Style::default().dim()
+            };
+            for (row, index) in drawn.iter().enumerate() {
+                if search.matches.binary_search(index).is_err() {
+                    let row = line_rect(row);
+                    frame.buffer_mut().set_style(row, dim_style);
+                }
+            }
+        }
+
+        if let Some(visual) = self.visual {
+            let selected_style = if options.highlight {
+                Style::default().bg(theme().selection)
+            } else {
+                Style::default().reversed()
+            };
+            for (row, index) in drawn.iter().enumerate() {
+                if visual.lines().contains(index) {
+                    let row = line_rect(row);
+                    frame.buffer_mut().set_style(row, selected_style);
+                }
+            }
+        }
+
+        // Rulers go over diff tints so they stay visible on changed rows
+        if options.highlight {
+            let ruler_style = Style::default().bg(tint(theme().comment));
+            for &column in &options.rulers {
+                let Some(offset) = (column as usize - 1).checked_sub(self.h_scroll) else {
+                    continue;
+                };
+                let offset = gutter_cells + offset;
+                if offset < content.width as usize {
+                    let x = content.x + offset as u16;
+                    let ruler = Rect::new(x, content.y, 1, content.height);
+                    frame.buffer_mut().set_style(ruler, ruler_style);
+                }
+            }
+        }
+
+        if options.minimap {
+            let height = columns[1].height as usize;
+            if self.minimap.as_ref().is_none_or(|m| m.height != height) {
+                self.minimap = Some(Minimap::build(&self.buffer.lines, height));
+            }
+            if let Some(minimap) = &self.minimap {
+                frame.render_widget(
+                    Paragraph::new(minimap.render(
+                        shown.first().copied().unwrap_or(scroll),
+                        self.lines_in_view(scroll),
+                        options.highlight,
+                    )),
+                    columns[1],
+                );
+            }
+        }
+
+        // Vertical scrollbar, drawn over the right border only when there is
+        // somewhere to scroll to; otherwise the plain border shows through
+        let rows = self.scrollbar_rows();
+        // Wrapped lines leave fewer to a screen, and so more to scroll past
+        let scroll_range = match self.wrap {
+            true => self.max_scroll() + (rows - self.total_lines()),
+            false => max_scroll(rows, self.visible),
+        };
+        if scroll_range > 0 {
+            let mut scrollbar_state = ScrollbarState::new(scroll_range).position(scroll);
+            let scrollbar = match options.highlight {
+                true => scrollbar(theme()),
+                false => Scrollbar::default().orientation(ScrollbarOrientation::VerticalRight),
+            };
+            frame.render_stateful_widget(scrollbar, area, &mut scrollbar_state);
+
+            // Tick every search hit on the track, between the end arrows
+            let track = area.height.saturating_sub(2) as usize;
+            let x = area.right() - 1;
+            let mark_style = if options.highlight {
+                Style::default().fg(theme().warning)
+            } else {
+                Style::default()
+            };
+            let filter = self.filter.as_ref();
+            let header_rows = self.header_rows();
+            if let Some(search) = &mut self.search {
+                let row_of = |line| {
+                    let row = filter.map_or(line, |filter| filter.row_of(line));
+                    row.saturating_sub(header_rows)
+                };
+                for &mark in search.track_marks(track, rows, row_of) {
+                    let y = area.y + 1 + mark as u16;
+                    if let Some(cell) = frame.buffer_mut().cell_mut((x, y)) {
+                        cell.set_symbol("━").set_style(mark_style);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Screen regions computed by `App::layout`.
+struct Areas {
+    panes: Vec<Rect>,
+    results: Option<Rect>,
+    info: Option<Rect>,
+    status: Rect,
+}
+
+/// Tallest the results panel grows, borders included.
+const RESULTS_MAX_HEIGHT: usize = 10;
+
+/// Every line matching a search, or every mark, listed in a panel below
+/// the panes.
+struct Results {
+    /// Pane whose buffer the entries point into
+    pane: usize,
+    title: String,
+    /// `(line_index, preview)` pairs in file order
+    entries: Vec<(usize, String)>,
+    /// Letter of each entry's mark, when listing marks rather than matches
+    marks: Option<Vec<char>>,
+    state: ListState,
+}
+
+impl Results {
+    fn new(pane: usize, buffer: &Buffer, search: &Search) -> Self {
+        let entries = search
+            .matches
+            .iter()
+            .map(|&line| (line, buffer.lines[line].trim().to_string()))
+            .collect();
+        let mut state = ListState::default();
+        state.select(Some(search.current.unwrap_or(0)));
+
+        Self {
+            pane,
+            title: format!(" {} matches for {} ", search.matches.len(), search.label()),
+            entries,
+            marks: None,
+            state,
+        }
+    }
+
+    /// The marks set in `pane`, in file order, with `selected` highlighted.
+    fn marks(pane: usize, buffer: &Buffer, marks: &HashMap<char, usize>, selected: usize) -> Self {
+        let mut marked: Vec<(usize, char)> =
+            marks.iter().map(|(&mark, &line)| (line, mark)).collect();
+        marked.sort_unstable();
+        let entries = marked
+            .iter()
+            .map(|&(line, mark)| {
+                let preview = buffer.lines.get(line).map_or("", |text| text.trim());
+                (line, format!("'{mark} {preview}"))
+            })
+            .collect();
+        let mut state = ListState::default();
+        state.select(Some(selected.min(marked.len().saturating_sub(1))));
+
+        Self {
+            pane,
+            title: match marked.len() {
+                1 => " 1 mark (d deletes) ".to_string(),
+                count => format!(" {count} marks (d deletes) "),
+            },
+            entries,
+            marks: Some(marked.into_iter().map(|(_, mark)| mark).collect()),
+            state,
+        }
+    }
+
+    fn selected_line(&self) -> Option<usize> {
+        self.entries
+            .get(self.state.selected()?)
+            .map(|&(line, _)| line)
+    }
+
+    fn draw(&mut self, frame: &mut Frame, area: Rect, color: bool) {
+        let number_width = self
+            .entries
+            .last()
+            .map_or(1, |&(line, _)| (line + 1).to_string().len());
+        let (number_style, highlight_style) = if color {
+            (
+                Style::default().fg(theme().comment),
+                Style::default().bg(theme().selection),
+            )
+        } else {
+            (Style::default(), Style::default().reversed())
+        };
+
+        let items: Vec<ListItem> = if self.entries.is_empty() {
+            let empty = match self.marks {
+                Some(_) => "[no marks; m{letter} sets one]",
+                None => "[no matches]",
+            };
+            vec![ListItem::new(empty)]
+        } else {
+            self.entries
+                .iter()
+                .map(|(line, preview)| {
+                    ListItem::new(Line::from(vec![
+                        Span::styled(format!("{:>number_width$}: ", line + 1), number_style),
+                        Span::raw(preview.as_str()),
+                    ]))
+                })
+                .collect()
+        };
+
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(self.title.as_str()),
+            )
+            .highlight_style(highlight_style);
+        frame.render_stateful_widget(list, area, &mut self.state);
+    }
+}
+
+/// A file offered by a `Picker`.
+#[derive(Debug, Clone)]
+struct PickerEntry {
+    path: PathBuf,
+    /// Text matched against the query and shown in the list
+    label: String,
+    /// Extra context after the label, such as the last line viewed
+    detail: String,
+    /// Line to open at (0-based), if one was remembered
+    line: Option<usize>,
+    /// False for files that have gone away; shown dimmed, can't be opened
+    exists: bool,
+}
+
+impl PickerEntry {
+    fn new(path: PathBuf, label: String) -> Self {
+        Self {
+            exists: path.exists(),
+            path,
+            label,
+            detail: String::new(),
+            line: None,
+        }
+    }
+}
+
+/// A small full-screen list of files to open, narrowed by typing.
+struct Picker {
+    title: String,
+    list_title: String,
+    entries: Vec<PickerEntry>,
+    query: String,
+    state: ListState,
+}
+
+impl Picker {
+    fn new(title: String, list_title: String, entries: Vec<PickerEntry>) -> Self {
+        let mut state = ListState::default();
+        state.select(Some(0));
+        Self {
+            title,
+            list_title,
+            entries,
+            query: String::new(),
+            state,
+        }
+    }
+
+    /// Files next to where `missing` should have been, or `None` when the
+    /// directory can't be listed or holds no files.
+    fn for_missing(missing: &Path) -> Option<Self> {
+        // Kept as typed (possibly empty) so picked paths read naturally
+        let dir = missing.parent().unwrap_or(Path::new(""));
+        let listed = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        let mut names: Vec<String> = std::fs::read_dir(listed)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_file()))
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect();
+        if names.is_empty() {
+            return None;
+        }
+        names.sort();
+
+        let list_title = if dir.as_os_str().is_empty() {
+            "./".to_string()
+        } else {
+            format!("{}/", dir.display())
+        };
+        let entries = names
+            .into_iter()
+            .map(|name| PickerEntry::new(dir.join(&name), name))
+            .collect();
+        let mut picker = Self::new(
+            format!("{} not found; open instead:", missing.display()),
+            list_title,
+            entries,
+        );
+        picker.query = missing
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Some(picker)
+    }
+
+    /// Entries ordered by edit distance to the query, or as given while the
+    /// query is empty. A prefix match ranks as if it were one edit away, so
+    /// typing the start of a name works.
+    fn ranked(&self) -> Vec<PickerEntry> {
+        let query = self.query.to_lowercase();
+        let mut ranked: Vec<(usize, &PickerEntry)> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let label = entry.label.to_lowercase();
+                let distance = if query.is_empty() {
+                    0
+                } else if label.starts_with(&query) {
+                    1
+                } else {
+                    edit_distance(&label, &query)
+                };
+                (distance, entry)
+            })
+            .collect();
+        // Stable, so equally close entries keep their original order
+        ranked.sort_by_key(|&(distance, _)| distance);
+        ranked.into_iter().map(|(_, entry)| entry.clone()).collect()
+    }
+
+    /// Runs the picker until a file is chosen (`Some`) or it is dismissed.
+    fn run<B: Backend>(
+        mut self,
+        terminal: &mut Terminal<B>,
+        color: bool,
+    ) -> Result<Option<PickerEntry>> {
+        loop {
+            let ranked = self.ranked();
+            terminal.draw(|frame| {
+                self.draw(frame, &ranked, color);
+                fit_colors(frame);
+            })?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Esc => return Ok(None),
+                KeyCode::Enter => {
+                    let chosen = self.state.selected().and_then(|i| ranked.get(i));
+                    if let Some(entry) = chosen.filter(|entry| entry.exists) {
+                        return Ok(Some(entry.clone()));
+                    }
+                }
+                KeyCode::Down => self.state.select_next(),
+                KeyCode::Up => self.state.select_previous(),
+                KeyCode::Backspace => {
+                    self.query.pop();
+                    self.state.select(Some(0));
+                }
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(None);
+                }
+                KeyCode::Char(c) => {
+                    self.query.push(c);
+                    self.state.select(Some(0));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame, ranked: &[PickerEntry], color: bool) {
+        let area = centered_rect(60, 20, frame.area());
+        let (highlight_style, detail_style, gone_style) = if color {
+            (
+                Style::default().bg(theme().selection),
+                Style::default().fg(theme().comment),
+                Style::default().fg(theme().comment).dim(),
+            )
+        } else {
+            (
+                Style::default().reversed(),
+                Style::default(),
+                Style::default().dim(),
+            )
+        };
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Min(1)])
+            .split(area);
+
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            Paragraph::new(self.query.as_str()).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!(" {} ", self.title)),
+            ),
+            chunks[0],
+        );
+        let items: Vec<ListItem> = ranked
+            .iter()
+            .map(|entry| {
+                let mut spans = vec![Span::raw(entry.label.as_str())];
+                if !entry.detail.is_empty() {
+                    spans.push(Span::styled(format!("  {}", entry.detail), detail_style));
+                }
+                let line = Line::from(spans);
+                ListItem::new(if entry.exists {
+                    line
+                } else {
+                    line.style(gone_style)
+                })
+            })
+            .collect();
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!(" {} ", self.list_title))
+                    .title_bottom(" ↑↓: choose | Enter: open | Esc: quit "),
+            )
+            .highlight_style(highlight_style);
+        frame.render_stateful_widget(list, chunks[1], &mut self.state);
+    }
+}
+
+/// Levenshtein distance between `a` and `b`, by char.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Replaces a missing `path` with a file chosen from a picker over its
+/// directory. Errors as before when there is nothing to pick from or the
+/// picker is dismissed.
+fn pick_missing_file(path: &Path, color: bool) -> Result<PathBuf> {
+    let not_found = || anyhow::anyhow!("Failed to read file: {}: not found", path.display());
+    let picker = Picker::for_missing(path).ok_or_else(not_found)?;
+    let chosen = run_picker(picker, color)?.ok_or_else(not_found)?;
+    Ok(chosen.path)
+}
+
+/// Shows `picker` on the alternate screen and returns what was chosen.
+fn run_picker(picker: Picker, color: bool) -> Result<Option<PickerEntry>> {
+    enable_raw_mode()?;
+    stdout().execute(EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
+    let chosen = picker.run(&mut terminal, color);
+    disable_raw_mode()?;
+    stdout().execute(LeaveAlternateScreen)?;
+    chosen
+}
+
+/// Defaults from the `peek` directory under the XDG config directory.
+///
+/// `config.toml` holds settings for every file, each named after its
+/// command-line flag, a `[keys]` table giving actions (named in `ACTIONS`)
+/// keys in place of their defaults, and `[files."PATTERN"]` tables with
+/// settings for file names matching a pattern (`*` and `?` wildcards):
+///
+/// ```text
+/// theme = "solarized-light"
+/// tab-width = 4
+/// line-numbers = true
+/// lines = 20
+/// ruler = [80, 100]
+///
+/// [keys]
+/// quit = ["q", "esc"]
+/// down = ["j", "down", "ctrl-n"]
+///
+/// [files."*.log"]
+/// follow = true
+/// line-numbers = false
+/// ```
+///
+/// A setting of `false` becomes the flag's `--no-` form, so a file table
+/// can turn off what the settings above it turn on. Only the first file
+/// table whose pattern matches the file name applies. Its flags go after
+/// the settings and before the ones typed, so the command line wins.
+#[derive(Debug, Default)]
+struct Config {
+    /// `config.toml` settings as the flags they stand for
+    defaults: Vec<String>,
+    /// Default keys with `[keys]` bindings in place
+    keymap: Keymap,
+    /// `[files."PATTERN"]` tables in file order, as the flags they stand for
+    rules: Vec<(String, Vec<String>)>,
+}
+
+/// The `config.toml` table settings are being read into.
+#[derive(Clone, Copy)]
+enum ConfigTable {
+    Settings,
+    Keys,
+    /// The last of `Config::rules`
+    Files,
+}
+
+impl Config {
+    fn dir() -> Option<PathBuf> {
+        let config = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(config.join("peek"))
+    }
+
+    /// The configured defaults; none from a file that is missing or can't
+    /// be read, but a mistake in `config.toml` is an error.
+    fn load() -> Result<Self> {
+        let read = |name: &str| {
+            Self::dir()
+                .map(|dir| dir.join(name))
+                .and_then(|path| Some((std::fs::read_to_string(&path).ok()?, path)))
+        };
+        let mut config = Self::default();
+        if let Some((text, path)) = read("config.toml") {
+            config
+                .read_settings(&text)
+                .map_err(|err| anyhow::anyhow!("{}: {err}", path.display()))?;
+        }
+        Ok(config)
+    }
+
+    /// `args`, parsed from `cli_args`, with the settings and the first
+    /// matching file table placed before the flags typed.
+    fn apply(&self, args: Args, cli_args: &[std::ffi::OsString]) -> Result<Args> {
+        let mut defaults = self.defaults.clone();
+        if let Some(rule) = args.files.first().and_then(|file| self.defaults_for(file)) {
+            defaults.extend_from_slice(rule);
+        }
+        if defaults.is_empty() {
+            return Ok(args);
+        }
+        let mut merged = cli_args[..1].to_vec();
+        merged.extend(defaults.iter().map(Into::into));
+        merged.extend_from_slice(&cli_args[1..]);
+        Args::try_parse_from(merged)
+            .map_err(|err| anyhow::anyhow!("Bad config defaults `{}`: {err}", defaults.join(" ")))
+    }
+
+    /// Takes in the settings, `[keys]` and `[files."PATTERN"]` tables of a
+    /// `config.toml`: a plain subset of TOML, with strings, numbers,
+    /// booleans and arrays of them.
+    fn read_settings(&mut self, text: &str) -> Result<(), String> {
+        let cli = Args::command();
+        let mut table = ConfigTable::Settings;
+        for (number, line) in text.lines().enumerate() {
+            let fail = |reason: &str| format!("line {}: {reason}", number + 1);
+            let line = strip_toml_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[') {
+                let Some(name) = header.strip_suffix(']').map(str::trim) else {
+                    return Err(fail("expected ] after the table name"));
+                };
+                // The pattern is quoted, as its dots aren't TOML's
+                let pattern = name
+                    .strip_prefix("files.")
+                    .filter(|pattern| pattern.starts_with(['"', '\'']))
+                    .and_then(toml_scalar)
+                    .filter(|(_, rest)| rest.trim().is_empty());
+                table = match (name, pattern) {
+                    ("keys", _) => ConfigTable::Keys,
+                    (_, Some((pattern, _))) => {
+                        self.rules.push((pattern, Vec::new()));
+                        ConfigTable::Files
+                    }
+                    _ => return Err(fail(&format!("unknown table [{name}]"))),
+                };
+                continue;
+            }
+
+            let Some((name, value)) = toml_setting(line) else {
+                return Err(fail(
+                    "expected name = a string, number, boolean or array of them",
+                ));
+            };
+            let flags = match table {
+                ConfigTable::Settings => &mut self.defaults,
+                ConfigTable::Files => match self.rules.last_mut() {
+                    Some((_, flags)) => flags,
+                    None => unreachable!("a file table starts with its rule"),
+                },
+                ConfigTable::Keys => {
+                    let Some(&(action, _, _)) = ACTIONS.iter().find(|(_, known, _)| *known == name)
+                    else {
+                        let names: Vec<&str> = ACTIONS.iter().map(|(_, name, _)| *name).collect();
+                        return Err(fail(&format!(
+                            "unknown action {name}; actions are {}",
+                            names.join(", ")
+                        )));
+                    };
+                    let keys = value
+                        .iter()
+                        .map(|key| {
+                            KeyBinding::parse(key)
+                                .ok_or_else(|| fail(&format!("unknown key {key}")))
+                        })
+                        .collect::<Result<_, _>>()?;
+                    self.keymap.bind(action, keys);
+                    continue;
+                }
+            };
+            let name = name.replace('_', "-");
+            if !cli.get_arguments().any(|arg| arg.get_long() == Some(&name)) {
+                return Err(fail(&format!("unknown setting {name}")));
+            }
+            let flag = format!("--{name}");
+            match value.as_slice() {
+                [value] if value == "true" => flags.push(flag),
+                // Flags that are off by default and have no --no- form
+                // have nothing to turn off
+                [value] if value == "false" => {
+                    let negated = format!("no-{name}");
+                    if cli
+                        .get_arguments()
+                        .any(|arg| arg.get_long() == Some(&negated))
+                    {
+                        flags.push(format!("--{negated}"));
+                    }
+                }
+                values => {
+                    for value in values {
+                        flags.push(flag.clone());
+                        flags.push(value.clone());
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Flags for the first pattern matching `file`'s name.
+    fn defaults_for(&self, file: &Path) -> Option<&[String]> {
+        let name = file.file_name()?.to_str()?;
+        self.rules
+            .iter()
+            .find(|(pattern, _)| glob_matches(pattern, name))
+            .map(|(_, flags)| flags.as_slice())
+    }
+}
+
+/// `line` up to any `#` comment outside quotes.
+fn strip_toml_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(open) if c == open => quote = None,
+            Some(_) => {}
+            None if c == '#' => return &line[..i],
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None => {}
+        }
+    }
+    line
+}
+
+/// The TOML scalar at the start of `text`, a quoted string or a bare word
+/// such as a number, and the text after it.
+fn toml_scalar(text: &str) -> Option<(String, &str)> {
+    let text = text.trim_start();
+    let quote = text.chars().next()?;
+    if quote == '"' || quote == '\'' {
+        let mut value = String::new();
+        let mut chars = text.char_indices().skip(1);
+        while let Some((i, c)) = chars.next() {
+            match c {
+                c if c == quote => return Some((value, &text[i + 1..])),
+                '\\' if quote == '"' => value.push(match chars.next()?.1 {
+                    'n' => '\n',
+                    't' => '\t',
+                    escaped => escaped,
+                }),
+                c => value.push(c),
+            }
+        }
+        return None;
+    }
+    let end = text.find([',', ']']).unwrap_or(text.len());
+    let word = text[..end].trim();
+    (!word.is_empty()).then(|| (word.to_string(), &text[end..]))
+}
+
+/// The name and value of a TOML `name = value` line.
+fn toml_setting(line: &str) -> Option<(String, Vec<String>)> {
+    let (name, value) = line.split_once('=')?;
+    let (name, _) = toml_scalar(name).filter(|(_, rest)| rest.trim().is_empty())?;
+    Some((name, toml_value(value)?))
+}
+
+/// The built-in themes, then each `NAME.toml` in `peek/themes` under the
+/// XDG config directory, which replaces a built-in one of the same name.
+fn load_themes() -> Result<Vec<(String, Theme)>> {
+    let mut themes = built_in_themes();
+    let Some(entries) = Config::dir().and_then(|dir| std::fs::read_dir(dir.join("themes")).ok())
+    else {
+        return Ok(themes);
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "toml")
+        })
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        let (Some(name), Ok(text)) = (
+            path.file_stem().and_then(|stem| stem.to_str()),
+            std::fs::read_to_string(&path),
+        ) else {
+            continue;
+        };
+        let theme =
+            parse_theme(&text).map_err(|err| anyhow::anyhow!("{}: {err}", path.display()))?;
+        match themes.iter_mut().find(|(known, _)| known == name) {
+            Some(known) => known.1 = theme,
+            None => themes.push((name.to_string(), theme)),
+        }
+    }
+    Ok(themes)
+}
+
+/// A theme file: `base` names the built-in theme to start from (Dracula
+/// unless given), and every other setting replaces one of its colors:
+///
+/// ```text
+/// base = "gruvbox"
+/// background = "#1d2021"
+/// keyword = "#d3869b"
+/// ```
+fn parse_theme(text: &str) -> Result<Theme, String> {
+    let fail = |line: usize, reason: &str| format!("line {}: {reason}", line + 1);
+    let mut settings = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = strip_toml_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        match toml_setting(line) {
+            Some((name, mut value)) if value.len() == 1 => {
+                settings.push((number, name, value.remove(0)));
+            }
+            _ => return Err(fail(number, "expected name = \"value\"")),
+        }
+    }
+
+    let mut theme = Theme::DRACULA;
+    // The base goes first wherever it is, so the colors replace its own
+    if let Some((number, _, value)) = settings.iter().find(|(_, name, _)| name == "base") {
+        theme = BUILT_IN_THEMES
+            .iter()
+            .find(|(name, _)| name == value)
+            .map(|(_, theme)| *theme)
+            .ok_or_else(|| fail(*number, &format!("no built-in theme called {value}")))?;
+    }
+    for (number, name, value) in &settings {
+        let fail = |reason: &str| fail(*number, reason);
+        let color = match name.as_str() {
+            "base" => continue,
+            "background" => &mut theme.background,
+            "foreground" => &mut theme.foreground,
+            "comment" => &mut theme.comment,
+            "doc_comment" | "doc-comment" => &mut theme.doc_comment,
+            "keyword" => &mut theme.keyword,
+            "types" | "type" => &mut theme.types,
+            "string" => &mut theme.string,
+            "number" => &mut theme.number,
+            "error" => &mut theme.error,
+            "special" => &mut theme.special,
+            "warning" => &mut theme.warning,
+            "selection" => &mut theme.selection,
+            _ => return Err(fail(&format!("unknown color {name}"))),
+        };
+        *color = value
+            .parse()
+            .map_err(|_| fail(&format!("expected a color like \"#282a36\", not {value}")))?;
+    }
+    Ok(theme)
+}
+
+/// A TOML value as one string, or as many for an array.
+fn toml_value(text: &str) -> Option<Vec<String>> {
+    let Some(mut rest) = text.trim().strip_prefix('[') else {
+        let (value, rest) = toml_scalar(text)?;
+        return rest.trim().is_empty().then_some(vec![value]);
+    };
+    let mut values = Vec::new();
+    loop {
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix(']') {
+            return after.trim().is_empty().then_some(values);
+        }
+        let (value, after) = toml_scalar(rest)?;
+        values.push(value);
+        rest = after.trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest);
+    }
+}
+
+/// Whether `name` matches `pattern`, where `*` stands for any run of
+/// characters and `?` for exactly one.
+fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    // Position to retry from after the last `*`: (pattern, name)
+    let mut star = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Most files remembered in the history.
+const MAX_HISTORY: usize = 100;
+
+/// Files viewed before, most recent first, with the line each was left at.
+/// Stored as `line<TAB>path` lines under the XDG state directory.
+#[derive(Debug, Default)]
+struct History {
+    entries: Vec<(PathBuf, usize)>,
+}
+
+impl History {
+    fn path() -> Option<PathBuf> {
+        let state = std::env::var_os("XDG_STATE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state"))
+            })?;
+        Some(state.join("peek").join("history"))
+    }
+
+    /// The saved history; empty if there is none or it can't be read.
+    fn load() -> Self {
+        let text = Self::path()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .unwrap_or_default();
+        let entries = text
+            .lines()
+            .filter_map(|entry| {
+                let (line, path) = entry.split_once('\t')?;
+                Some((PathBuf::from(path), line.parse().ok()?))
+            })
+            .collect();
+        Self { entries }
+    }
+
+    /// Moves `path` to the front, remembering `line` (0-based) for it.
+    fn record(&mut self, path: PathBuf, line: usize) {
+        self.entries.retain(|(seen, _)| *seen != path);
+        self.entries.insert(0, (path, line));
+        self.entries.truncate(MAX_HISTORY);
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = Self::path().context("No HOME or XDG_STATE_HOME to save history in")?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let text: String = self
+            .entries
+            .iter()
+            .map(|(file, line)| format!("{line}\t{}\n", file.display()))
+            .collect();
+        std::fs::write(&path, text).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    fn picker(&self) -> Picker {
+        let entries = self
+            .entries
+            .iter()
+            .map(|(path, line)| {
+                let mut entry = PickerEntry::new(path.clone(), path.display().to_string());
+                entry.detail = format!("line {}", line + 1);
+                entry.line = Some(*line);
+                entry
+            })
+            .collect();
+        Picker::new(
+            "Recent files".to_string(),
+            "most recent first".to_string(),
+            entries,
+        )
+    }
+}
+
+/// Viewer state: the open panes plus everything shared between them.
+struct App {
+    panes: Vec<Pane>,
+    focus: usize,
+    split: Direction,
+    fixed_height: Option<usize>,
+    options: ViewOptions,
+    mode: Mode,
+    /// One-shot feedback shown in the status line until the next keypress
+    message: Option<String>,
+    /// Identifier whose occurrences are highlighted (`*`)
+    reference: Option<String>,
+    motion: Motion,
+    /// Set by Ctrl-w while waiting for the window command that follows
+    pending_window: bool,
+    /// Set by `m` or `'` while waiting for the mark's letter
+    pending_mark: Option<MarkAction>,
+    /// Search in force when `/` or `?` opened, put back if it is cancelled
+    search_before_prompt: Option<Search>,
+    /// Scroll every pane together (diff view)
+    linked: bool,
+    /// Context kept above jump targets; `None` centres them
+    scrolloff: Option<usize>,
+    page_overlap: usize,
+    /// Match list opened with `R`; takes j/k/Enter while shown
+    results: Option<Results>,
+    /// Animation length for jumps, when `--smooth` is on
+    smooth: Option<Duration>,
+    /// Files still being read in the background
+    loaders: Vec<Loader>,
+    /// What each key does, after `[keys]` in `config.toml`
+    keymap: Keymap,
+    /// Files watched by `--follow`, with the bytes of each read so far
+    followed: Vec<(PathBuf, u64)>,
+    /// Start position for the first pane, applied when its load finishes
+    /// unless it has been scrolled by then
+    pending_start: Option<StartCommand>,
+    /// The `+/pattern` start search found nothing; fp exits with
+    /// `EXIT_NO_MATCH`
+    start_missed: bool,
+    /// `--confirm-quit`: the first `q` only asks
+    confirm_quit: bool,
+    /// Set while waiting for the key that confirms or cancels quitting
+    quit_pending: bool,
+    esc_quits: bool,
+    /// Set by `v`; the run loop suspends the screen and opens the editor
+    edit_pending: bool,
+}
+
+impl App {
+    fn new(panes: Vec<Pane>, fixed_height: Option<usize>, options: ViewOptions) -> Self {
+        Self {
+            panes,
+            focus: 0,
+            split: Direction::Horizontal,
+            fixed_height,
+            options,
+            mode: Mode::Normal,
+            message: None,
+            reference: None,
+            motion: Motion::default(),
+            pending_window: false,
+            pending_mark: None,
+            search_before_prompt: None,
+            linked: false,
+            scrolloff: None,
+            page_overlap: 0,
+            results: None,
+            smooth: None,
+            loaders: Vec::new(),
+            keymap: Keymap::default(),
+            followed: Vec::new(),
+            pending_start: None,
+            start_missed: false,
+            confirm_quit: false,
+            quit_pending: false,
+            esc_quits: true,
+            edit_pending: false,
+        }
+    }
+
+    fn pane(&mut self) -> &mut Pane {
+        &mut self.panes[self.focus]
+    }
+
+    /// Splits `area` into the info row (with `--info`), one bordered rect
+    /// per pane, the results panel (when open) and the status row.
+    fn layout(&self, area: Rect) -> Areas {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(u16::from(self.options.info)),
+                Constraint::Min(1),
+                Constraint::Length(1),
+            ])
+            .split(area);
+
+        let results_height = self.results.as_ref().map_or(0, |results| {
+            (results.entries.len().max(1) + 2).min(RESULTS_MAX_HEIGHT) as u16
+        });
+        let body = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(results_height)])
+            .split(chunks[1]);
+
+        let count = self.panes.len().max(1) as u32;
+        let panes = Layout::default()
+            .direction(self.split)
+            .constraints(vec![Constraint::Ratio(1, count); count as usize])
+            .split(body[0]);
+
+        Areas {
+            panes: panes.to_vec(),
+            results: self.results.as_ref().map(|_| body[1]),
+            info: self.options.info.then_some(chunks[0]),
+            status: chunks[2],
+        }
+    }
+
+    /// Recomputes every pane's viewport for a screen of `area` and re-clamps
+    /// scroll positions, so the bottom of each file stays in view on shrink.
+    fn resize(&mut self, area: Rect) {
+        let areas = self.layout(area);
+        for (pane, rect) in self.panes.iter_mut().zip(areas.panes) {
+            pane.set_header(self.options.freeze_header);
+            pane.set_squeeze(self.options.squeeze);
+            pane.set_wrap(self.options.wrap);
+            let height = visible_height(rect.height.saturating_sub(2), self.fixed_height);
+            pane.visible = height.saturating_sub(usize::from(pane.shows_header()));
+            pane.clamp();
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let size = frame.area();
+        self.resize(size);
+        let Areas {
+            panes: rects,
+            results: results_area,
+            info: info_area,
+            status: status_area,
+        } = self.layout(size);
+
+        if let Some(area) = info_area {
+            let buffer = &self.panes[self.focus].buffer;
+            let info = match buffer.info() {
+                Some(info) => info.to_line(&buffer.name, self.options.exact_bytes),
+                None => Line::styled(
+                    format!("{} (no file information)", buffer.name.display()),
+                    Style::default().fg(theme().comment),
+                ),
+            };
+            let info = if self.options.highlight {
+                info
+            } else {
+                Line::from(info.to_string())
+            };
+            frame.render_widget(Paragraph::new(info), area);
+        }
+
+        let multiple = self.panes.len() > 1;
+        for (index, (pane, rect)) in self.panes.iter_mut().zip(rects).enumerate() {
+            pane.draw(
+                frame,
+                rect,
+                multiple && index == self.focus,
+                &self.options,
+                self.reference.as_deref(),
+            );
+        }
+
+        if let (Some(results), Some(area)) = (&mut self.results, results_area) {
+            results.draw(frame, area, self.options.highlight);
+        }
+
+        let status_style = if self.options.highlight {
+            Style::default().fg(theme().warning)
+        } else {
+            Style::default()
+        };
+        let status_line = Line::from(self.status(status_area.width as usize)).style(status_style);
+        frame.render_widget(Paragraph::new(status_line), status_area);
+
+        if self.options.stats {
+            let buffer = &self.panes[self.focus].buffer;
+            let mut stats = buffer.stats().to_lines(self.options.exact_bytes);
+            let mut border_style = Style::default().fg(theme().keyword);
+            if !self.options.highlight {
+                stats = stats
+                    .iter()
+                    .map(|line| Line::from(line.to_string()))
+                    .collect();
+                border_style = Style::default();
+            }
+            let popup = centered_rect(44, 11, size);
+            frame.render_widget(Clear, popup);
+            frame.render_widget(
+                Paragraph::new(stats).block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title(" File info ")
+                        .border_style(border_style),
+                ),
+                popup,
+            );
+        }
+    }
+
+    /// Status text for a row `width` cells wide. Falls back to shorter
+    /// variants on narrow terminals and never exceeds `width`.
+    fn status(&self, width: usize) -> String {
+        let pane = &self.panes[self.focus];
+        let mut position = pane.position_label(&self.options);
+        let mut compact = pane.compact_position_label();
+        if self.panes.len() > 1 {
+            let prefix = format!("[{}/{}]", self.focus + 1, self.panes.len());
+            position = format!("{prefix} {position}");
+            compact = format!("{prefix} {compact}");
+        }
+
+        let variants = match (&self.mode, &pane.search) {
+            (Mode::Prompt(prompt, query), search) => {
+                let note = match (prompt, search) {
+                    (
+                        Prompt::Search { .. },
+                        Some(Search {
+                            error: Some(error), ..
+                        }),
+                    ) => {
+                        format!("  [E: {error}]")
+                    }
+                    (Prompt::Search { .. }, _) if self.options.regex => "  [regex]".to_string(),
+                    _ => String::new(),
+                };
+                // Keep the end of the query (where the user is typing) visible
+                return fit_width_end(&format!("{}{query}{note}", prompt.symbol()), width);
+            }
+            (Mode::Normal, _) if self.message.is_some() => {
+                vec![self.message.clone().unwrap_or_default()]
+            }
+            (Mode::Normal, _) if let Some(visual) = pane.visual => {
+                let lines = visual.lines();
+                let selected = &pane.buffer.lines[lines.clone()];
+                let words: usize = selected
+                    .iter()
+                    .map(|line| line.split_whitespace().count())
+                    .sum();
+                let range = format!(
+                    "-- VISUAL -- lines {}-{}: {} lines, {} words",
+                    grouped(lines.start() + 1),
+                    grouped(lines.end() + 1),
+                    grouped(selected.len()),
+                    grouped(words)
+                );
+                vec![
+                    format!("{range} | y: yank | :w FILE: write | Esc: cancel"),
+                    range,
+                    format!("-- VISUAL -- {} lines", grouped(selected.len())),
+                ]
+            }
+            (Mode::Normal, Some(search)) => vec![
+                format!(
+                    "{position} | {} {} | n/N: next/prev | Esc: clear",
+                    search.label(),
+                    search.count_label()
+                ),
+                format!("{position} | {} {}", search.label(), search.count_label()),
+                format!("{compact} {}", search.count_label()),
+            ],
+            (Mode::Normal, None) if pane.pages_sideways() => vec![
+                format!(
+                    "{position} | long lines: PgUp/PgDn, h/l scroll sideways | 0/$: start/end | q: quit"
+                ),
+                format!("{position} | long lines: PgDn scrolls sideways"),
+                compact,
+            ],
+            (Mode::Normal, None) => vec![
+                format!(
+                    "{position} | ↑↓/j k: line | PgUp/PgDn: page | gg/G: top/bottom | /: search | q: quit"
+                ),
+                format!("{position} | /: search | q: quit"),
+                compact,
+            ],
+        };
+
+        let fallback = variants.last().cloned().unwrap_or_default();
+        variants
+            .into_iter()
+            .find(|variant| variant.width() <= width)
+            .unwrap_or_else(|| fit_width(&fallback, width))
+    }
+
+    /// Called when a lone `g` times out: behaves like `gg`.
+    fn flush_pending_g(&mut self) {
+        self.motion.pending_g = false;
+        let line = self
+            .motion
+            .count
+            .take()
+            .map_or(0, |line| line.saturating_sub(1));
+        let scrolloff = self.scrolloff;
+        self.pane().jump_to(line, scrolloff);
+    }
+
+    /// Handles a Ctrl-w window command. Returns after consuming `key`.
+    fn window_command(&mut self, key: KeyEvent) {
+        self.pending_window = false;
+        match key.code {
+            KeyCode::Char('w') | KeyCode::Tab => {
+                self.focus = (self.focus + 1) % self.panes.len();
+            }
+            KeyCode::Char('W') | KeyCode::BackTab => {
+                self.focus = (self.focus + self.panes.len() - 1) % self.panes.len();
+            }
+            KeyCode::Char('s') | KeyCode::Char('v') => {
+                self.split = if key.code == KeyCode::Char('s') {
+                    Direction::Vertical
+                } else {
+                    Direction::Horizontal
+                };
+                let pane = self.panes[self.focus].split();
+                self.panes.insert(self.focus + 1, pane);
+                self.focus += 1;
+            }
+            KeyCode::Char('q') | KeyCode::Char('c') if self.panes.len() > 1 => {
+                self.panes.remove(self.focus);
+                self.focus = self.focus.min(self.panes.len() - 1);
+            }
+            _ => {}
+        }
+    }
+
+    /// Applies one keypress. Returns `false` when the viewer should exit.
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        let now = Instant::now();
+        let before: Vec<(usize, f64)> = self
+            .panes
+            .iter()
+            .map(|pane| (pane.scroll, pane.shown_scroll(now)))
+            .collect();
+
+        let running = self.apply_key(key);
+        // Somewhere to start is moot once the first pane has been scrolled
+        if self.panes.first().map(|pane| pane.scroll) != before.first().map(|&(scroll, _)| scroll) {
+            self.pending_start = None;
+        }
+        if self.linked {
+            let Pane {
+                scroll, h_scroll, ..
+            } = self.panes[self.focus];
+            for pane in &mut self.panes {
+                pane.scroll = scroll;
+                pane.h_scroll = h_scroll;
+            }
+        }
+
+        // Start (or retarget) from wherever the view is drawn right now, so
+        // rapid keypresses bend the animation instead of queueing behind it.
+        // Single-line steps stay instant.
+        for (pane, (scroll, shown)) in self.panes.iter_mut().zip(before) {
+            if pane.scroll == scroll {
+                continue;
+            }
+            pane.animation = self
+                .smooth
+                .filter(|_| pane.scroll.abs_diff(scroll) > 1 || pane.animation.is_some())
+                .map(|duration| ScrollAnimation {
+                    from: shown,
+                    start: now,
+                    duration,
+                });
+        }
+        running
+    }
+
+    fn is_loading(&self) -> bool {
+        !self.loaders.is_empty()
+    }
+
+    /// Whether a search has yet to look through the whole of its buffer.
+    fn is_searching(&self) -> bool {
+        self.panes.iter().any(|pane| {
+            pane.search
+                .as_ref()
+                .is_some_and(|search| !search.is_complete(&pane.buffer.lines))
+        })
+    }
+
+    /// Takes each unfinished search another `SEARCH_CHUNK` lines on.
+    fn continue_searches(&mut self) {
+        for pane in &mut self.panes {
+            if let Some(search) = &mut pane.search {
+                search.scan_to(&pane.buffer.lines, search.scanned + SEARCH_CHUNK);
+            }
+        }
+    }
+
+    /// Takes whatever the loader threads have produced since the last frame.
+    fn receive_loaded(&mut self) {
+        let mut loaders = std::mem::take(&mut self.loaders);
+        loaders.retain(|loader| {
+            loop {
+                match loader.receiver.try_recv() {
+                    Ok(loaded) => {
+                        let finished = matches!(loaded, Loaded::Done | Loaded::Failed(_));
+                        if let Err(err) = self.apply_loaded(loader.buffer, loaded) {
+                            self.message = Some(format!("E: {err:#}"));
+                            return false;
+                        }
+                        if finished {
+                            return false;
+                        }
+                    }
+                    Err(TryRecvError::Empty) => return true,
+                    Err(TryRecvError::Disconnected) => return false,
+                }
+            }
+        });
+        self.loaders = loaders;
+    }
+
+    /// Folds `loaded` into buffer `target` and brings the panes showing it
+    /// up to date. A buffer no pane shows any more is left alone; one that
+    /// can't be changed is an error, as the lines would be lost.
+    fn apply_loaded(&mut self, target: BufferId, loaded: Loaded) -> Result<()> {
+        let owners: Vec<usize> = (0..self.panes.len())
+            .filter(|&i| self.panes[i].buffer.id == target)
+            .collect();
+        let Some(&first) = owners.first() else {
+            return Ok(());
+        };
+        if let Loaded::Failed(err) = &loaded {
+            self.message = Some(err.clone());
+        }
+        let done = matches!(loaded, Loaded::Done | Loaded::Appended(..));
+        // Followed panes at the bottom stay there as lines are appended
+        let pinned: Vec<bool> = owners
+            .iter()
+            .map(|&i| {
+                let pane = &self.panes[i];
+                matches!(loaded, Loaded::Appended(..)) && pane.scroll >= pane.max_scroll()
+            })
+            .collect();
+
+        // Panes share the buffer; park them on a placeholder so it can be
+        // borrowed mutably, then hand the grown buffer back to each
+        let placeholder = Rc::new(Buffer::new(PathBuf::new(), Vec::new()));
+        let mut buffer = std::mem::replace(&mut self.panes[first].buffer, Rc::clone(&placeholder));
+        for &i in &owners[1..] {
+            self.panes[i].buffer = Rc::clone(&placeholder);
+        }
+        let applied = Rc::get_mut(&mut buffer)
+            .map(|buffer| buffer.apply_loaded(loaded))
+            .is_some();
+        for &i in &owners {
+            self.panes[i].buffer = Rc::clone(&buffer);
+        }
+        if !applied {
+            anyhow::bail!(
+                "{} is held elsewhere, so lines read into it were lost",
+                buffer.name.display()
+            );
+        }
+        for (&i, pinned) in owners.iter().zip(pinned) {
+            let pane = &mut self.panes[i];
+            pane.minimap = None;
+            if done {
+                pane.refresh_matches();
+                if pinned {
+                    pane.scroll = pane.max_scroll();
+                }
+            } else if pane
+                .filter
+                .as_ref()
+                .is_some_and(|filter| !filter.has_pattern())
+            {
+                // A squeeze alone hides no text, so keep it in step as lines arrive
+                pane.update_filter(String::new(), true);
+            }
+        }
+
+        if done
+            && first == 0
+            && let Some(start) = self.pending_start.take()
+        {
+            let searching = matches!(start, StartCommand::Search(_));
+            let pane = &mut self.panes[0];
+            let note = pane.apply_start(start, self.options.search_mode());
+            self.start_missed = searching && pane.finds_nothing();
+            if note.is_some() {
+                self.message = note;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads whatever has been appended to each file shown since the last
+    /// look. A file seen for the first time is followed from its current
+    /// end; one that has shrunk was truncated or replaced, so is reloaded.
+    fn follow_files(&mut self) {
+        let mut names: Vec<PathBuf> = Vec::new();
+        for pane in &self.panes {
+            if pane.buffer.follows() && !names.contains(&pane.buffer.name) {
+                names.push(pane.buffer.name.clone());
+            }
+        }
+        self.followed.retain(|(name, _)| names.contains(name));
+
+        for name in names {
+            let Ok(size) = std::fs::metadata(&name).map(|metadata| metadata.len()) else {
+                continue;
+            };
+            let Some(index) = self
+                .followed
+                .iter()
+                .position(|(followed, _)| *followed == name)
+            else {
+                self.followed.push((name, size));
+                continue;
+            };
+            let offset = self.followed[index].1;
+            if size < offset {
+                self.followed[index].1 = size;
+                if let Err(err) = self.reload(&name) {
+                    self.message = Some(format!("E: {err:#}"));
+                }
+                continue;
+            }
+            if size == offset {
+                continue;
+            }
+            let Some(pane) = self.panes.iter().find(|pane| pane.buffer.name == name) else {
+                continue;
+            };
+            let (target, encoding) = (pane.buffer.id, pane.buffer.encoding);
+            let mut bytes = Vec::new();
+            let read = File::open(&name).and_then(|mut file| {
+                file.seek(SeekFrom::Start(offset))?;
+                file.take(size - offset).read_to_end(&mut bytes)
+            });
+            if let Err(err) = read {
+                self.message = Some(format!("E: {}: {err}", name.display()));
+                continue;
+            }
+            // Only complete lines are taken; a part-written one waits
+            let Some(newline) = bytes.iter().rposition(|&byte| byte == b'\n') else {
+                continue;
+            };
+            self.followed[index].1 = offset + newline as u64 + 1;
+            let (text, _) = encoding.decode_without_bom_handling(&bytes[..newline]);
+            let lines = text.split('\n').map(line_text).collect();
+            let endings = LineEndings::count(&bytes[..=newline]);
+            if let Err(err) = self.apply_loaded(target, Loaded::Appended(lines, endings)) {
+                self.message = Some(format!("E: {err:#}"));
+            }
+        }
+    }
+
+    fn is_animating(&self) -> bool {
+        let now = Instant::now();
+        self.panes.iter().any(|pane| pane.is_animating(now))
+    }
+
+    fn apply_key(&mut self, key: KeyEvent) -> bool {
+        self.message = None;
+
+        if std::mem::take(&mut self.quit_pending) {
+            // Any key but another quit stays
+            return !self.is_quit_key(key);
+        }
+
+        if let Mode::Prompt(prompt, query) = &mut self.mode {
+            let prompt = *prompt;
+            match key.code {
+                KeyCode::Char(c) if matches!(prompt, Prompt::Command { .. }) => {
+                    query.push(c);
+                    self.preview_goto();
+                }
+                KeyCode::Tab if matches!(prompt, Prompt::Command { .. }) => {
+                    if let Some(completed) = complete_command(query) {
+                        *query = completed;
+                    }
+                }
+                KeyCode::Char('r')
+                    if matches!(prompt, Prompt::Search { .. })
+                        && key.modifiers.contains(KeyModifiers::CONTROL) =>
+                {
+                    self.options.regex = !self.options.regex;
+                    self.preview_search();
+                }
+                KeyCode::Char(c) => {
+                    query.push(c);
+                    self.preview_search();
+                }
+                KeyCode::Backspace => {
+                    query.pop();
+                    self.preview_goto();
+                    self.preview_search();
+                }
+                KeyCode::Enter | KeyCode::Esc if matches!(prompt, Prompt::Command { .. }) => {
+                    let Prompt::Command { origin } = prompt else {
+                        return true;
+                    };
+                    let command = std::mem::take(query);
+                    self.mode = Mode::Normal;
+                    // Jump from where the prompt opened, so Ctrl-o returns there
+                    self.pane().scroll = origin;
+                    if key.code == KeyCode::Esc {
+                        return true;
+                    }
+                    let total_lines = self.panes[self.focus].buffer.lines.len();
+                    match goto_target(&command, total_lines) {
+                        Some(line) => {
+                            let scrolloff = self.scrolloff;
+                            let pane = self.pane();
+                            pane.jump_to(line, scrolloff);
+                            pane.clamp();
+                        }
+                        None => return self.run_command(&command),
+                    }
+                }
+                KeyCode::Enter | KeyCode::Esc
+                    if matches!(prompt, Prompt::Search { .. })
+                        && (query.is_empty() || key.code == KeyCode::Esc) =>
+                {
+                    let Prompt::Search { origin, .. } = prompt else {
+                        return true;
+                    };
+                    self.mode = Mode::Normal;
+                    let search = self.search_before_prompt.take();
+                    let pane = self.pane();
+                    pane.search = search;
+                    pane.scroll = origin;
+                }
+                KeyCode::Enter if prompt == Prompt::Filter => {
+                    // An empty filter shows every line again, as in less
+                    let pattern = std::mem::take(query);
+                    self.mode = Mode::Normal;
+                    self.pane().set_filter(pattern);
+                }
+                KeyCode::Enter => {
+                    let Prompt::Search { origin, backward } = prompt else {
+                        return true;
+                    };
+                    let query = std::mem::take(query);
+                    self.mode = Mode::Normal;
+                    self.search_before_prompt = None;
+                    let (scrolloff, mode) = (self.scrolloff, self.options.search_mode());
+                    let pane = self.pane();
+                    // Jump from where the prompt opened, so Ctrl-o returns there
+                    pane.scroll = origin;
+                    let mut committed = pane.search_for(query, mode);
+                    committed.backward = backward;
+                    let lines = &pane.buffer.lines;
+                    if let Some(line) = committed.first_toward(pane.top_line(), backward, lines) {
+                        pane.jump_to(line, scrolloff);
+                    }
+                    pane.search = Some(committed);
+                }
+                KeyCode::Esc => self.mode = Mode::Normal,
+                _ => {}
+            }
+            return true;
+        }
+
+        // Before the panel's keys, so `md` sets mark d rather than deleting
+        if let Some(action) = self.pending_mark.take() {
+            if let KeyCode::Char(letter) = key.code
+                && letter.is_ascii_alphabetic()
+            {
+                self.use_mark(action, letter);
+            }
+            return true;
+        }
+
+        if self.results.is_some() && self.results_key(key) {
+            return true;
+        }
+
+        if self.pending_window {
+            self.window_command(key);
+            return true;
+        }
+
+        if self.motion.pending_g {
+            if self.keymap.action(key) == Some(Action::Top) {
+                self.flush_pending_g();
+                return true;
+            }
+            // Any other key only drops the half-typed `gg` and its count,
+            // as in Vim
+            self.motion = Motion::default();
+            return true;
+        }
+
+        if let KeyCode::Char(c) = key.code
+            && self.motion.accepts_digit(c)
+        {
+            self.motion.push_digit(c.to_digit(10).unwrap_or(0));
+            return true;
+        }
+        let count = self.motion.count.take();
+        let repeat = count.unwrap_or(1);
+
+        if self.panes[self.focus].visual.is_some() && self.visual_key(key, repeat) {
+            return true;
+        }
+
+        let Some(action) = self.keymap.action(key) else {
+            return true;
+        };
+        match action {
+            Action::Cancel if count.is_some() => {}
+            Action::Cancel if self.options.stats => self.options.stats = false,
+            Action::Stats => self.options.stats = !self.options.stats,
+            Action::Cancel if self.reference.is_some() => self.reference = None,
+            Action::Cancel if self.panes[self.focus].search.is_some() => {
+                let pane = self.pane();
+                pane.last_query = pane.search.take().map(|search| search.query);
+            }
+            Action::Cancel
+                if self.panes[self.focus]
+                    .filter
+                    .as_ref()
+                    .is_some_and(Filter::has_pattern) =>
+            {
+                self.pane().set_filter(String::new())
+            }
+            Action::Cancel if !self.esc_quits => {}
+            Action::Quit | Action::Cancel if self.confirm_quit => {
+                self.quit_pending = true;
+                self.message = Some("Quit? Press q again, any other key to stay".to_string());
+            }
+            Action::Quit | Action::Cancel => return false,
+            Action::Search => self.open_search(false),
+            Action::SearchBackward => self.open_search(true),
+            Action::Filter => self.mode = Mode::Prompt(Prompt::Filter, String::new()),
+            Action::Command => {
+                let origin = self.panes[self.focus].scroll;
+                self.mode = Mode::Prompt(Prompt::Command { origin }, String::new());
+            }
+            Action::Reference => {
+                self.reference = match self.reference {
+                    Some(_) => None,
+                    None => {
+                        let pane = &self.panes[self.focus];
+                        pane.buffer
+                            .lines
+                            .get(pane.top_line())
+                            .and_then(|line| first_identifier(line))
+                    }
+                };
+            }
+            Action::OpenUrl => self.open_url(),
+            Action::Export => self.export(),
+            Action::Edit => self.edit_pending = true,
+            Action::SetMark => self.pending_mark = Some(MarkAction::Set),
+            Action::Visual => {
+                let pane = self.pane();
+                let line = pane.top_line();
+                pane.visual = Some(Visual {
+                    anchor: line,
+                    cursor: line,
+                });
+            }
+            Action::JumpToMark => self.pending_mark = Some(MarkAction::Jump),
+            Action::Marks => match &self.results {
+                Some(results) if results.marks.is_some() => self.results = None,
+                _ => self.open_marks(0),
+            },
+            Action::JumpBack => {
+                let pane = self.pane();
+                for _ in 0..repeat {
+                    let Some(line) = pane.jumps.back(pane.top_line()) else {
+                        break;
+                    };
+                    pane.scroll = pane.row_of(line);
+                }
+            }
+            Action::JumpForward => self.jump_forward(repeat),
+            Action::Window => self.pending_window = true,
+            Action::Results => self.open_results(),
+            Action::NextMatch | Action::PrevMatch => {
+                let (scrolloff, mode) = (self.scrolloff, self.options.search_mode());
+                let pane = self.pane();
+                pane.restore_search(mode);
+                // After `?`, `n` carries on up the file, as in less and Vim
+                let backward = pane.search.as_ref().is_some_and(|search| search.backward);
+                let forward = (action == Action::NextMatch) != backward;
+                // Matches wrap around, so more steps than there are matches
+                // would only go round again
+                let matches = pane
+                    .search
+                    .as_ref()
+                    .map_or(0, |search| search.matches.len());
+                for _ in 0..repeat.min(matches) {
+                    let origin = pane.search_origin();
+                    let next = pane.search.as_mut().and_then(|search| match forward {
+                        true => search.next_after(origin, &pane.buffer.lines),
+                        false => search.prev_before(origin, &pane.buffer.lines),
+                    });
+                    if let Some(line) = next {
+                        pane.jump_to(line, scrolloff);
+                    }
+                }
+            }
+            Action::ParagraphDown => self.pane().paragraph_jump(true, repeat),
+            Action::ParagraphUp => self.pane().paragraph_jump(false, repeat),
+            Action::WordRight => self.pane().word_scroll(true, repeat),
+            Action::WordLeft => self.pane().word_scroll(false, repeat),
+            Action::Right => {
+                let pane = self.pane();
+                pane.h_scroll = (pane.h_scroll + repeat).min(pane.shown_width().max(pane.h_scroll));
+            }
+            Action::Left => {
+                let pane = self.pane();
+                pane.h_scroll = pane.h_scroll.saturating_sub(repeat);
+            }
+            Action::LineStart => self.pane().h_scroll = 0,
+            Action::LineEnd => self.pane().scroll_to_line_end(),
+            Action::Down => {
+                self.pane().line_down(repeat);
+            }
+            Action::Up => {
+                let pane = self.pane();
+                pane.scroll = pane.scroll.saturating_sub(repeat);
+            }
+            Action::PageDown if self.panes[self.focus].pages_sideways() => {
+                self.pane().page_sideways(true, repeat)
+            }
+            Action::PageUp if self.panes[self.focus].pages_sideways() => {
+                self.pane().page_sideways(false, repeat)
+            }
+            Action::PageDown => {
+                let overlap = self.page_overlap;
+                let pane = self.pane();
+                for _ in 0..repeat {
+                    let scroll = pane.scroll;
+                    pane.page_down(overlap);
+                    if pane.scroll == scroll {
+                        break;
+                    }
+                }
+            }
+            Action::PageUp => {
+                let overlap = self.page_overlap;
+                let pane = self.pane();
+                for _ in 0..repeat {
+                    let scroll = pane.scroll;
+                    pane.page_up(overlap);
+                    if pane.scroll == scroll {
+                        break;
+                    }
+                }
+            }
+            Action::Top => {
+                // Keep the count so `42gg` works like `42G`
+                self.motion.count = count;
+                self.motion.pending_g = true;
+            }
+            Action::Link if self.panes.len() > 1 => {
+                self.linked = !self.linked;
+                self.message = Some(
+                    if self.linked {
+                        "Panes scroll together"
+                    } else {
+                        "Panes scroll independently"
+                    }
+                    .to_string(),
+                );
+            }
+            Action::Link => {}
+            Action::Minimap => self.options.minimap = !self.options.minimap,
+            Action::IndentGuides => self.options.indent_guides = !self.options.indent_guides,
+            Action::Scope => self.options.scope = !self.options.scope,
+            Action::Header => self.options.freeze_header = !self.options.freeze_header,
+            // Numbered, then numbered from the top line, then no numbers
+            Action::Numbers => {
+                let options = &mut self.options;
+                (options.line_numbers, options.relative_numbers) =
+                    match (options.line_numbers, options.relative_numbers) {
+                        (false, false) => (true, false),
+                        (true, false) => (true, true),
+                        _ => (false, false),
+                    };
+            }
+            Action::Focus => self.options.focus = !self.options.focus,
+            Action::Squeeze => self.options.squeeze = !self.options.squeeze,
+            Action::Follow => self.options.follow = !self.options.follow,
+            Action::Wrap => self.options.wrap = !self.options.wrap,
+            Action::Theme => {
+                let next = (THEME.load(Ordering::Relaxed) + 1) % themes().len();
+                THEME.store(next, Ordering::Relaxed);
+                self.message = Some(format!("theme {}", themes()[next].0));
+            }
+            Action::Bottom => {
+                let scrolloff = self.scrolloff;
+                let pane = self.pane();
+                match count {
+                    Some(line) => pane.jump_to(line.saturating_sub(1), scrolloff),
+                    None => pane.jump_to_bottom(),
+                }
+            }
+        }
+
+        true
+    }
+
+    fn is_quit_key(&self, key: KeyEvent) -> bool {
+        match self.keymap.action(key) {
+            Some(Action::Quit) => true,
+            Some(Action::Cancel) => self.esc_quits,
+            _ => key.code == KeyCode::Char('y'),
+        }
+    }
+
+    /// Runs a `:` command line. Returns `false` when it asks to quit.
+    fn run_command(&mut self, line: &str) -> bool {
+        let mut words = line.split_whitespace();
+        let Some(name) = words.next() else {
+            return true;
+        };
+        match (name, words.next()) {
+            ("q" | "quit", None) => return false,
+            ("noh" | "nohlsearch", None) => {
+                // Like Esc, the query stays around for `n`
+                let pane = self.pane();
+                if let Some(search) = pane.search.take() {
+                    pane.last_query = Some(search.query);
+                }
+            }
+            ("range", None) => {
+                self.message = Some(match &self.pane().search_range {
+                    Some(range) => format!("range {} {}", range.start() + 1, range.end() + 1),
+                    None => "range: whole file".to_string(),
+                });
+            }
+            ("range", Some("clear")) => self.pane().set_search_range(None),
+            ("range", Some(start)) => match (
+                start.parse::<usize>(),
+                words.next().map(str::parse::<usize>),
+            ) {
+                (Ok(start), Some(Ok(end))) => {
+                    // 1-based and inclusive, in either order
+                    let (start, end) = (start.min(end).max(1), start.max(end).max(1));
+                    self.pane().set_search_range(Some(start - 1..=end - 1));
+                }
+                _ => self.message = Some("E: usage: range FIRST LAST | range clear".to_string()),
+            },
+            ("set", Some(option)) => self.set_option(option),
+            ("theme", Some(name)) if !set_theme(name) => {
+                let known: Vec<&str> = themes().iter().map(|(name, _)| name.as_str()).collect();
+                self.message = Some(format!(
+                    "E: unknown theme: {name} (have {})",
+                    known.join(", ")
+                ));
+            }
+            ("theme", Some(_)) => {}
+            ("theme", None) => {
+                let current = &themes()[THEME.load(Ordering::Relaxed)].0;
+                self.message = Some(format!("theme {current}"));
+            }
+            ("w" | "write" | "w!" | "write!", _) => {
+                let path = line.trim_start()[name.len()..].trim();
+                self.write_lines(path, name.ends_with('!'));
+            }
+            _ => self.message = Some(format!("E: not a command: {line}")),
+        }
+        true
+    }
+
+    /// `:set name`, `:set noname` or `:set name!` for one of the display
+    /// toggles.
+    fn set_option(&mut self, option: &str) {
+        let (name, value) = match option.strip_suffix('!') {
+            Some(name) => (name, None),
+            None => match option.strip_prefix("no") {
+                Some(name) => (name, Some(false)),
+                None => (option, Some(true)),
+            },
+        };
+        let options = &mut self.options;
+        let flag = match name {
+            "minimap" => &mut options.minimap,
+            "guides" | "indentguides" => &mut options.indent_guides,
+            "scope" => &mut options.scope,
+            "rainbow" => &mut options.rainbow,
+            "word" => &mut options.whole_word,
+            "regex" => &mut options.regex,
+            "markdown" => &mut options.markdown,
+            "stats" => &mut options.stats,
+            "info" => &mut options.info,
+            "header" => &mut options.freeze_header,
+            "number" | "nu" => &mut options.line_numbers,
+            "relativenumber" | "rnu" => &mut options.relative_numbers,
+            "bytes" => &mut options.exact_bytes,
+            "focus" => &mut options.focus,
+            "squeeze" => &mut options.squeeze,
+            "follow" => &mut options.follow,
+            "wrap" => &mut options.wrap,
+            "highlight" | "syntax" => &mut options.highlight,
+            _ => {
+                self.message = Some(format!("E: unknown option: {name}"));
+                return;
+            }
+        };
+        *flag = value.unwrap_or(!*flag);
+
+        // Searches already made follow a change of word or regex matching
+        if name == "word" || name == "regex" {
+            self.refresh_search_mode();
+        }
+    }
+
+    /// Reruns every pane's search with the current word and regex options.
+    fn refresh_search_mode(&mut self) {
+        let mode = self.options.search_mode();
+        for pane in &mut self.panes {
+            if let Some(search) = &mut pane.search {
+                search.mode = mode;
+            }
+            pane.refresh_matches();
+        }
+    }
+
+    /// Opens a `/` (or with `backward`, `?`) prompt. The current search
+    /// gives way to the one being typed, and comes back on Esc.
+    fn open_search(&mut self, backward: bool) {
+        let pane = self.pane();
+        let origin = pane.scroll;
+        self.search_before_prompt = pane.search.take();
+        self.mode = Mode::Prompt(Prompt::Search { origin, backward }, String::new());
+    }
+
+    /// Shows the matches for the query typed so far at a `/` or `?` prompt,
+    /// scrolled to the nearest one from where the prompt opened.
+    fn preview_search(&mut self) {
+        let Mode::Prompt(Prompt::Search { origin, backward }, query) = &self.mode else {
+            return;
+        };
+        let (origin, backward, query) = (*origin, *backward, query.clone());
+        let (scrolloff, mode) = (self.scrolloff, self.options.search_mode());
+        let pane = self.pane();
+        pane.scroll = origin;
+        pane.search = (!query.is_empty()).then(|| {
+            let mut search = pane.search_for(query, mode);
+            search.backward = backward;
+            let lines = &pane.buffer.lines;
+            if let Some(line) = search.first_toward(pane.top_line(), backward, lines) {
+                pane.scroll = pane.scroll_for(pane.row_of(line), scrolloff);
+            }
+            search
+        });
+        pane.clamp();
+    }
+
+    /// Scrolls to the line typed so far at a `:` prompt, or back to where
+    /// the prompt opened once the input is empty.
+    fn preview_goto(&mut self) {
+        let Mode::Prompt(Prompt::Command { origin }, query) = &self.mode else {
+            return;
+        };
+        let total_lines = self.panes[self.focus].buffer.lines.len();
+        let (origin, target) = (*origin, goto_target(query, total_lines));
+        let scrolloff = self.scrolloff;
+        let pane = self.pane();
+        pane.scroll = match target {
+            Some(line) => pane.scroll_for(pane.row_of(line), scrolloff),
+            None => origin,
+        };
+        pane.clamp();
+    }
+
+    /// Handles navigation inside the results panel. Returns whether `key`
+    /// was consumed; anything else falls through to the normal bindings.
+    fn results_key(&mut self, key: KeyEvent) -> bool {
+        let Some(results) = &mut self.results else {
+            return false;
+        };
+        match key.code {
+            KeyCode::Char('j') | KeyCode::Down => results.state.select_next(),
+            KeyCode::Char('k') | KeyCode::Up => results.state.select_previous(),
+            KeyCode::Enter => {
+                if let Some(line) = results.selected_line() {
+                    let scrolloff = self.scrolloff;
+                    let pane = results.pane.min(self.panes.len() - 1);
+                    self.focus = pane;
+                    self.panes[pane].jump_to(line, scrolloff);
+                    // Entries mirror the search's matches, so keep `n`/`N` in step
+                    if results.marks.is_none()
+                        && let Some(search) = &mut self.panes[pane].search
+                    {
+                        search.current = results.state.selected();
+                    }
+                }
+            }
+            KeyCode::Char('d') if results.marks.is_some() => {
+                let selected = results.state.selected().unwrap_or(0);
+                let mark = results.marks.as_ref().and_then(|marks| marks.get(selected));
+                if let Some(mark) = mark.copied() {
+                    let pane = results.pane.min(self.panes.len() - 1);
+                    self.panes[pane].marks.remove(&mark);
+                    self.focus = pane;
+                    self.open_marks(selected);
+                }
+            }
+            KeyCode::Esc => self.results = None,
+            _ => return false,
+        }
+        true
+    }
+
+    fn jump_forward(&mut self, repeat: usize) {
+        let pane = self.pane();
+        for _ in 0..repeat {
+            let Some(line) = pane.jumps.forward() else {
+                break;
+            };
+            pane.scroll = pane.row_of(line);
+        }
+    }
+
+    /// Keys with a meaning of their own while a `V` selection is active.
+    /// Returns whether `key` was consumed; the rest scroll as usual.
+    fn visual_key(&mut self, key: KeyEvent, repeat: usize) -> bool {
+        let scrolloff = self.scrolloff;
+        let pane = self.pane();
+        match key.code {
+            KeyCode::Char('j') | KeyCode::Down => pane.move_visual(true, repeat, scrolloff),
+            KeyCode::Char('k') | KeyCode::Up => pane.move_visual(false, repeat, scrolloff),
+            KeyCode::Esc | KeyCode::Char('V') => pane.visual = None,
+            KeyCode::Char('y') => {
+                let count = pane.visual.map_or(0, |visual| visual.lines().count());
+                let text = pane.selected_text().unwrap_or_default();
+                pane.visual = None;
+                self.message = Some(match copy_to_clipboard(&text) {
+                    Ok(()) => format!("Yanked {} lines", grouped(count)),
+                    Err(err) => format!("E: can't reach the clipboard: {err}"),
+                });
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// `:w FILE` writes the `V` selection, or the whole file without one.
+    /// An existing file is only replaced by `:w!`.
+    fn write_lines(&mut self, path: &str, overwrite: bool) {
+        if path.is_empty() {
+            self.message = Some("E: usage: w FILE".to_string());
+            return;
+        }
+        let path = PathBuf::from(path);
+        if path.exists() && !overwrite {
+            self.message = Some(format!("E: {} exists (:w! replaces it)", path.display()));
+            return;
+        }
+        let pane = self.pane();
+        let text = match pane.selected_text() {
+            Some(text) => text,
+            None => pane.buffer.lines.join("\n") + "\n",
+        };
+        let count = text.lines().count();
+        self.message = Some(match std::fs::write(&path, text) {
+            Ok(()) => {
+                pane.visual = None;
+                format!("Wrote {} lines to {}", grouped(count), path.display())
+            }
+            Err(err) => format!("E: can't write {}: {err}", path.display()),
+        });
+    }
+
+    /// Sets or jumps to the mark called `letter` in the focused pane.
+    fn use_mark(&mut self, action: MarkAction, letter: char) {
+        let scrolloff = self.scrolloff;
+        let pane = self.pane();
+        match action {
+            MarkAction::Set => {
+                let line = pane.top_line();
+                pane.marks.insert(letter, line);
+                self.message = Some(format!("Mark '{letter} set at line {}", line + 1));
+                // Keep an open marks panel in step
+                if let Some(results) = self.results.as_ref().filter(|r| r.marks.is_some()) {
+                    let selected = results.state.selected().unwrap_or(0);
+                    self.open_marks(selected);
+                }
+            }
+            MarkAction::Jump => match pane.marks.get(&letter) {
+                Some(&line) => pane.jump_to(line, scrolloff),
+                None => self.message = Some(format!("E: mark '{letter} not set")),
+            },
+        }
+    }
+
+    /// Lists the focused pane's marks in the results panel.
+    fn open_marks(&mut self, selected: usize) {
+        let pane = &self.panes[self.focus];
+        self.results = Some(Results::marks(
+            self.focus,
+            &pane.buffer,
+            &pane.marks,
+            selected,
+        ));
+    }
+
+    fn open_results(&mut self) {
+        let pane = &mut self.panes[self.focus];
+        match &mut pane.search {
+            Some(search) => {
+                search.finish(&pane.buffer.lines);
+                self.results = Some(Results::new(self.focus, &pane.buffer, search));
+            }
+            None => self.message = Some("No active search; press / first".to_string()),
+        }
+    }
+
+    /// Saves the focused buffer as `<file name>.html` in the working directory.
+    fn export(&mut self) {
+        let buffer = &self.panes[self.focus].buffer;
+        let name = buffer
+            .name
+            .file_name()
+            .map_or_else(|| "stdin".into(), |name| name.to_string_lossy());
+        let path = PathBuf::from(format!("{name}.html"));
+        self.message = Some(match buffer.export_html(&path, &self.options) {
+            Ok(()) => format!("Exported to {}", path.display()),
+            Err(err) => format!("{err:#}"),
+        });
+    }
+
+    fn open_url(&mut self) {
+        let pane = &mut self.panes[self.focus];
+        let top = pane.top_line();
+        let urls = pane
+            .buffer
+            .lines
+            .get(top)
+            .map(|line| find_urls(line))
+            .unwrap_or_default();
+        if urls.is_empty() {
+            self.message = Some(format!("No URL on line {}", top + 1));
+            return;
+        }
+
+        let index = match pane.last_url {
+            Some((line, index)) if line == top => (index + 1) % urls.len(),
+            _ => 0,
+        };
+        pane.last_url = Some((top, index));
+        let url = &urls[index];
+        self.message = Some(match open::that_detached(url) {
+            Ok(()) => format!("Opened {url}"),
+            Err(err) => format!("Could not open {url}: {err}"),
+        });
+    }
+
+    /// Rereads `path` after an edit into every pane showing it. In a diff
+    /// both sides are realigned, since the edit shifts rows on each.
+    fn reload(&mut self, path: &Path) -> Result<()> {
+        let mut names: Vec<&Path> = Vec::new();
+        for pane in &self.panes {
+            if !names.contains(&pane.buffer.name.as_path()) {
+                names.push(&pane.buffer.name);
+            }
+        }
+        let diffed = self.panes.iter().any(|pane| pane.buffer.diff.is_some());
+        let buffers = match names.as_slice() {
+            [old, new] if diffed => {
+                let old = load_buffer(old.to_path_buf())?;
+                let new = load_buffer(new.to_path_buf())?;
+                let (left, right) = align_diff(&old.lines, &new.lines);
+                vec![
+                    Buffer::with_diff(old.name, left),
+                    Buffer::with_diff(new.name, right),
+                ]
+            }
+            _ => vec![load_buffer(path.to_path_buf())?],
+        };
+
+        for buffer in buffers {
+            let buffer = Rc::new(buffer);
+            for pane in &mut self.panes {
+                if pane.buffer.name == buffer.name {
+                    pane.buffer = Rc::clone(&buffer);
+                    pane.minimap = None;
+                    pane.refresh_matches();
+                    pane.clamp();
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Editors known to open at line N when given `+N`.
+const LINE_ARG_EDITORS: [&str; 14] = [
+    "vi",
+    "vim",
+    "nvim",
+    "gvim",
+    "view",
+    "nano",
+    "pico",
+    "emacs",
+    "emacsclient",
+    "micro",
+    "kak",
+    "joe",
+    "jed",
+    "ne",
+];
+
+/// The user's editor split into program and arguments, from `$VISUAL` or
+/// else `$EDITOR`.
+fn editor_command() -> Option<Vec<String>> {
+    ["VISUAL", "EDITOR"]
+        .into_iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .map(|value| {
+            value
+                .split_whitespace()
+                .map(String::from)
+                .collect::<Vec<_>>()
+        })
+        .find(|words| !words.is_empty())
+}
+
+/// Hands the terminal to the user's editor on the focused file, opened at
+/// the top line where the editor allows, then reloads whatever was saved.
+fn edit_focused_file<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> Result<()> {
+    let pane = &app.panes[app.focus];
+    let (path, line) = (pane.buffer.name.clone(), pane.top_line() + 1);
+    if !path.is_file() {
+        app.message = Some(format!("E: {} is not a file to edit", path.display()));
+        return Ok(());
+    }
+    let Some(command) = editor_command() else {
+        app.message = Some("E: set $VISUAL or $EDITOR to edit files".to_string());
+        return Ok(());
+    };
+    let (program, args) = (&command[0], &command[1..]);
+    let takes_line = Path::new(program)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .is_some_and(|stem| LINE_ARG_EDITORS.contains(&stem));
+
+    disable_raw_mode()?;
+    stdout().execute(LeaveAlternateScreen)?;
+    let status = Command::new(program)
+        .args(args)
+        .args(takes_line.then(|| format!("+{line}")))
+        .arg(&path)
+        .status();
+    enable_raw_mode()?;
+    stdout().execute(EnterAlternateScreen)?;
+    terminal.clear()?;
+
+    match status {
+        Ok(status) => {
+            if !status.success() {
+                app.message = Some(format!("{program} exited with {status}"));
+            }
+            if let Err(err) = app.reload(&path) {
+                app.message = Some(format!("{err:#}"));
+            }
+        }
+        Err(err) => app.message = Some(format!("E: can't run {program}: {err}")),
+    }
+    Ok(())
+}
+
+fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> Result<()> {
+    // let theme = Theme::new(ThemeName::Dracula);
+    // let palette = theme.palette();
+
+    loop {
+        app.receive_loaded();
+        terminal.draw(|frame| {
+            app.draw(frame);
+            fit_colors(frame);
+        })?;
+
+        // Keep redrawing while a smooth scroll is in flight
+        if app.is_animating() && !event::poll(FRAME_INTERVAL)? {
+            continue;
+        }
+        // Keep the progress indicator moving while files load
+        if app.is_loading() && !event::poll(LOAD_POLL_INTERVAL)? {
+            continue;
+        }
+        // Count the rest of a search's matches while no key is waiting
+        if app.is_searching() && !event::poll(Duration::ZERO)? {
+            app.continue_searches();
+            continue;
+        }
+        if app.options.follow && !event::poll(FOLLOW_INTERVAL)? {
+            app.follow_files();
+            continue;
+        }
+
+        // A lone `g` that times out behaves like `gg`
+        if app.motion.pending_g && !event::poll(PENDING_G_TIMEOUT)? {
+            app.flush_pending_g();
+            continue;
+        }
+
+        let key = match event::read()? {
+            Event::Key(key) => key,
+            Event::Resize(width, height) => {
+                // Re-clamp immediately so a shrinking window keeps the bottom in view
+                terminal.autoresize()?;
+                app.resize(Rect::new(0, 0, width, height));
+                continue;
+            }
+            _ => continue,
+        };
+
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        if !app.handle_key(key) {
+            return Ok(());
+        }
+        if app.edit_pending {
+            app.edit_pending = false;
+            edit_focused_file(terminal, app)?;
+        }
+    }
+}
+
+/// Decodes file contents, stripping any byte-order mark. UTF-8 is tried
+/// first; anything else is decoded with the encoding `chardetng` guesses,
+/// replacing undecodable bytes rather than failing.
+fn decode_text(bytes: &[u8]) -> (String, &'static Encoding) {
+    if let Some((encoding, bom_len)) = Encoding::for_bom(bytes) {
+        let (text, _) = encoding.decode_without_bom_handling(&bytes[bom_len..]);
+        return (text.into_owned(), encoding);
+    }
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return (text.to_string(), UTF_8);
+    }
+
+    let mut detector = chardetng::EncodingDetector::new(chardetng::Iso2022JpDetection::Deny);
+    detector.feed(bytes, true);
+    let encoding = detector.guess(None, chardetng::Utf8Detection::Allow);
+    let (text, _) = encoding.decode_without_bom_handling(bytes);
+    (text.into_owned(), encoding)
+}
+
+/// Expands a leading `~` and `$VAR` / `${VAR}` references, since paths
+/// passed without a shell (or quoted) arrive unexpanded. An undefined
+/// variable is an error rather than a literal `$NAME` in the path.
+fn expand_path(path: &Path) -> Result<PathBuf> {
+    let Some(text) = path.to_str() else {
+        return Ok(path.to_path_buf());
+    };
+    let expanded = shellexpand::full(text)
+        .with_context(|| format!("Cannot expand path: {}", path.display()))?;
+    Ok(PathBuf::from(expanded.as_ref()))
+}
+
+/// Opens `path` and starts a thread streaming its lines, so the viewer can
+/// come up before a large file is fully read. UTF-8 is decoded as it
+/// arrives; anything else is decoded in one go once the file is read.
+fn load_in_background(path: PathBuf) -> Result<(Buffer, Receiver<Loaded>)> {
+    let pipe = is_fifo(&path);
+    // Opening a FIFO waits for a writer, so that happens on the loader thread
+    let file = if pipe {
+        None
+    } else {
+        let file = File::open(&path)
+            .with_context(|| format!("Failed to read file: {}", path.display()))?;
+        Some(file)
+    };
+    Ok(spawn_loader(path, file, pipe))
+}
+
+/// Streams piped standard input as it arrives, the way a named pipe is
+/// read, so `cmd | fp` shows output before `cmd` finishes. Keys still come
+/// from the terminal, which crossterm opens as `/dev/tty`. `None` where
+/// stdin can't be handed to a loader thread; it is read whole instead.
+#[cfg(unix)]
+fn stdin_in_background() -> Result<Option<(Buffer, Receiver<Loaded>)>> {
+    use std::os::fd::AsFd;
+    let fd = std::io::stdin()
+        .as_fd()
+        .try_clone_to_owned()
+        .context("Failed to read standard input")?;
+    let file = File::from(fd);
+    // `fp < file` redirects a regular file, whose size shows progress
+    let pipe = !file.metadata().is_ok_and(|metadata| metadata.is_file());
+    Ok(Some(spawn_loader(
+        PathBuf::from("<stdin>"),
+        Some(file),
+        pipe,
+    )))
+}
+
+#[cfg(not(unix))]
+fn stdin_in_background() -> Result<Option<(Buffer, Receiver<Loaded>)>> {
+    Ok(None)
+}
+
+/// Reads `path` (already open as `file`, unless it is a FIFO still to be
+/// opened) on a thread, sending lines back as they are read.
+fn spawn_loader(path: PathBuf, file: Option<File>, pipe: bool) -> (Buffer, Receiver<Loaded>) {
+    let (sender, receiver) = mpsc::channel();
+
+    let thread_path = path.clone();
+    std::thread::spawn(move || {
+        // A failed send means the viewer has quit; just stop reading
+        let result = file
+            .map_or_else(|| File::open(&thread_path), Ok)
+            .and_then(|mut file| {
+                let size = file.metadata().map_or(0, |metadata| metadata.len());
+                stream_lines(&mut file, size, pipe, &sender)
+            });
+        let last = match result {
+            Ok(()) => Loaded::Done,
+            Err(err) => Loaded::Failed(format!("Error reading file: {err}")),
+        };
+        let _ = sender.send(last);
+    });
+
+    let mut buffer = Buffer::new(path, Vec::new());
+    buffer.progress = Some(0);
+    buffer.pipe = pipe;
+    (buffer, receiver)
+}
+
+/// Whether `path` is a named pipe, read as lines arrive until the writer
+/// closes it.
+#[cfg(unix)]
+fn is_fifo(path: &Path) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    std::fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_fifo())
+}
+
+#[cfg(not(unix))]
+fn is_fifo(_path: &Path) -> bool {
+    false
+}
+
+/// Sends `file` to the viewer a chunk of whole lines at a time. A `pipe`
+/// can't be reread, so invalid UTF-8 met part-way through is replaced
+/// rather than redecoded.
+fn stream_lines(
+    file: &mut File,
+    size: u64,
+    pipe: bool,
+    sender: &Sender<Loaded>,
+) -> std::io::Result<()> {
+    let mut chunk = vec![0; LOAD_CHUNK_SIZE];
+    let mut pending = Vec::new();
+    let mut read = 0u64;
+    let mut first = true;
+    // Lines sent so far and the bytes they came from, to extrapolate a total
+    let (mut sent, mut sent_bytes) = (0usize, 0u64);
+
+    loop {
+        let len = file.read(&mut chunk)?;
+        read += len as u64;
+        pending.extend_from_slice(&chunk[..len]);
+        if first {
+            first = false;
+            match Encoding::for_bom(&pending) {
+                Some((encoding, bom_len)) if encoding == UTF_8 => {
+                    pending.drain(..bom_len);
+                }
+                // Everything read so far is still pending, so nothing is lost
+                Some(_) => return decode_whole_file(file, pending, sender),
+                None => {}
+            }
+        }
+
+        // Only complete lines are sent; the tail waits for the next chunk.
+        // At the end the tail is the last line, empty after a final newline
+        let (end, consumed) = if len == 0 {
+            (pending.len(), pending.len())
+        } else {
+            match pending.iter().rposition(|&byte| byte == b'\n') {
+                Some(newline) => (newline, newline + 1),
+                None => continue,
+            }
+        };
+        let text = match std::str::from_utf8(&pending[..end]) {
+            Ok(text) => Cow::Borrowed(text),
+            Err(_) if pipe => String::from_utf8_lossy(&pending[..end]),
+            Err(_) => {
+                file.rewind()?;
+                return decode_whole_file(file, Vec::new(), sender);
+            }
+        };
+        let lines = if read == 0 {
+            Vec::new()
+        } else {
+            text.split('\n').map(line_text).collect()
+        };
+        let endings = LineEndings::count(&pending[..consumed]);
+        pending.drain(..consumed);
+
+        sent += lines.len();
+        sent_bytes += consumed as u64;
+        let progress = (read * 100).checked_div(size).unwrap_or(100).min(100) as u8;
+        let estimate = match (sent as u64 * size).checked_div(sent_bytes) {
+            Some(estimate) if !pipe => estimate as usize,
+            _ => sent,
+        };
+        if sender
+            .send(Loaded::Lines(lines, endings, progress, estimate))
+            .is_err()
+            || len == 0
+        {
+            return Ok(());
+        }
+    }
+}
+
+/// Splits file contents into lines the way editors count them: text that
+/// ends in a newline has an empty last line, and only an empty file has no
+/// lines at all. `\r\n` endings are accepted.
+fn split_lines(text: &str) -> Vec<String> {
+    if text.is_empty() {
+        return Vec::new();
+    }
+    text.split('\n').map(line_text).collect()
+}
+
+fn line_text(line: &str) -> String {
+    line.strip_suffix('\r').unwrap_or(line).to_string()
+}
+
+/// Reads the rest of `file` after the `bytes` already taken from it and
+/// decodes the lot with the detected encoding.
+fn decode_whole_file(
+    file: &mut File,
+    mut bytes: Vec<u8>,
+    sender: &Sender<Loaded>,
+) -> std::io::Result<()> {
+    file.read_to_end(&mut bytes)?;
+    let (text, encoding) = decode_text(&bytes);
+    let lines = split_lines(&text);
+    let endings = LineEndings::count(text.as_bytes());
+    let _ = sender.send(Loaded::Decoded(lines, encoding, endings));
+    Ok(())
+}
+
+/// Whether the terminal has a light background: as it reports its
+/// background color when asked, else as it advertises through `COLORFGBG`
+/// (`fg;bg`, as set by rxvt, Konsole and others). Unknown means dark.
+fn terminal_is_light() -> bool {
+    if stdout().is_terminal()
+        && let Some(light) = query_background_is_light()
+    {
+        return light;
+    }
+    std::env::var("COLORFGBG")
+        .ok()
+        .and_then(|value| value.rsplit(';').next()?.parse::<u8>().ok())
+        .is_some_and(|background| matches!(background, 7 | 9..=15))
+}
+
+/// How long the terminal gets to report its background color.
+const BACKGROUND_QUERY_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Asks the terminal for its background color (OSC 11). `None` when it
+/// doesn't say in time or answers with something else.
+#[cfg(unix)]
+fn query_background_is_light() -> Option<bool> {
+    use std::os::fd::AsRawFd;
+    let mut tty = File::options()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .ok()?;
+    enable_raw_mode().ok()?;
+    let mut reply = Vec::new();
+    // Device attributes (DA1) go after, and every terminal answers those,
+    // so one that ignores OSC 11 is found out without waiting it out
+    let asked = tty
+        .write_all(b"\x1b]11;?\x1b\\\x1b[c")
+        .and_then(|()| tty.flush());
+    let deadline = Instant::now() + BACKGROUND_QUERY_TIMEOUT;
+    while asked.is_ok() && !has_device_attributes(&reply) {
+        let Some(left) = deadline.checked_duration_since(Instant::now()) else {
+            break;
+        };
+        let mut ready = libc::pollfd {
+            fd: tty.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: `ready` is one valid pollfd for the duration of the call
+        let polled = unsafe { libc::poll(&mut ready, 1, left.as_millis() as libc::c_int) };
+        let mut chunk = [0; 64];
+        match polled {
+            1.. => match tty.read(&mut chunk) {
+                Ok(len) if len > 0 => reply.extend_from_slice(&chunk[..len]),
+                _ => break,
+            },
+            _ => break,
+        }
+    }
+    let _ = disable_raw_mode();
+    background_is_light(&String::from_utf8_lossy(&reply))
+}
+
+#[cfg(not(unix))]
+fn query_background_is_light() -> Option<bool> {
+    None
+}
+
+/// Whether `reply` holds a whole DA1 answer, `ESC [ ? … c`.
+fn has_device_attributes(reply: &[u8]) -> bool {
+    reply
+        .windows(3)
+        .position(|start| start == b"\x1b[?")
+        .is_some_and(|start| reply[start..].contains(&b'c'))
+}
+
+/// Reads an OSC 11 answer, `ESC ] 11 ; rgb:RRRR/GGGG/BBBB` with one to four
+/// hex digits a channel, and weighs the color's brightness.
+fn background_is_light(reply: &str) -> Option<bool> {
+    let (_, color) = reply.split_once("]11;rgb:")?;
+    let end = color.find(['\x07', '\x1b']).unwrap_or(color.len());
+    let channels: Vec<f64> = color[..end]
+        .split('/')
+        .map(|channel| {
+            let value = u32::from_str_radix(channel, 16).ok()?;
+            let max = 16u32.checked_pow(channel.len() as u32)? - 1;
+            (1..=4)
+                .contains(&channel.len())
+                .then(|| f64::from(value) / f64::from(max))
+        })
+        .collect::<Option<_>>()?;
+    let [r, g, b] = channels[..] else {
+        return None;
+    };
+    Some(0.299 * r + 0.587 * g + 0.114 * b > 0.5)
+}
+
+/// Writes the files to `out` one after another, as `cat` would, with
+/// SGR escapes for their highlighting when `color`. The first file starts
+/// at `start`, where the viewer would open it: a line past its end is an
+/// error, and a `+/pattern` matching nothing prints all of it but gives
+/// `EXIT_NO_MATCH`.
+fn print_files(
+    out: &mut impl Write,
+    files: &[PathBuf],
+    options: &ViewOptions,
+    color: bool,
+    mut start: Option<StartCommand>,
+) -> Result<ExitCode> {
+    let depth = COLOR_DEPTH.get().copied().unwrap_or(ColorDepth::TrueColor);
+    let options = ViewOptions {
+        highlight: color,
+        ..options.clone()
+    };
+    let mut status = ExitCode::SUCCESS;
+    for path in files {
+        let buffer = Rc::new(load_buffer(path.clone())?);
+        let mut first = 0;
+        if let Some(start) = start.take() {
+            let total = buffer.lines.len();
+            if let StartCommand::Line(line) = start
+                && line > total.max(1)
+            {
+                anyhow::bail!(
+                    "Line {} is past the end of {} ({} lines)",
+                    grouped(line),
+                    buffer.name.display(),
+                    grouped(total)
+                );
+            }
+            let mut pane = Pane::new(Rc::clone(&buffer));
+            pane.visible = 1;
+            pane.apply_start(start, options.search_mode());
+            if pane.finds_nothing() {
+                status = ExitCode::from(EXIT_NO_MATCH);
+            }
+            first = pane.top_line();
+        }
+        for (index, line) in buffer.lines.iter().enumerate().skip(first) {
+            if index > first {
+                out.write_all(b"\n")?;
+            }
+            match color {
+                true => {
+                    let styled = buffer.styled_line(index, &options, None);
+                    out.write_all(to_ansi(&styled, depth).as_bytes())?;
+                }
+                false => out.write_all(line.as_bytes())?,
+            }
+        }
+    }
+    out.flush()?;
+    Ok(status)
+}
+
+/// Placeholder in the file list for standard input.
+const STDIN_ARG: &str = "-";
+
+/// Reads all of standard input into a buffer named `<stdin>`.
+fn read_stdin() -> Result<Buffer> {
+    let mut bytes = Vec::new();
+    std::io::stdin()
+        .read_to_end(&mut bytes)
+        .context("Failed to read standard input")?;
+    let (text, encoding) = decode_text(&bytes);
+    let lines = split_lines(&text);
+
+    let mut buffer = Buffer::new(PathBuf::from("<stdin>"), lines);
+    buffer.encoding = encoding;
+    buffer.endings = LineEndings::count(text.as_bytes());
+    Ok(buffer)
+}
+
+/// Reads `path` into lines for a new buffer.
+fn load_buffer(path: PathBuf) -> Result<Buffer> {
+    if path.as_os_str() == STDIN_ARG {
+        return read_stdin();
+    }
+    let bytes =
+        std::fs::read(&path).with_context(|| format!("Failed to read file: {}", path.display()))?;
+    let (text, encoding) = decode_text(&bytes);
+    let lines = split_lines(&text);
+
+    let mut buffer = Buffer::new(path, lines);
+    buffer.encoding = encoding;
+    buffer.endings = LineEndings::count(text.as_bytes());
+    Ok(buffer)
+}
+
+fn main() -> Result<ExitCode> {
+    let (cli_args, start_command) = split_start_command(std::env::args_os())?;
+    let config = Config::load()?;
+    let mut args = config.apply(Args::parse_from(&cli_args), &cli_args)?;
+    args.files = args
+        .files
+        .iter()
+        .map(|path| expand_path(path))
+        .collect::<Result<_>>()?;
+
+    if cfg!(not(feature = "syntect")) && args.accurate {
+        anyhow::bail!("--accurate needs fp built with the `syntect` feature");
+    }
+    if cfg!(not(feature = "tree-sitter")) && args.tree_sitter {
+        anyhow::bail!("--tree-sitter needs fp built with the `tree-sitter` feature");
+    }
+    let options = ViewOptions::from(&args);
+    // The crossterm ratatui draws with drops colors under NO_COLOR by itself
+    if args.color == ColorWhen::Always {
+        ratatui::crossterm::style::force_color_output(true);
+    }
+    let depth = args.color_depth.unwrap_or_else(|| {
+        let colorterm = std::env::var("COLORTERM").ok();
+        let term = std::env::var("TERM").ok();
+        ColorDepth::from_env(colorterm.as_deref(), term.as_deref())
+    });
+    let _ = COLOR_DEPTH.set(depth);
+    let _ = THEMES.set(load_themes()?);
+    match &args.theme {
+        Some(name) if !args.light && !args.dark => {
+            if !set_theme(name) {
+                let known: Vec<&str> = themes().iter().map(|(name, _)| name.as_str()).collect();
+                anyhow::bail!("No theme called `{name}`; there are {}", known.join(", "));
+            }
+        }
+        _ => {
+            let light = args.light || (!args.dark && terminal_is_light());
+            set_theme(if light { "solarized-light" } else { "dracula" });
+        }
+    }
+
+    let mut history = History::load();
+    let mut start_command = start_command;
+    // `fp file | head` and scripts get the text rather than a pager
+    let printing = args.print || !stdout().is_terminal();
+    // `cmd | fp` pages the output, like less
+    if args.files.is_empty() && !std::io::stdin().is_terminal() {
+        args.files.push(PathBuf::from(STDIN_ARG));
+    }
+    if args.files.is_empty() {
+        if history.entries.is_empty() || printing {
+            anyhow::bail!("No file given, and no recently viewed files to pick from");
+        }
+        let Some(chosen) = run_picker(history.picker(), options.highlight)? else {
+            return Ok(ExitCode::SUCCESS);
+        };
+        // Reopen where it was left unless told otherwise
+        if let Some(line) = chosen.line.filter(|_| start_command.is_none()) {
+            start_command = Some(StartCommand::Line(line + 1));
+        }
+        args.files.push(chosen.path);
+    }
+
+    if args.files.len() > 1 && !args.split && !args.diff && !printing {
+        anyhow::bail!(
+            "Viewing several files at once (`-` for stdin among them) needs --split or --diff"
+        );
+    }
+
+    if args.diff && args.files.len() != 2 {
+        anyhow::bail!("--diff needs exactly two files");
+    }
+
+    let stdin_args = args
+        .files
+        .iter()
+        .filter(|path| path.as_os_str() == STDIN_ARG)
+        .count();
+    if stdin_args > 1 {
+        anyhow::bail!("Standard input (`-`) can only be read once");
+    }
+
+    // Offer a typo'd file's neighbours, unless scripted or told not to
+    if !args.no_picker && !printing {
+        for path in &mut args.files {
+            if path.as_os_str() != STDIN_ARG && !path.exists() {
+                *path = pick_missing_file(path, options.highlight)?;
+            }
+        }
+    }
+
+    if let Some(path) = &args.export {
+        let buffer = load_buffer(args.files[0].clone())?;
+        buffer.export_html(path, &options)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let start = start_command
+        .or_else(|| args.start_line.map(StartCommand::Line))
+        .or_else(|| args.start_percent.map(StartCommand::Percent))
+        .or_else(|| args.byte_offset.map(StartCommand::ByteOffset));
+
+    if printing {
+        let color =
+            options.highlight && (args.color == ColorWhen::Always || stdout().is_terminal());
+        let mut out = std::io::BufWriter::new(stdout().lock());
+        return match print_files(&mut out, &args.files, &options, color, start) {
+            // The reader has all it wants, as with `fp file | head`
+            Err(err)
+                if err
+                    .downcast_ref::<std::io::Error>()
+                    .is_some_and(|err| err.kind() == std::io::ErrorKind::BrokenPipe) =>
+            {
+                Ok(ExitCode::SUCCESS)
+            }
+            result => result,
+        };
+    }
+
+    let mut panes = Vec::new();
+    let mut loaders = Vec::new();
+    if args.diff {
+        let old = load_buffer(args.files[0].clone())?;
+        let new = load_buffer(args.files[1].clone())?;
+        let (left, right) = align_diff(&old.lines, &new.lines);
+        panes.push(Pane::new(Rc::new(Buffer::with_diff(old.name, left))));
+        panes.push(Pane::new(Rc::new(Buffer::with_diff(new.name, right))));
+    } else {
+        for path in &args.files {
+            let (buffer, receiver) = if path.as_os_str() == STDIN_ARG {
+                match stdin_in_background()? {
+                    Some(loading) => loading,
+                    None => {
+                        panes.push(Pane::new(Rc::new(read_stdin()?)));
+                        continue;
+                    }
+                }
+            } else {
+                load_in_background(path.clone())?
+            };
+            let buffer = Rc::new(buffer);
+            loaders.push(Loader {
+                buffer: buffer.id,
+                receiver,
+            });
+            panes.push(Pane::new(buffer));
+        }
+    }
+    // `--split` on a single file opens two views of it
+    if args.split && panes.len() == 1 {
+        panes.push(panes[0].split());
+    }
+    for pane in &mut panes {
+        pane.header = options.freeze_header;
+        pane.set_squeeze(options.squeeze);
+    }
+
+    // Background loads apply the start position once the file is in
+    let mut start_note = None;
+    let mut start_missed = false;
+    let start = match start {
+        Some(start) if loaders.is_empty() => {
+            let searching = matches!(start, StartCommand::Search(_));
+            start_note = panes[0].apply_start(start, options.search_mode());
+            start_missed = searching && panes[0].finds_nothing();
+            if args.diff {
+                panes[1].scroll = panes[0].scroll;
+            }
+            None
+        }
+        start => start,
+    };
+
+    enable_raw_mode()?;
+    stdout().execute(EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
+
+    let mut app = App::new(panes, args.lines.map(usize::from), options);
+    app.linked = args.diff;
+    app.scrolloff = args.scrolloff;
+    app.page_overlap = args.page_overlap;
+    app.loaders = loaders;
+    app.pending_start = start;
+    app.message = start_note;
+    app.start_missed = start_missed;
+    app.keymap = config.keymap;
+    app.confirm_quit = args.confirm_quit;
+    app.esc_quits = !args.no_esc_quit;
+    app.smooth = args
+        .smooth
+        .then(|| Duration::from_millis(args.smooth_ms))
+        .filter(|duration| !duration.is_zero());
+    let res = run_app(&mut terminal, &mut app);
+
+    disable_raw_mode()?;
+    stdout().execute(LeaveAlternateScreen)?;
+
+    // The focused pane goes last so it heads the recent-files list
+    let focused = app.panes.get(app.focus).into_iter();
+    for pane in app.panes.iter().chain(focused) {
+        if let Ok(path) = pane.buffer.name.canonicalize() {
+            history.record(path, pane.top_line());
+        }
+    }
+    // History is a convenience; failing to save it shouldn't fail the run
+    let _ = history.save();
+
+    res?;
+    Ok(if app.start_missed {
+        ExitCode::from(EXIT_NO_MATCH)
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Display options as `fp` would set them from `args`, with colors on
+    /// whatever `NO_COLOR` says.
+    fn options(args: &[&str]) -> ViewOptions {
+        let args = Args::parse_from(std::iter::once("fp").chain(args.iter().copied()));
+        ViewOptions {
+            highlight: true,
+            ..ViewOptions::from(&args)
+        }
+    }
+
+    fn lines(text: &[&str]) -> Vec<String> {
+        text.iter().map(|line| line.to_string()).collect()
+    }
+
+    /// The pieces of `line` carrying the `*` reference background.
+    fn references(line: &Line<'_>) -> Vec<String> {
+        line.spans
+            .iter()
+            .filter(|span| span.style.bg == Some(theme().selection))
+            .map(|span| span.content.to_string())
+            .collect()
+    }
+
+    /// A pane onto `count` lines numbered from 1, `visible` rows tall.
+    fn pane(count: usize, visible: usize) -> Pane {
+        let text = (1..=count).map(|number| number.to_string()).collect();
+        let mut pane = Pane::new(Rc::new(Buffer::new(PathBuf::from("lines.txt"), text)));
+        pane.visible = visible;
+        pane
+    }
+
+    /// `fp` showing `count` numbered lines in one pane `visible` rows tall.
+    fn app(count: usize, visible: usize) -> App {
+        App::new(vec![pane(count, visible)], None, options(&[]))
+    }
+
+    /// Presses each character of `keys` in turn.
+    fn type_keys(app: &mut App, keys: &str) {
+        for c in keys.chars() {
+            app.handle_key(KeyEvent::from(KeyCode::Char(c)));
+        }
+    }
+
+    #[cfg(feature = "syntect")]
+    #[test]
+    fn references_are_found_inside_grammar_spans() {
+        let buffer = Buffer::new(
+            PathBuf::from("count.rs"),
+            lines(&["    let count = 1;", "    count += counter(count);"]),
+        );
+        let options = options(&["--accurate"]);
+        let first = buffer.styled_line(0, &options, Some("count"));
+        let second = buffer.styled_line(1, &options, Some("count"));
+        assert_eq!(references(&first), ["count"]);
+        assert_eq!(references(&second), ["count", "count"]);
+    }
+
+    #[cfg(feature = "tree-sitter")]
+    #[test]
+    fn tree_sitter_colors_lines_by_the_whole_file() {
+        let buffer = Buffer::new(
+            PathBuf::from("comment.rs"),
+            lines(&["/* a comment", "fn inside() {}", "*/", "fn outside() {}"]),
+        );
+        let options = options(&["--tree-sitter", "--fast"]);
+        let colors = |index| -> Vec<_> {
+            let line = buffer.styled_line(index, &options, None);
+            line.spans.iter().map(|span| span.style.fg).collect()
+        };
+        assert!(
+            colors(1)
+                .iter()
+                .all(|&color| color == Some(theme().comment))
+        );
+        assert!(colors(3).contains(&Some(theme().keyword)));
+        assert!(!colors(3).contains(&Some(theme().comment)));
+    }
+
+    #[test]
+    fn page_down_and_bottom_agree_on_the_viewport() {
+        let options = options(&[]);
+        for (fixed_height, visible) in [(None, 21), (Some(10), 10)] {
+            let mut app = App::new(vec![pane(100, 0)], fixed_height, options.clone());
+            app.page_overlap = 2;
+            // The height comes from the last draw or resize, nowhere else
+            app.resize(Rect::new(0, 0, 40, 24));
+            assert_eq!(app.panes[0].visible, visible);
+            app.handle_key(KeyEvent::from(KeyCode::PageDown));
+            assert_eq!(app.panes[0].scroll, visible - 2);
+
+            for _ in 0..100 {
+                app.handle_key(KeyEvent::from(KeyCode::PageDown));
+            }
+            let paged = app.panes[0].scroll;
+            app.handle_key(KeyEvent::from(KeyCode::Char('g')));
+            app.handle_key(KeyEvent::from(KeyCode::Char('g')));
+            assert_eq!(app.panes[0].scroll, 0);
+            app.handle_key(KeyEvent::from(KeyCode::Char('G')));
+            assert_eq!(app.panes[0].scroll, paged);
+            assert_eq!(paged, max_scroll(100, visible));
+            assert_eq!(paged, 100 - visible);
+        }
+    }
+
+    #[test]
+    fn counts_repeat_motions() {
+        let mut app = app(100, 20);
+        type_keys(&mut app, "5j");
+        assert_eq!(app.panes[0].scroll, 5);
+        type_keys(&mut app, "gg");
+        assert_eq!(app.panes[0].scroll, 0);
+        type_keys(&mut app, "42G");
+        assert_eq!(app.panes[0].scroll, center_on(41, 20));
+        assert_eq!(app.motion.count, None);
+    }
+
+    #[test]
+    fn another_key_after_g_only_cancels_it() {
+        let mut app = app(100, 20);
+        type_keys(&mut app, "10j3g");
+        type_keys(&mut app, "j");
+        assert_eq!(app.panes[0].scroll, 10);
+        assert!(!app.motion.pending_g);
+        assert_eq!(app.motion.count, None);
+    }
+
+    #[test]
+    fn huge_counts_stop_once_motions_stop_moving() {
+        let mut app = app(100, 20);
+        let count = usize::MAX.to_string();
+        type_keys(&mut app, &count);
+        app.handle_key(KeyEvent::from(KeyCode::PageDown));
+        assert_eq!(app.panes[0].scroll, 80);
+        type_keys(&mut app, &count);
+        app.handle_key(KeyEvent::from(KeyCode::PageUp));
+        assert_eq!(app.panes[0].scroll, 0);
+        type_keys(&mut app, &count);
+        type_keys(&mut app, "}");
+        assert_eq!(app.panes[0].scroll, 80);
+
+        // Nineteen lines hold a 5; the count goes round them only once
+        let pane = &mut app.panes[0];
+        pane.search = Some(pane.search_for("5".to_string(), SearchMode::default()));
+        type_keys(&mut app, &count);
+        type_keys(&mut app, "n");
+        let search = app.panes[0].search.as_ref().unwrap();
+        assert_eq!(search.matches.len(), 19);
+        assert!(search.current.is_some());
+    }
+
+    #[test]
+    fn byte_offsets_count_line_endings_and_boms_as_stored() {
+        let crlf = b"one\r\ntwo\r\nthree\r\nfour\r\n";
+        let start_of = |text: &[u8], line: &[u8]| {
+            let start = text.windows(line.len()).position(|window| window == line);
+            start.unwrap()
+        };
+        let four = start_of(crlf, b"four");
+        assert_eq!(byte_offset_to_line(&crlf[..four], UTF_8), 4);
+        assert_eq!(byte_offset_to_line(&crlf[..four - 1], UTF_8), 3);
+        assert_eq!(byte_offset_to_line(&crlf[..0], UTF_8), 1);
+
+        let bom = b"\xef\xbb\xbfa\nb\n";
+        assert_eq!(byte_offset_to_line(&bom[..5], UTF_8), 2);
+        // UTF-16 puts a zero byte after each newline
+        let utf16 = b"\xff\xfea\x00\n\x00b\x00";
+        assert_eq!(byte_offset_to_line(&utf16[..5], UTF_8), 1);
+        assert_eq!(byte_offset_to_line(&utf16[..6], UTF_8), 2);
+    }
+
+    #[test]
+    fn byte_offsets_in_piped_text_use_its_line_endings() {
+        let mut buffer = Buffer::new(PathBuf::from(STDIN_ARG), lines(&["one", "two", "three"]));
+        buffer.endings = LineEndings::count(b"one\r\ntwo\r\nthree");
+        let mut pane = Pane::new(Rc::new(buffer));
+        pane.visible = 1;
+        pane.apply_start(StartCommand::ByteOffset(10), SearchMode::default());
+        assert_eq!(pane.top_line(), 2);
+        let note = pane.apply_start(StartCommand::ByteOffset(1000), SearchMode::default());
+        assert_eq!((pane.top_line(), note), (2, None));
+    }
+
+    #[test]
+    fn byte_offsets_are_read_from_the_file_on_disk() {
+        let path = std::env::temp_dir().join(format!("fp-offsets-{}.txt", std::process::id()));
+        std::fs::write(&path, "\u{feff}one\r\ntwo\r\nthree\r\nfour\r\n").unwrap();
+        let mut pane = Pane::new(Rc::new(load_buffer(path.clone()).unwrap()));
+        pane.visible = 1;
+        let mut top_line_at = |offset| {
+            pane.apply_start(StartCommand::ByteOffset(offset), SearchMode::default());
+            pane.top_line()
+        };
+        // The BOM, two CRLF lines and "three\r" are still on line 3
+        assert_eq!(top_line_at(3 + 5 + 5 + 6), 2);
+        assert_eq!(top_line_at(3 + 5 + 5 + 7), 3);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn scrolloff_keeps_context_around_the_visual_cursor() {
+        let mut tall = app(100, 10);
+        tall.scrolloff = Some(3);
+        type_keys(&mut tall, "V");
+        // Rows 0 to 6 leave three lines below the cursor without scrolling
+        type_keys(&mut tall, "6j");
+        assert_eq!(tall.panes[0].scroll, 0);
+        type_keys(&mut tall, "j");
+        assert_eq!(tall.panes[0].scroll, 1);
+        type_keys(&mut tall, "j");
+        assert_eq!(tall.panes[0].scroll, 2);
+
+        // Back up, the view follows once the cursor is three from the top
+        type_keys(&mut tall, "3k");
+        assert_eq!(tall.panes[0].scroll, 2);
+        type_keys(&mut tall, "k");
+        assert_eq!(tall.panes[0].scroll, 1);
+
+        // A margin wider than half the screen shrinks to fit
+        let mut short = app(100, 4);
+        short.scrolloff = Some(3);
+        type_keys(&mut short, "V3j");
+        assert_eq!(short.panes[0].scroll, 1);
+    }
+
+    #[test]
+    fn apostrophes_in_prose_are_not_strings() {
+        let options = options(&["--fast"]);
+        let strings = |name: &str, text: &str| -> Vec<String> {
+            let buffer = Buffer::new(PathBuf::from(name), lines(&[text]));
+            let line = buffer.styled_line(0, &options, None);
+            let spans = line
+                .spans
+                .iter()
+                .filter(|span| span.style.fg == Some(theme().string));
+            spans.map(|span| span.content.to_string()).collect()
+        };
+        assert!(strings("notes.txt", "don't stop here").is_empty());
+        assert!(strings("README.md", "It's the user's choice, isn't it?").is_empty());
+        assert_eq!(strings("char.rs", "let x = 'a';"), ["'a'"]);
+        assert!(strings("lifetime.rs", "fn f<'a>(x: &'a str) {}").is_empty());
+    }
+
+    #[test]
+    fn loaded_lines_go_to_the_buffer_they_were_read_for() {
+        let mut app = app(10, 5);
+        let target = app.panes[0].buffer.id;
+        let more = Loaded::Lines(lines(&["11", "12"]), LineEndings::default(), 50, 20);
+        app.apply_loaded(target, more).unwrap();
+        assert_eq!(app.panes[0].buffer.lines.len(), 12);
+
+        // A reload replaces the buffer; the old load's lines go nowhere
+        let old = target;
+        app.panes[0].buffer = Rc::new(Buffer::new(PathBuf::from("lines.txt"), lines(&["new"])));
+        let stale = Loaded::Lines(lines(&["13"]), LineEndings::default(), 60, 20);
+        app.apply_loaded(old, stale).unwrap();
+        assert_eq!(app.panes[0].buffer.lines, ["new"]);
+    }
+
+    #[test]
+    fn a_buffer_that_cannot_take_loaded_lines_is_an_error() {
+        let mut app = app(10, 5);
+        let held = Rc::clone(&app.panes[0].buffer);
+        let more = Loaded::Lines(lines(&["11"]), LineEndings::default(), 50, 20);
+        assert!(app.apply_loaded(held.id, more).is_err());
+        assert!(Rc::ptr_eq(&app.panes[0].buffer, &held));
+    }
+
+    #[test]
+    fn the_start_position_waits_for_the_load_unless_scrolled() {
+        let mut untouched = app(100, 10);
+        let id = untouched.panes[0].buffer.id;
+        untouched.pending_start = Some(StartCommand::Line(51));
+        untouched.apply_loaded(id, Loaded::Done).unwrap();
+        assert_eq!(untouched.panes[0].top_line(), 50);
+
+        let mut scrolled = app(100, 10);
+        let id = scrolled.panes[0].buffer.id;
+        scrolled.pending_start = Some(StartCommand::Line(51));
+        type_keys(&mut scrolled, "3j");
+        scrolled.apply_loaded(id, Loaded::Done).unwrap();
+        assert_eq!(scrolled.panes[0].top_line(), 3);
+    }
+
+    #[test]
+    fn searches_stop_counting_past_the_cap_until_needed() {
+        let text: Vec<String> = (0..200_000).map(|n| format!("match {n}")).collect();
+        let mut search = Search::new("match".to_string(), SearchMode::default());
+        search.start(&text);
+        assert_eq!(search.matches.len(), MAX_MATCH_COUNT + 1);
+        assert_eq!(search.scanned, MAX_MATCH_COUNT + 1);
+        assert_eq!(search.count_label(), format!("[0/>{MAX_MATCH_COUNT}]"));
+
+        // Jumps look on as far as they need
+        assert_eq!(search.next_after(5_000, &text), Some(5_001));
+        assert_eq!(search.scanned, 5_002);
+        assert_eq!(search.prev_before(0, &text), Some(199_999));
+        assert!(search.is_complete(&text));
+    }
+
+    #[test]
+    fn searches_finish_counting_between_keypresses() {
+        let mut app = app(3, 3);
+        let text: Vec<String> = (0..250_000).map(|n| format!("line {n}")).collect();
+        app.panes[0].buffer = Rc::new(Buffer::new(PathBuf::from("long.txt"), text));
+        let pane = &mut app.panes[0];
+        pane.search = Some(pane.search_for("line".to_string(), SearchMode::default()));
+        assert!(app.is_searching());
+        while app.is_searching() {
+            app.continue_searches();
+        }
+        assert_eq!(app.panes[0].search.as_ref().unwrap().matches.len(), 250_000);
+    }
+
+    #[test]
+    fn printing_reports_missing_files_and_start_positions() {
+        let path = std::env::temp_dir().join(format!("fp-print-{}.txt", std::process::id()));
+        std::fs::write(&path, "one\ntwo\nthree\n").unwrap();
+        let options = options(&[]);
+        let print = |file: &Path, start| {
+            let mut out = Vec::new();
+            let status = print_files(&mut out, &[file.to_path_buf()], &options, false, start);
+            status.map(|status| (status, String::from_utf8(out).unwrap()))
+        };
+
+        let from_two = print(&path, Some(StartCommand::Line(2))).unwrap();
+        assert_eq!(from_two, (ExitCode::SUCCESS, "two\nthree\n".to_string()));
+        let found = print(&path, Some(StartCommand::Search("thr".to_string()))).unwrap();
+        assert_eq!(found, (ExitCode::SUCCESS, "three\n".to_string()));
+        // All of the file, but the search's miss shows in the status
+        let missed = print(&path, Some(StartCommand::Search("four".to_string()))).unwrap();
+        let no_match = ExitCode::from(EXIT_NO_MATCH);
+        assert_eq!(missed, (no_match, "one\ntwo\nthree\n".to_string()));
+
+        assert!(print(&path, Some(StartCommand::Line(9))).is_err());
+        std::fs::remove_file(&path).unwrap();
+        assert!(print(&path, None).is_err());
+    }
+
+    #[test]
+    fn the_status_line_always_shows_the_column() {
+        let mut pane = pane(100, 10);
+        let options = options(&[]);
+        assert_eq!(pane.position_label(&options), "Line 1-10 of 100, col 1");
+        pane.h_scroll = 10;
+        assert_eq!(pane.position_label(&options), "Line 1-10 of 100, col 11");
+    }
+
+    #[test]
+    fn paths_expand_home_and_variables() {
+        let home = std::env::home_dir().unwrap();
+        assert_eq!(
+            expand_path(Path::new("~/notes.txt")).unwrap(),
+            home.join("notes.txt")
+        );
+        assert_eq!(
+            expand_path(Path::new("$HOME/notes.txt")).unwrap(),
+            home.join("notes.txt")
+        );
+        assert_eq!(
+            expand_path(Path::new("plain.txt")).unwrap(),
+            Path::new("plain.txt")
+        );
+        let undefined = "$FP_TEST_NOPE/notes.txt";
+        assert!(expand_path(Path::new(undefined)).is_err());
+    }
+
+    fn split(args: &[&str]) -> (Vec<String>, Option<StartCommand>) {
+        let args = std::iter::once("fp")
+            .chain(args.iter().copied())
+            .map(Into::into);
+        let (rest, command) = split_start_command(args).unwrap();
+        let rest = rest
+            .into_iter()
+            .map(|arg| arg.into_string().unwrap())
+            .collect();
+        (rest, command)
+    }
+
+    #[test]
+    fn start_commands_come_only_from_positional_arguments() {
+        let (rest, command) = split(&["+42", "a.rs"]);
+        assert_eq!(rest, ["fp", "a.rs"]);
+        assert!(matches!(command, Some(StartCommand::Line(42))));
+
+        let (rest, command) = split(&["--comment-prefix", "+", "a.rs", "+/fn"]);
+        assert_eq!(rest, ["fp", "--comment-prefix", "+", "a.rs"]);
+        assert!(matches!(command, Some(StartCommand::Search(ref p)) if p == "fn"));
+
+        for args in [
+            &["--comment-prefix", "+1", "a.rs"][..],
+            &["-l", "+5", "a.rs"],
+            &["--split", "-l", "+5", "a.rs"],
+            &["--comment-prefix=+", "--", "+3"],
+        ] {
+            let (rest, command) = split(args);
+            assert_eq!(rest[1..], *args, "{args:?}");
+            assert!(command.is_none(), "{args:?}");
+        }
+
+        // Attached values leave the next argument positional
+        let (rest, command) = split(&["-l5", "+7", "--theme=x", "+8"]);
+        assert_eq!(rest, ["fp", "-l5", "--theme=x"]);
+        assert!(matches!(command, Some(StartCommand::Line(8))));
+    }
+
+    #[test]
+    fn overlong_lines_skip_highlighting() {
+        let minified = "let x = \"a\"; // b ".repeat(6_000);
+        assert!(minified.len() > 100_000);
+        let buffer = Buffer::new(PathBuf::from("min.rs"), vec![minified.clone()]);
+        let started = std::time::Instant::now();
+        let line = buffer.styled_line(0, &options(&[]), None);
+        assert!(started.elapsed() < Duration::from_millis(500));
+        assert_eq!(line.spans.len(), 1);
+        assert_eq!(line.spans[0].content, minified);
+        assert_eq!(line.style.fg, Some(theme().comment));
+
+        let short = Buffer::new(PathBuf::from("min.rs"), vec![minified[..190].to_string()]);
+        assert!(short.styled_line(0, &options(&[]), None).spans.len() > 1);
+    }
+
+    /// The lines `stream_lines` sends for a file holding `text`.
+    fn streamed(text: &str) -> Vec<String> {
+        let path = std::env::temp_dir().join(format!("fp-stream-{}.txt", std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let mut file = File::open(&path).unwrap();
+        stream_lines(&mut file, text.len() as u64, false, &sender).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        drop(sender);
+        receiver
+            .into_iter()
+            .flat_map(|loaded| match loaded {
+                Loaded::Lines(lines, ..) => lines,
+                _ => panic!("expected plain lines"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn line_counts_keep_a_final_newline() {
+        for (text, count) in [
+            ("", 0),
+            ("one", 1),
+            ("one\n", 2),
+            ("one\ntwo", 2),
+            ("one\r\ntwo\r\n", 3),
+            ("\n\n\n", 4),
+        ] {
+            assert_eq!(split_lines(text).len(), count, "{text:?}");
+            assert_eq!(streamed(text), split_lines(text), "{text:?}");
+        }
+        assert_eq!(split_lines("one\r\ntwo\r\n"), ["one", "two", ""]);
+
+        // A line straddling a chunk, and a newline ending a chunk exactly
+        let long = "x".repeat(LOAD_CHUNK_SIZE);
+        for text in [
+            format!("a\n{long}\nb"),
+            format!("a\n{long}\nb\n"),
+            format!("{}\n", &long[1..]),
+            format!("{}\nc", &long[1..]),
+        ] {
+            let lines = streamed(&text);
+            assert_eq!(lines, split_lines(&text));
+            assert_eq!(lines.len(), text.matches('\n').count() + 1);
+        }
+    }
+
+    #[test]
+    fn paragraph_motions_stop_at_blank_lines() {
+        // Blank lines at 2, 5 (whitespace only) and 6
+        let text = lines(&["a", "b", "", "c", "d", "  ", "", "e"]);
+        assert_eq!(paragraph_below(&text, 0), Some(2));
+        assert_eq!(paragraph_below(&text, 2), Some(5));
+        assert_eq!(paragraph_below(&text, 4), Some(5));
+        assert_eq!(paragraph_below(&text, 5), None);
+        assert_eq!(paragraph_below(&text, 7), None);
+        assert_eq!(paragraph_above(&text, 7), Some(6));
+        assert_eq!(paragraph_above(&text, 6), Some(2));
+        assert_eq!(paragraph_above(&text, 3), Some(2));
+        assert_eq!(paragraph_above(&text, 2), None);
+        assert_eq!(paragraph_above(&text, 100), Some(6));
+
+        let mut app = app(3, 2);
+        app.panes[0].buffer = Rc::new(Buffer::new(PathBuf::from("prose.txt"), text));
+        type_keys(&mut app, "}");
+        assert_eq!(app.panes[0].scroll, 2);
+        type_keys(&mut app, "}");
+        assert_eq!(app.panes[0].scroll, 5);
+        type_keys(&mut app, "{");
+        assert_eq!(app.panes[0].scroll, 2);
+    }
+
+    #[test]
+    fn viewport_fills_the_pane_unless_lines_is_shorter() {
+        assert_eq!(visible_height(40, None), 40);
+        assert_eq!(visible_height(40, Some(10)), 10);
+        // A terminal shorter than --lines can only show what fits
+        assert_eq!(visible_height(8, Some(70)), 8);
+        assert_eq!(visible_height(0, Some(10)), 0);
+        assert_eq!(visible_height(0, None), 0);
+    }
+
+    #[test]
+    fn lines_must_be_positive_and_start_lines_clamp() {
+        let error = Args::try_parse_from(["fp", "--lines", "0", "a.rs"]).unwrap_err();
+        assert_eq!(error.kind(), clap::error::ErrorKind::ValueValidation);
+        assert!(Args::try_parse_from(["fp", "--lines", "1", "a.rs"]).is_ok());
+
+        let mut pane = pane(100, 10);
+        let note = pane.apply_start(StartCommand::Line(5000), SearchMode::default());
+        assert_eq!(
+            note.as_deref(),
+            Some("Line 5,000 is past the end; showing line 100")
+        );
+        assert_eq!(pane.scroll, pane.max_scroll());
+        assert_eq!(
+            pane.apply_start(StartCommand::Line(100), SearchMode::default()),
+            None
+        );
+    }
+
+    /// A pane onto `count` numbered lines with `word` appended to the ones
+    /// at `hits`.
+    fn pane_with(count: usize, word: &str, hits: &[usize]) -> Pane {
+        let text = (0..count)
+            .map(|line| match hits.contains(&line) {
+                true => format!("{line} {word}"),
+                false => line.to_string(),
+            })
+            .collect();
+        let mut pane = Pane::new(Rc::new(Buffer::new(PathBuf::from(word), text)));
+        pane.visible = 5;
+        pane
+    }
+
+    fn search(app: &mut App, query: &str) {
+        type_keys(app, &format!("/{query}"));
+        app.handle_key(KeyEvent::from(KeyCode::Enter));
+    }
+
+    fn next_pane(app: &mut App) {
+        app.handle_key(KeyEvent::new(KeyCode::Char('w'), KeyModifiers::CONTROL));
+        type_keys(app, "w");
+    }
+
+    #[test]
+    fn each_pane_repeats_its_own_search() {
+        let panes = vec![
+            pane_with(100, "apple", &[10, 50, 90]),
+            pane_with(100, "pear", &[30, 70]),
+        ];
+        let mut app = App::new(panes, None, options(&[]));
+        search(&mut app, "apple");
+        assert_eq!(app.panes[0].top_line(), 10 - 2);
+        next_pane(&mut app);
+        search(&mut app, "pear");
+        assert_eq!(app.panes[1].top_line(), 30 - 2);
+
+        next_pane(&mut app);
+        type_keys(&mut app, "n");
+        assert_eq!(app.panes[0].top_line(), 50 - 2);
+        assert_eq!(app.panes[1].top_line(), 30 - 2);
+        next_pane(&mut app);
+        type_keys(&mut app, "n");
+        assert_eq!(app.panes[1].top_line(), 70 - 2);
+        assert_eq!(app.panes[0].top_line(), 50 - 2);
+
+        let queries = app
+            .panes
+            .iter()
+            .map(|pane| pane.search.as_ref().unwrap().query.as_str());
+        assert_eq!(queries.collect::<Vec<_>>(), ["apple", "pear"]);
+    }
+
+    #[test]
+    fn jumps_center_their_target() {
+        assert_eq!(center_on(50, 21), 40);
+        assert_eq!(center_on(50, 20), 40);
+        assert_eq!(center_on(3, 20), 0);
+        assert_eq!(scroll_to_target(50, 21, None), 40);
+        assert_eq!(scroll_to_target(50, 21, Some(3)), 47);
+        // A margin too big for the view shrinks to keep the target on screen
+        assert_eq!(scroll_to_target(50, 5, Some(10)), 48);
+
+        let mut pane = pane(100, 21);
+        pane.jump_to(50, None);
+        assert_eq!(pane.top_line() + 21 / 2, 50);
+        // The bottom of the file still clamps
+        pane.jump_to(98, None);
+        assert_eq!(pane.scroll, pane.max_scroll());
+    }
+
+    #[test]
+    fn n_moves_past_a_line_that_matches_twice() {
+        let mut pane = pane_with(20, "foo foo", &[2, 9, 15]);
+        pane.visible = 3;
+        let mut app = App::new(vec![pane], None, options(&[]));
+        let current = |app: &App| {
+            let search = app.panes[0].search.as_ref().unwrap();
+            search.matches[search.current.unwrap()]
+        };
+        search(&mut app, "foo");
+        assert_eq!(current(&app), 2);
+        assert_eq!(app.panes[0].search.as_ref().unwrap().matches, [2, 9, 15]);
+        for expected in [9, 15, 2, 9] {
+            type_keys(&mut app, "n");
+            assert_eq!(current(&app), expected);
+            assert_eq!(app.panes[0].top_line(), expected - 1);
+        }
+        for expected in [2, 15, 9] {
+            type_keys(&mut app, "N");
+            assert_eq!(current(&app), expected);
+        }
+    }
+
+    #[test]
+    fn line_end_shows_the_end_of_the_widest_visible_line() {
+        let wide = format!("{}日本語", "x".repeat(80));
+        let hidden = "y".repeat(200);
+        let text = lines(&["short", &wide, "", "", hidden.as_str()]);
+        let mut pane = Pane::new(Rc::new(Buffer::new(PathBuf::from("wide.txt"), text)));
+        pane.visible = 3;
+        pane.text_width = 30;
+        let mut app = App::new(vec![pane], None, options(&[]));
+        type_keys(&mut app, "$");
+        assert_eq!(app.panes[0].h_scroll, 86 - 30);
+        type_keys(&mut app, "0");
+        assert_eq!(app.panes[0].h_scroll, 0);
+    }
+
+    #[test]
+    fn modes_format_like_ls() {
+        assert_eq!(mode_string(0o100_644), "-rw-r--r--");
+        assert_eq!(mode_string(0o100_755), "-rwxr-xr-x");
+        assert_eq!(mode_string(0o040_700), "drwx------");
+        assert_eq!(mode_string(0o120_777), "lrwxrwxrwx");
+        assert_eq!(mode_string(0o104_755), "-rwsr-xr-x");
+        assert_eq!(mode_string(0o102_640), "-rw-r-S---");
+        assert_eq!(mode_string(0o041_777), "drwxrwxrwt");
+    }
+
+    /// What `app` draws on a `width` x `height` screen.
+    fn drawn(app: &mut App, width: u16, height: u16) -> ratatui::buffer::Buffer {
+        let mut terminal =
+            Terminal::new(ratatui::backend::TestBackend::new(width, height)).unwrap();
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+        terminal.backend().buffer().clone()
+    }
+
+    /// The rows `app` draws on a `width` x `height` screen.
+    fn screen(app: &mut App, width: u16, height: u16) -> Vec<String> {
+        let buffer = drawn(app, width, height);
+        (0..height)
+            .map(|y| (0..width).map(|x| buffer[(x, y)].symbol()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn a_frozen_header_stays_on_screen_at_the_bottom() {
+        let mut app = App::new(vec![pane(100, 10)], None, options(&["--freeze-header"]));
+        let numbers = |rows: &[String]| -> Vec<String> {
+            rows[1..rows.len() - 2]
+                .iter()
+                .map(|row| {
+                    row.trim_start_matches('│')
+                        .split_whitespace()
+                        .next()
+                        .unwrap()
+                        .to_string()
+                })
+                .collect()
+        };
+        let rows = screen(&mut app, 30, 12);
+        assert_eq!(
+            numbers(&rows),
+            ["1", "2", "3", "4", "5", "6", "7", "8", "9"]
+        );
+        type_keys(&mut app, "G");
+        let rows = screen(&mut app, 30, 12);
+        assert_eq!(
+            numbers(&rows),
+            ["1", "93", "94", "95", "96", "97", "98", "99", "100"]
+        );
+    }
+
+    #[test]
+    fn visual_selections_span_anchor_to_cursor_either_way() {
+        let selection = |anchor, cursor| Visual { anchor, cursor }.lines();
+        assert_eq!(selection(4, 9), 4..=9);
+        assert_eq!(selection(9, 4), 4..=9);
+        assert_eq!(selection(4, 4), 4..=4);
+
+        let mut pane = pane(20, 5);
+        pane.scroll = 5;
+        pane.visual = Some(Visual {
+            anchor: 7,
+            cursor: 7,
+        });
+        let selected = |pane: &Pane| pane.visual.unwrap().lines();
+        pane.move_visual(true, 3, None);
+        assert_eq!(selected(&pane), 7..=10);
+        // Back past the anchor, the selection flips to run up from it
+        pane.move_visual(false, 5, None);
+        assert_eq!(selected(&pane), 5..=7);
+        pane.move_visual(false, 100, None);
+        assert_eq!((selected(&pane), pane.scroll), (0..=7, 0));
+        pane.move_visual(true, 100, None);
+        assert_eq!(selected(&pane), 7..=19);
+        assert_eq!(pane.scroll, pane.max_scroll());
+    }
+
+    #[test]
+    fn focus_mode_dims_lines_without_a_match() {
+        let mut app = App::new(vec![pane_with(20, "apple", &[3])], None, options(&[]));
+        drawn(&mut app, 30, 12);
+        search(&mut app, "apple");
+        type_keys(&mut app, "F");
+        let screen = drawn(&mut app, 30, 12);
+        // Row 0 is the border, so line `first` is on row 1
+        let first = app.panes[0].top_line();
+        let row_of = |line: usize| (line - first + 1) as u16;
+        let fg = |line| screen[(1, row_of(line))].fg;
+        assert_eq!(fg(first), theme().comment);
+        assert_eq!(fg(first + 1), theme().comment);
+        assert_ne!(fg(3), theme().comment);
+
+        type_keys(&mut app, "F");
+        let screen = drawn(&mut app, 30, 12);
+        assert_ne!(screen[(1, row_of(first))].fg, theme().comment);
+    }
+
+    #[test]
+    fn squeezing_keeps_one_blank_line_and_true_numbers() {
+        let text = lines(&["a", "", "", " ", "", "", "b", "c"]);
+        let filter = Filter::new(String::new(), true, &text);
+        assert_eq!(filter.lines, [0, 1, 6, 7]);
+
+        let pane = Pane::new(Rc::new(Buffer::new(PathBuf::from("blank.txt"), text)));
+        let options = options(&["--squeeze", "--line-numbers"]);
+        let mut app = App::new(vec![pane], None, options);
+        let rows = screen(&mut app, 20, 12);
+        let words = |row: &String| -> Vec<String> {
+            row.split(['│', ' '])
+                .filter(|word| !word.is_empty())
+                .map(String::from)
+                .collect()
+        };
+        let gutter: Vec<_> = rows[1..6].iter().map(words).collect();
+        assert_eq!(
+            gutter,
+            [
+                vec!["1", "a"],
+                vec!["2"],
+                vec!["7", "b"],
+                vec!["8", "c"],
+                vec![]
+            ]
+        );
+        assert_eq!(app.panes[0].total_lines(), 4);
+    }
+
+    #[test]
+    fn indent_guides_mark_each_tab_stop() {
+        let guides = |line: &str, tab_width| {
+            let line = highlight_with_guides(line, Language::Rust, tab_width, &[]);
+            let span = &line.spans[0];
+            assert_eq!(span.style.fg, Some(theme().comment));
+            let chars = span.content.chars().enumerate();
+            chars
+                .filter(|&(_, c)| c == '│')
+                .map(|(column, _)| column)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(guides("        let x = 1;", 4), [0, 4]);
+        assert_eq!(guides("        let x = 1;", 2), [0, 2, 4, 6]);
+        assert_eq!(guides("\t  x", 4), [0, 4]);
+
+        // Guides replace only the indentation, keeping content columns
+        let line = highlight_with_guides("        let x = 1;", Language::Rust, 4, &[]);
+        assert_eq!(line.width(), 18);
+        assert!(line.to_string().starts_with("│   │   let"));
+        let unindented = highlight_with_guides("let x = 1;", Language::Rust, 4, &[]);
+        assert!(!unindented.to_string().contains('│'));
+    }
+
+    #[test]
+    fn j_stops_with_the_last_line_at_the_bottom() {
+        let mut tall = app(30, 10);
+        type_keys(&mut tall, &"j".repeat(50));
+        assert_eq!(tall.panes[0].scroll, 20);
+        tall.panes[0].line_down(usize::MAX);
+        assert_eq!(tall.panes[0].scroll, 20);
+        type_keys(&mut tall, "k");
+        assert_eq!(tall.panes[0].scroll, 19);
+
+        // A file shorter than the screen never scrolls
+        let mut short = app(5, 10);
+        type_keys(&mut short, "jjj");
+        assert_eq!(short.panes[0].scroll, 0);
+    }
+
+    #[test]
+    fn star_marks_every_occurrence_of_the_word() {
+        let text = lines(&["count = count + 1", "print(count, counter, count)"]);
+        let pane = Pane::new(Rc::new(Buffer::new(PathBuf::from("count.py"), text)));
+        let mut app = App::new(vec![pane], None, options(&[]));
+        type_keys(&mut app, "*");
+        assert_eq!(app.reference.as_deref(), Some("count"));
+
+        let buffer = &app.panes[0].buffer;
+        let spans: Vec<String> = (0..2)
+            .flat_map(|index| {
+                references(&buffer.styled_line(index, &app.options, app.reference.as_deref()))
+            })
+            .collect();
+        assert_eq!(spans, ["count"; 4]);
+
+        type_keys(&mut app, "*");
+        assert_eq!(app.reference, None);
+    }
+
+    #[test]
+    fn percentages_map_to_lines() {
+        assert_eq!(percent_to_line(0, 101), 1);
+        assert_eq!(percent_to_line(50, 101), 51);
+        assert_eq!(percent_to_line(100, 101), 101);
+        assert_eq!(percent_to_line(33, 10), 3);
+        assert_eq!(percent_to_line(200, 10), 10);
+        assert_eq!(percent_to_line(50, 0), 1);
+
+        let mut pane = pane(101, 10);
+        pane.apply_start(StartCommand::Percent(50), SearchMode::default());
+        assert_eq!(pane.top_line(), 50);
+        let both = Args::try_parse_from(["fp", "--start-line", "3", "--start-percent", "50"]);
+        assert_eq!(
+            both.unwrap_err().kind(),
+            clap::error::ErrorKind::ArgumentConflict
+        );
+        let over = Args::try_parse_from(["fp", "--start-percent", "101"]);
+        assert_eq!(
+            over.unwrap_err().kind(),
+            clap::error::ErrorKind::ValueValidation
+        );
+    }
+
+    #[test]
+    fn empty_and_blank_files_open_in_the_viewer() {
+        let path = std::env::temp_dir().join(format!("fp-blank-{}.txt", std::process::id()));
+        let name = path.file_name().unwrap().to_str().unwrap().to_string();
+        for (text, lines, empty) in [("", 0, true), ("\n", 2, false), ("  \t \n", 2, false)] {
+            std::fs::write(&path, text).unwrap();
+            let buffer = load_buffer(path.clone()).unwrap();
+            assert_eq!(buffer.lines.len(), lines, "{text:?}");
+            let mut app = App::new(vec![Pane::new(Rc::new(buffer))], None, options(&[]));
+            // Moving about a view with nothing to scroll is harmless
+            screen(&mut app, 40, 8);
+            type_keys(&mut app, "jGk}");
+            assert_eq!(app.panes[0].scroll, 0);
+            let rows = screen(&mut app, 40, 8);
+            assert!(rows[0].contains(&name), "{text:?}");
+            assert_eq!(rows[7].contains("[empty]"), empty, "{text:?}");
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn plain_lines_are_one_default_span() {
+        let buffer = Buffer::new(
+            PathBuf::from("main.rs"),
+            lines(&["pub fn main() { return; }"]),
+        );
+        for args in [&["--no-highlight"][..], &["--color", "never"]] {
+            let args = Args::parse_from(std::iter::once("fp").chain(args.iter().copied()));
+            let options = ViewOptions::from(&args);
+            assert!(!options.highlight);
+            let line = buffer.styled_line(0, &options, Some("main"));
+            assert_eq!(line.spans.len(), 1);
+            assert_eq!(line.spans[0].content, "pub fn main() { return; }");
+            assert_eq!(line.spans[0].style, Style::default());
+            assert_eq!(line.style, Style::default());
+        }
+        assert!(buffer.styled_line(0, &options(&[]), None).spans.len() > 1);
+    }
+
+    #[test]
+    fn boms_are_stripped_and_latin1_is_decoded() {
+        let (text, encoding) = decode_text("\u{feff}first\nsecond".as_bytes());
+        assert_eq!((text.as_str(), encoding), ("first\nsecond", UTF_8));
+
+        let utf16: Vec<u8> = [0xff, 0xfe]
+            .into_iter()
+            .chain("hi\n".encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        let (text, encoding) = decode_text(&utf16);
+        assert_eq!((text.as_str(), encoding), ("hi\n", encoding_rs::UTF_16LE));
+
+        let latin1 = b"caf\xe9 cr\xe8me br\xfbl\xe9e, na\xefve fa\xe7ade\n";
+        let (text, encoding) = decode_text(latin1);
+        assert_eq!(text, "café crème brûlée, naïve façade\n");
+        assert_eq!(encoding, encoding_rs::WINDOWS_1252);
+    }
+
+    #[test]
+    fn lines_past_the_right_edge_are_marked() {
+        let long = "x".repeat(50);
+        let text = lines(&["short", &long, &"y".repeat(28)]);
+        let pane = Pane::new(Rc::new(Buffer::new(PathBuf::from("wide.txt"), text)));
+        let mut app = App::new(vec![pane], None, options(&[]));
+        let marked = |rows: &[String]| -> Vec<bool> {
+            rows[1..4]
+                .iter()
+                .map(|row| row.contains(TRUNCATION_MARKER))
+                .collect()
+        };
+        let rows = screen(&mut app, 30, 8);
+        assert_eq!(marked(&rows), [false, true, false]);
+        assert!(rows[2].trim_end_matches('│').ends_with(TRUNCATION_MARKER));
+        // Scrolled to the end, the whole of the long line is in view
+        type_keys(&mut app, "$");
+        let rows = screen(&mut app, 30, 8);
+        assert_eq!(marked(&rows), [false, false, false]);
+    }
+
+    #[test]
+    fn pages_keep_the_overlap_in_view() {
+        let page = |overlap: usize, key: KeyCode, from: usize| {
+            let mut paged = app(100, 10);
+            paged.page_overlap = overlap;
+            paged.panes[0].scroll = from;
+            paged.handle_key(KeyEvent::from(key));
+            paged.panes[0].scroll
+        };
+        // The last `overlap` lines of the old screen top the new one
+        assert_eq!(page(2, KeyCode::PageDown, 0), 8);
+        assert_eq!(page(0, KeyCode::PageDown, 0), 10);
+        assert_eq!(page(2, KeyCode::PageUp, 40), 32);
+        assert_eq!(page(0, KeyCode::PageUp, 40), 30);
+        // An overlap as big as the page still moves a line at a time
+        assert_eq!(page(10, KeyCode::PageDown, 0), 1);
+        assert_eq!(page(25, KeyCode::PageUp, 40), 39);
+        // And the ends still stop the page
+        assert_eq!(page(2, KeyCode::PageDown, 85), 90);
+        assert_eq!(page(2, KeyCode::PageUp, 5), 0);
+
+        let args = Args::parse_from(["fp", "a.rs"]);
+        assert_eq!(args.page_overlap, 2);
+    }
+
+    #[test]
+    fn file_tables_set_flags_the_command_line_can_undo() {
+        let mut config = Config::default();
+        let text =
+            "line-numbers = true\n\n[files.\"*.log\"]\nfollow = true\nline-numbers = false\n";
+        config.read_settings(text).unwrap();
+        let configured = |typed: &[&str]| {
+            let cli_args: Vec<std::ffi::OsString> = std::iter::once("fp")
+                .chain(typed.iter().copied())
+                .map(Into::into)
+                .collect();
+            config
+                .apply(Args::parse_from(&cli_args), &cli_args)
+                .unwrap()
+        };
+
+        let log = configured(&["app.log"]);
+        assert!(log.follow);
+        assert!(!log.line_numbers);
+        let log = configured(&["app.log", "--no-follow", "--line-numbers"]);
+        assert!(!log.follow);
+        assert!(log.line_numbers);
+
+        let text = configured(&["notes.txt"]);
+        assert!(!text.follow);
+        assert!(text.line_numbers);
+    }
+
+    #[test]
+    fn comment_prefixes_may_start_with_a_hyphen() {
+        let args = Args::try_parse_from(["fp", "--comment-prefix", "--", "a.sql"]).unwrap();
+        assert_eq!(args.comment_prefix, ["--"]);
+        assert_eq!(args.files, [PathBuf::from("a.sql")]);
+
+        let args = Args::try_parse_from(["fp", "--comment-prefix", ";", "a.lisp"]).unwrap();
+        assert_eq!(args.comment_prefix, [";"]);
+        assert_eq!(args.files, [PathBuf::from("a.lisp")]);
+    }
+
+    #[test]
+    fn toml_comments_stop_at_quotes() {
+        assert_eq!(
+            strip_toml_comment("tab-width = 4 # spaces"),
+            "tab-width = 4 "
+        );
+        assert_eq!(strip_toml_comment("# all comment"), "");
+        assert_eq!(strip_toml_comment(r#"a = "x # y" # z"#), r#"a = "x # y" "#);
+        assert_eq!(strip_toml_comment("a = 'x # y'"), "a = 'x # y'");
+        assert_eq!(strip_toml_comment(r#"a = "x \" # y""#), r#"a = "x \" # y""#);
+    }
+
+    #[test]
+    fn toml_values_are_scalars_or_arrays() {
+        let value = |text: &str| toml_value(text);
+        let strings = |values: &[&str]| Some(values.iter().map(|v| v.to_string()).collect());
+        assert_eq!(value(" 4 "), strings(&["4"]));
+        assert_eq!(value("true"), strings(&["true"]));
+        assert_eq!(value(r#""a\tb\"c""#), strings(&["a\tb\"c"]));
+        // Single quotes take backslashes as they are
+        assert_eq!(value(r"'C:\temp'"), strings(&[r"C:\temp"]));
+        assert_eq!(value("[80, 100]"), strings(&["80", "100"]));
+        assert_eq!(value(r#"[ "q", 'esc', ]"#), strings(&["q", "esc"]));
+        assert_eq!(value("[]"), strings(&[]));
+
+        assert_eq!(value(""), None);
+        assert_eq!(value(r#""open"#), None);
+        assert_eq!(value(r#""a" "b""#), None);
+        assert_eq!(value("[1, 2"), None);
+        assert_eq!(value("[1] 2"), None);
+
+        assert_eq!(toml_scalar("42, 3]"), Some(("42".to_string(), ", 3]")));
+        assert_eq!(
+            toml_scalar(r#" "a" rest"#),
+            Some(("a".to_string(), " rest"))
+        );
+        assert_eq!(toml_scalar("  "), None);
+    }
+
+    #[test]
+    fn config_settings_become_flags() {
+        let mut config = Config::default();
+        let text = r##"
+            # Comments and blank lines are skipped
+            theme = "gruvbox"
+            tab_width = 8
+            comment-prefix = "#"  # the quoted # is kept
+            ruler = [80, 100]
+            line-numbers = true
+            minimap = false
+            wrap = false
+        "##;
+        config.read_settings(text).unwrap();
+        assert_eq!(
+            config.defaults,
+            [
+                "--theme",
+                "gruvbox",
+                "--tab-width",
+                "8",
+                "--comment-prefix",
+                "#",
+                "--ruler",
+                "80",
+                "--ruler",
+                "100",
+                "--line-numbers",
+                "--no-minimap",
+                "--no-wrap",
+            ]
+        );
+        assert!(config.rules.is_empty());
+    }
+
+    #[test]
+    fn config_keys_and_file_tables_take_their_own_settings() {
+        let mut config = Config::default();
+        let text = "wrap = true\n[keys]\nquit = [\"x\", \"ctrl-d\"]\n\
+                    [files.\"*.md\"]\nmarkdown = true\n[ files.'*.csv' ]\ncsv = true\nruler = 80\n";
+        config.read_settings(text).unwrap();
+        assert_eq!(config.defaults, ["--wrap"]);
+        assert_eq!(
+            config.rules,
+            [
+                ("*.md".to_string(), vec!["--markdown".to_string()]),
+                (
+                    "*.csv".to_string(),
+                    vec!["--csv".to_string(), "--ruler".to_string(), "80".to_string()]
+                ),
+            ]
+        );
+        let key = |code: KeyCode, modifiers| config.keymap.action(KeyEvent::new(code, modifiers));
+        assert_eq!(
+            key(KeyCode::Char('x'), KeyModifiers::NONE),
+            Some(Action::Quit)
+        );
+        assert_eq!(
+            key(KeyCode::Char('d'), KeyModifiers::CONTROL),
+            Some(Action::Quit)
+        );
+        assert_eq!(key(KeyCode::Char('q'), KeyModifiers::NONE), None);
+        assert_eq!(
+            config.defaults_for(Path::new("dir/notes.md")),
+            Some(&["--markdown".to_string()][..])
+        );
+        assert_eq!(config.defaults_for(Path::new("notes.txt")), None);
+    }
+
+    #[test]
+    fn config_mistakes_name_their_line() {
+        let error = |text: &str| Config::default().read_settings(text).unwrap_err();
+        assert_eq!(
+            error("wrap = true\n[keys"),
+            "line 2: expected ] after the table name"
+        );
+        assert_eq!(error("[colors]"), "line 1: unknown table [colors]");
+        assert_eq!(
+            error("[files.*.log]"),
+            "line 1: unknown table [files.*.log]"
+        );
+        assert_eq!(error("\n\nbogus = 1"), "line 3: unknown setting bogus");
+        assert_eq!(
+            error("line numbers = 1"),
+            "line 1: unknown setting line numbers"
+        );
+        for line in ["wrap", "= true", "wrap = ", "theme = \"open", "ruler = [80"] {
+            assert_eq!(
+                error(line),
+                "line 1: expected name = a string, number, boolean or array of them",
+                "{line}"
+            );
+        }
+        assert!(
+            error("[keys]\nleap = \"l\"").starts_with("line 2: unknown action leap; actions are ")
+        );
+        assert_eq!(
+            error("[keys]\nquit = \"hyper-q\""),
+            "line 2: unknown key hyper-q"
+        );
+    }
+
+    #[test]
+    fn key_names_parse_with_modifiers() {
+        let key = |code: KeyCode, modifiers| Some(KeyBinding::of(KeyEvent::new(code, modifiers)));
+        assert_eq!(
+            KeyBinding::parse("q"),
+            key(KeyCode::Char('q'), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            KeyBinding::parse("G"),
+            key(KeyCode::Char('G'), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            KeyBinding::parse("-"),
+            key(KeyCode::Char('-'), KeyModifiers::NONE)
+        );
+        // Ctrl takes letters as lowercase, whatever the case written
+        assert_eq!(
+            KeyBinding::parse("ctrl-D"),
+            key(KeyCode::Char('d'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            KeyBinding::parse("C-d"),
+            key(KeyCode::Char('d'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            KeyBinding::parse("alt-x"),
+            key(KeyCode::Char('x'), KeyModifiers::ALT)
+        );
+        assert_eq!(
+            KeyBinding::parse("M-x"),
+            key(KeyCode::Char('x'), KeyModifiers::ALT)
+        );
+        assert_eq!(
+            KeyBinding::parse("ctrl-alt-pagedown"),
+            key(KeyCode::PageDown, KeyModifiers::CONTROL | KeyModifiers::ALT)
+        );
+        assert_eq!(
+            KeyBinding::parse("shift-tab"),
+            key(KeyCode::Tab, KeyModifiers::SHIFT)
+        );
+        // Shift is part of a character already
+        assert_eq!(
+            KeyBinding::parse("shift-g"),
+            key(KeyCode::Char('g'), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            KeyBinding::parse("ctrl--"),
+            key(KeyCode::Char('-'), KeyModifiers::CONTROL)
+        );
+
+        for (name, code) in [
+            ("Esc", KeyCode::Esc),
+            ("escape", KeyCode::Esc),
+            ("enter", KeyCode::Enter),
+            ("return", KeyCode::Enter),
+            ("tab", KeyCode::Tab),
+            ("space", KeyCode::Char(' ')),
+            ("backspace", KeyCode::Backspace),
+            ("up", KeyCode::Up),
+            ("down", KeyCode::Down),
+            ("left", KeyCode::Left),
+            ("right", KeyCode::Right),
+            ("PgUp", KeyCode::PageUp),
+            ("pageup", KeyCode::PageUp),
+            ("pgdn", KeyCode::PageDown),
+            ("home", KeyCode::Home),
+            ("end", KeyCode::End),
+        ] {
+            assert_eq!(
+                KeyBinding::parse(name),
+                key(code, KeyModifiers::NONE),
+                "{name}"
+            );
+        }
+
+        for name in [
+            "",
+            "ctrl-",
+            "super-q",
+            "hyper-q",
+            "f1",
+            "ctrl-pagedwn",
+            "qq",
+        ] {
+            assert_eq!(KeyBinding::parse(name), None, "{name}");
+        }
+    }
+
+    #[test]
+    fn key_tables_change_what_a_key_does() {
+        let mut config = Config::default();
+        config
+            .read_settings("[keys]\ndown = [\"n\"]\nbottom = [\"j\", \"ctrl-e\"]\n")
+            .unwrap();
+        let mut remapped = app(100, 10);
+        remapped.keymap = config.keymap;
+
+        // j now goes to the bottom, and n does what j did
+        type_keys(&mut remapped, "j");
+        assert_eq!(remapped.panes[0].scroll, 90);
+        type_keys(&mut remapped, "gg");
+        type_keys(&mut remapped, "nn");
+        assert_eq!(remapped.panes[0].scroll, 2);
+        remapped.handle_key(KeyEvent::new(KeyCode::Char('e'), KeyModifiers::CONTROL));
+        assert_eq!(remapped.panes[0].scroll, 90);
+        // A rebound action loses its default keys; G did nothing else
+        type_keys(&mut remapped, "ggG");
+        assert_eq!(remapped.panes[0].scroll, 0);
+        remapped.handle_key(KeyEvent::from(KeyCode::Down));
+        assert_eq!(remapped.panes[0].scroll, 0);
+    }
+
+    #[test]
+    fn theme_files_pick_a_base_and_replace_colors() {
+        assert_eq!(parse_theme("# nothing set\n"), Ok(Theme::DRACULA));
+        assert_eq!(parse_theme("base = \"nord\""), Ok(Theme::NORD));
+
+        // The base applies first even when it comes last
+        let theme = parse_theme(
+            "keyword = \"#d3869b\"\ndoc-comment = 'red'\ntype = \"208\"\nbase = \"gruvbox\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            theme,
+            Theme {
+                keyword: Color::Rgb(0xd3, 0x86, 0x9b),
+                doc_comment: Color::Red,
+                types: Color::Indexed(208),
+                ..Theme::GRUVBOX
+            }
+        );
+        let theme = parse_theme("selection = \"#000000\"  # black").unwrap();
+        assert_eq!(theme.selection, Color::Rgb(0, 0, 0));
+        assert_eq!(theme.background, Theme::DRACULA.background);
+    }
+
+    #[test]
+    fn theme_mistakes_name_their_line() {
+        let error = |text: &str| parse_theme(text).unwrap_err();
+        assert_eq!(
+            error("\nbase = \"vim\""),
+            "line 2: no built-in theme called vim"
+        );
+        assert_eq!(error("links = \"#ffffff\""), "line 1: unknown color links");
+        assert_eq!(
+            error("keyword = \"#12345\""),
+            "line 1: expected a color like \"#282a36\", not #12345"
+        );
+        assert_eq!(
+            error("string = \"teal-ish\""),
+            "line 1: expected a color like \"#282a36\", not teal-ish"
+        );
+        for line in [
+            "keyword",
+            "keyword = [\"red\", \"blue\"]",
+            "keyword = \"red",
+        ] {
+            assert_eq!(error(line), "line 1: expected name = \"value\"", "{line}");
+        }
+    }
+
+    #[test]
+    fn the_last_line_lands_on_the_bottom_row_just_past_a_screen() {
+        // 13 rows: the pane's two borders and the status row leave 10 for text
+        let shown = |total: usize, key: KeyCode| {
+            let mut paged = App::new(vec![pane(total, 0)], None, options(&[]));
+            screen(&mut paged, 20, 13);
+            paged.handle_key(KeyEvent::from(key));
+            let rows = screen(&mut paged, 20, 13);
+            let number = |row: &String| row.trim_matches(|c: char| !c.is_ascii_digit()).to_string();
+            (number(&rows[1]), number(&rows[10]))
+        };
+        for total in [11, 12] {
+            for key in [KeyCode::PageDown, KeyCode::Char('G')] {
+                let (top, bottom) = shown(total, key);
+                assert_eq!(bottom, total.to_string(), "{total} lines, {key:?}");
+                assert_eq!(top, (total - 9).to_string(), "{total} lines, {key:?}");
+            }
+        }
+    }
 }
@@ -0,0 +1,164 @@
+//! Grammar-based highlighting through [syntect], behind the `syntect`
+//! feature. Slower to start than [`highlight`](crate::highlight) but knows
+//! many more languages, and tracks multi-line strings and comments.
+
+use crate::{Theme, color_rgb, join_clusters, show_controls};
+use ratatui::prelude::*;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use syntect::highlighting::{
+    self as grammar_style, FontStyle, HighlightIterator, HighlightState, Highlighter,
+    ScopeSelectors, StyleModifier, ThemeItem, ThemeSettings,
+};
+use syntect::parsing::{ParseState, ScopeStack, SyntaxReference, SyntaxSet};
+
+/// Syntaxes bundled with syntect, loaded on first use.
+fn syntaxes() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+/// Highlights one file a line at a time, carrying the parser's state from
+/// each line into the next so constructs spanning lines color correctly.
+///
+/// ```
+/// use file_peek::{GrammarHighlighter, Theme};
+/// use std::path::Path;
+///
+/// let theme = Theme::default();
+/// let mut highlighter = GrammarHighlighter::for_file(Path::new("x.py"), "", &theme).unwrap();
+/// let line = highlighter.highlight_next("import os");
+/// let import = line.spans.iter().find(|span| span.content == "import").unwrap();
+/// assert_eq!(import.style.fg, Some(theme.keyword));
+///
+/// // Still inside the docstring opened on the line before
+/// highlighter.highlight_next("\"\"\"Docs");
+/// let line = highlighter.highlight_next("def not_code():");
+/// assert_eq!(line.spans[0].style.fg, Some(theme.doc_comment));
+///
+/// // Named and palette colors come out as the RGB xterm shows for them
+/// use ratatui::style::Color;
+/// let theme = Theme { keyword: Color::Indexed(203), special: Color::LightBlue, ..Theme::default() };
+/// let mut highlighter = GrammarHighlighter::for_file(Path::new("x.py"), "", &theme).unwrap();
+/// let mut color = |line, text| {
+///     let line = highlighter.highlight_next(line);
+///     line.spans.iter().find(|span| span.content == text).unwrap().style.fg
+/// };
+/// assert_eq!(color("import os", "import"), Some(Color::Rgb(0xff, 0x5f, 0x5f)));
+/// assert_eq!(color("x = None", "None"), Some(Color::Rgb(0x5c, 0x5c, 0xff)));
+/// ```
+pub struct GrammarHighlighter {
+    syntax: &'static SyntaxReference,
+    colors: Theme,
+    theme: grammar_style::Theme,
+    parse: ParseState,
+    highlight: HighlightState,
+}
+
+impl GrammarHighlighter {
+    /// A highlighter for `path`, found by extension or else by its
+    /// `first_line` (a shebang or mode line). `None` when no grammar knows
+    /// the file.
+    pub fn for_file(path: &Path, first_line: &str, theme: &Theme) -> Option<Self> {
+        let syntaxes = syntaxes();
+        let syntax = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(|extension| syntaxes.find_syntax_by_extension(extension))
+            .or_else(|| syntaxes.find_syntax_by_first_line(first_line))
+            .filter(|syntax| syntax.name != "Plain Text")?;
+        let colors = *theme;
+        let theme = grammar_theme(theme);
+        let highlighter = Highlighter::new(&theme);
+        Some(Self {
+            syntax,
+            colors,
+            parse: ParseState::new(syntax),
+            highlight: HighlightState::new(&highlighter, ScopeStack::new()),
+            theme,
+        })
+    }
+
+    /// Highlights the next line of the file. Lines must be fed in order,
+    /// starting from the first.
+    pub fn highlight_next(&mut self, line: &str) -> Line<'static> {
+        // The bundled grammars expect each line to keep its newline
+        let text = format!("{line}\n");
+        let Ok(changes) = self.parse.parse_line(&text, syntaxes()) else {
+            // A grammar that fails once can't be trusted for the rest
+            self.parse = ParseState::new(self.syntax);
+            return Line::from(line.to_string());
+        };
+        let highlighter = Highlighter::new(&self.theme);
+        let spans: Vec<Span<'static>> =
+            HighlightIterator::new(&mut self.highlight, &changes, &text, &highlighter)
+                .map(|(style, token)| (style, token.trim_end_matches('\n')))
+                .filter(|(_, token)| !token.is_empty())
+                .map(|(style, token)| Span::styled(token.to_string(), span_style(style)))
+                .collect();
+        Line::from(join_clusters(show_controls(spans, &self.colors)))
+    }
+}
+
+/// A syntect theme that gives scopes the colors [`highlight`](crate::highlight)
+/// uses for the same kinds of token.
+fn grammar_theme(theme: &Theme) -> grammar_style::Theme {
+    let item = |scope: &str, color: Color, bold: bool| ThemeItem {
+        scope: ScopeSelectors::from_str(scope).unwrap_or_default(),
+        style: StyleModifier {
+            foreground: Some(grammar_color(color)),
+            background: None,
+            font_style: bold.then_some(FontStyle::BOLD),
+        },
+    };
+    grammar_style::Theme {
+        settings: ThemeSettings {
+            foreground: Some(grammar_color(theme.foreground)),
+            ..ThemeSettings::default()
+        },
+        // Later items win over earlier ones for equally specific scopes
+        scopes: vec![
+            item("comment", theme.comment, false),
+            item(
+                "comment.block.documentation, comment.line.documentation",
+                theme.doc_comment,
+                false,
+            ),
+            item("string", theme.string, false),
+            item("constant.numeric", theme.number, false),
+            item(
+                "constant.language, constant.character, variable.language",
+                theme.special,
+                false,
+            ),
+            item("keyword, storage.modifier", theme.keyword, true),
+            item(
+                "storage.type, entity.name.type, support.type, support.class",
+                theme.types,
+                false,
+            ),
+            item("invalid", theme.error, false),
+        ],
+        ..grammar_style::Theme::default()
+    }
+}
+
+fn grammar_color(color: Color) -> grammar_style::Color {
+    match color_rgb(color) {
+        Some((r, g, b)) => grammar_style::Color { r, g, b, a: 0xFF },
+        None => grammar_style::Color::WHITE,
+    }
+}
+
+fn span_style(style: grammar_style::Style) -> Style {
+    let grammar_style::Color { r, g, b, .. } = style.foreground;
+    let mut span_style = Style::default().fg(Color::Rgb(r, g, b));
+    if style.font_style.contains(FontStyle::BOLD) {
+        span_style = span_style.add_modifier(Modifier::BOLD);
+    }
+    if style.font_style.contains(FontStyle::ITALIC) {
+        span_style = span_style.add_modifier(Modifier::ITALIC);
+    }
+    span_style
+}
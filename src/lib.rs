@@ -0,0 +1,1820 @@
+//! Line-oriented syntax highlighting for [ratatui], as used by the `fp`
+//! pager. Each call turns one line of text into a styled [`Line`].
+
+#[cfg(feature = "syntect")]
+mod grammar;
+#[cfg(feature = "tree-sitter")]
+mod syntax_tree;
+mod viewer;
+
+#[cfg(feature = "syntect")]
+pub use grammar::GrammarHighlighter;
+use ratatui::prelude::*;
+use std::ops::Range;
+use std::path::Path;
+#[cfg(feature = "tree-sitter")]
+pub use syntax_tree::TreeHighlighter;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+pub use viewer::{FileView, FileViewState, PeekApp, max_scroll, page_down, page_up, scrollbar};
+
+/// Languages recognised for display purposes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    Rust,
+    Python,
+    Shell,
+    C,
+    Cpp,
+    JavaScript,
+    TypeScript,
+    Go,
+    Java,
+    Toml,
+    Yaml,
+    Json,
+    Markdown,
+    /// Unified diffs and patches
+    Diff,
+    Plain,
+}
+
+impl Language {
+    /// Human-readable name, as shown in the status line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::Rust => "Rust",
+            Language::Python => "Python",
+            Language::Shell => "Shell",
+            Language::C => "C",
+            Language::Cpp => "C++",
+            Language::JavaScript => "JavaScript",
+            Language::TypeScript => "TypeScript",
+            Language::Go => "Go",
+            Language::Java => "Java",
+            Language::Toml => "TOML",
+            Language::Yaml => "YAML",
+            Language::Json => "JSON",
+            Language::Markdown => "Markdown",
+            Language::Diff => "Diff",
+            Language::Plain => "Plain text",
+        }
+    }
+}
+
+/// Guesses the language of `path` from its extension.
+pub fn detect_language(path: &Path) -> Language {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+
+    match extension.as_deref() {
+        Some("rs") => Language::Rust,
+        Some("py" | "pyw") => Language::Python,
+        Some("sh" | "bash" | "zsh") => Language::Shell,
+        Some("c" | "h") => Language::C,
+        Some("cc" | "cpp" | "cxx" | "hpp" | "hh") => Language::Cpp,
+        Some("js" | "mjs" | "cjs" | "jsx") => Language::JavaScript,
+        Some("ts" | "tsx") => Language::TypeScript,
+        Some("go") => Language::Go,
+        Some("java") => Language::Java,
+        Some("toml") => Language::Toml,
+        Some("yml" | "yaml") => Language::Yaml,
+        Some("json") => Language::Json,
+        Some("md" | "markdown") => Language::Markdown,
+        Some("diff" | "patch") => Language::Diff,
+        _ => Language::Plain,
+    }
+}
+
+/// Guesses the language of a script from its `#!` line, for files whose
+/// extension says nothing. `env` (with or without `-S`) is looked through.
+///
+/// ```
+/// use file_peek::{Language, shebang_language};
+///
+/// assert_eq!(shebang_language("#!/usr/bin/env python3"), Some(Language::Python));
+/// assert_eq!(shebang_language("#!/bin/bash -e"), Some(Language::Shell));
+/// assert_eq!(shebang_language("#!/bin/sh"), Some(Language::Shell));
+/// assert_eq!(shebang_language("#!/usr/bin/env -S node --inspect"), Some(Language::JavaScript));
+/// assert_eq!(shebang_language("#!/usr/bin/perl"), None);
+/// assert_eq!(shebang_language("print('no shebang')"), None);
+/// ```
+pub fn shebang_language(first_line: &str) -> Option<Language> {
+    let mut words = first_line.strip_prefix("#!")?.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|word| !word.starts_with('-'))?;
+    }
+    // Versioned names: python3, python3.12
+    let name = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+
+    match name {
+        "python" | "pypy" => Some(Language::Python),
+        "sh" | "bash" | "zsh" | "dash" | "ksh" | "ash" => Some(Language::Shell),
+        "node" | "nodejs" | "deno" | "bun" => Some(Language::JavaScript),
+        "ts-node" | "tsx" => Some(Language::TypeScript),
+        "rust-script" => Some(Language::Rust),
+        _ => None,
+    }
+}
+
+/// Guesses the language of a file from its first line, for files whose
+/// name says nothing: a `#!` line (see [`shebang_language`]), or the start
+/// of a diff such as `git diff` output.
+///
+/// ```
+/// use file_peek::{Language, content_language};
+///
+/// assert_eq!(content_language("diff --git a/src/lib.rs b/src/lib.rs"), Some(Language::Diff));
+/// assert_eq!(content_language("@@ -1,3 +1,4 @@"), Some(Language::Diff));
+/// assert_eq!(content_language("#!/bin/sh"), Some(Language::Shell));
+/// assert_eq!(content_language("differences"), None);
+/// ```
+pub fn content_language(first_line: &str) -> Option<Language> {
+    let diff = ["diff --git ", "diff -", "@@ "]
+        .iter()
+        .any(|start| first_line.starts_with(start));
+    if diff {
+        return Some(Language::Diff);
+    }
+    shebang_language(first_line)
+}
+
+/// Colors used by [`highlight`]. Fields are named for what they color;
+/// [`Theme::DRACULA`] is the default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    pub background: Color,
+    pub foreground: Color,
+    pub comment: Color,
+    /// Rust doc comments (`///`, `//!`, `/** */`)
+    pub doc_comment: Color,
+    pub keyword: Color,
+    /// Types and links
+    pub types: Color,
+    pub string: Color,
+    pub number: Color,
+    pub error: Color,
+    /// Lifetimes and other special tokens
+    pub special: Color,
+    pub warning: Color,
+    /// Subtle background for the current line or a selection
+    pub selection: Color,
+}
+
+impl Theme {
+    /// The Dracula palette (https://draculatheme.com).
+    pub const DRACULA: Theme = Theme {
+        background: Color::Rgb(40, 42, 54),     // #282A36
+        foreground: Color::Rgb(248, 248, 242),  // #F8F8F2
+        comment: Color::Rgb(98, 114, 164),      // #6272A4
+        doc_comment: Color::Rgb(144, 160, 210), // #90A0D2 (lighter comment)
+        keyword: Color::Rgb(189, 147, 249),     // #BD93F9 (purple)
+        types: Color::Rgb(139, 233, 253),       // #8BE9FD (cyan)
+        string: Color::Rgb(80, 250, 123),       // #50FA7B (green)
+        number: Color::Rgb(255, 184, 108),      // #FFB86C (orange)
+        error: Color::Rgb(255, 85, 85),         // #FF5555 (red)
+        special: Color::Rgb(255, 121, 198),     // #FF79C6 (pink)
+        warning: Color::Rgb(241, 250, 140),     // #F1FA8C (yellow)
+        selection: Color::Rgb(68, 71, 90),      // #44475A
+    };
+
+    /// Solarized Light (https://ethanschoonover.com/solarized), for
+    /// terminals with a light background.
+    ///
+    /// ```
+    /// use file_peek::Theme;
+    /// use ratatui::style::Color;
+    ///
+    /// fn luma(color: Color) -> u32 {
+    ///     match color {
+    ///         Color::Rgb(r, g, b) => (299 * r as u32 + 587 * g as u32 + 114 * b as u32) / 1000,
+    ///         _ => unreachable!(),
+    ///     }
+    /// }
+    ///
+    /// let theme = Theme::SOLARIZED_LIGHT;
+    /// let background = luma(theme.background);
+    /// for color in [theme.foreground, theme.keyword, theme.types, theme.string, theme.comment] {
+    ///     assert!(background - luma(color) > 60, "{color:?} is too faint on light");
+    /// }
+    /// ```
+    pub const SOLARIZED_LIGHT: Theme = Theme {
+        background: Color::Rgb(253, 246, 227),  // #FDF6E3 (base3)
+        foreground: Color::Rgb(88, 110, 117),   // #586E75 (base01)
+        comment: Color::Rgb(147, 161, 161),     // #93A1A1 (base1)
+        doc_comment: Color::Rgb(101, 123, 131), // #657B83 (base00)
+        keyword: Color::Rgb(133, 153, 0),       // #859900 (green)
+        types: Color::Rgb(38, 139, 210),        // #268BD2 (blue)
+        string: Color::Rgb(42, 161, 152),       // #2AA198 (cyan)
+        number: Color::Rgb(211, 54, 130),       // #D33682 (magenta)
+        error: Color::Rgb(220, 50, 47),         // #DC322F (red)
+        special: Color::Rgb(108, 113, 196),     // #6C71C4 (violet)
+        warning: Color::Rgb(181, 137, 0),       // #B58900 (yellow)
+        selection: Color::Rgb(238, 232, 213),   // #EEE8D5 (base2)
+    };
+
+    /// Solarized Dark: the same accents as [`Theme::SOLARIZED_LIGHT`] on
+    /// the dark base tones.
+    pub const SOLARIZED_DARK: Theme = Theme {
+        background: Color::Rgb(0, 43, 54),      // #002B36 (base03)
+        foreground: Color::Rgb(131, 148, 150),  // #839496 (base0)
+        comment: Color::Rgb(88, 110, 117),      // #586E75 (base01)
+        doc_comment: Color::Rgb(101, 123, 131), // #657B83 (base00)
+        keyword: Color::Rgb(133, 153, 0),       // #859900 (green)
+        types: Color::Rgb(38, 139, 210),        // #268BD2 (blue)
+        string: Color::Rgb(42, 161, 152),       // #2AA198 (cyan)
+        number: Color::Rgb(211, 54, 130),       // #D33682 (magenta)
+        error: Color::Rgb(220, 50, 47),         // #DC322F (red)
+        special: Color::Rgb(108, 113, 196),     // #6C71C4 (violet)
+        warning: Color::Rgb(181, 137, 0),       // #B58900 (yellow)
+        selection: Color::Rgb(7, 54, 66),       // #073642 (base02)
+    };
+
+    /// Gruvbox dark (https://github.com/morhetz/gruvbox).
+    ///
+    /// ```
+    /// use file_peek::Theme;
+    ///
+    /// let themes = [
+    ///     Theme::DRACULA,
+    ///     Theme::GRUVBOX,
+    ///     Theme::MONOKAI,
+    ///     Theme::NORD,
+    ///     Theme::SOLARIZED_DARK,
+    ///     Theme::SOLARIZED_LIGHT,
+    /// ];
+    /// for theme in themes {
+    ///     // Tokens stay readable: nothing is drawn in the background color
+    ///     for color in [theme.foreground, theme.comment, theme.keyword, theme.string] {
+    ///         assert_ne!(color, theme.background);
+    ///     }
+    ///     assert_ne!(theme.selection, theme.background);
+    /// }
+    /// ```
+    pub const GRUVBOX: Theme = Theme {
+        background: Color::Rgb(40, 40, 40),     // #282828 (bg)
+        foreground: Color::Rgb(235, 219, 178),  // #EBDBB2 (fg)
+        comment: Color::Rgb(146, 131, 116),     // #928374 (gray)
+        doc_comment: Color::Rgb(168, 153, 132), // #A89984 (fg4)
+        keyword: Color::Rgb(251, 73, 52),       // #FB4934 (red)
+        types: Color::Rgb(250, 189, 47),        // #FABD2F (yellow)
+        string: Color::Rgb(184, 187, 38),       // #B8BB26 (green)
+        number: Color::Rgb(211, 134, 155),      // #D3869B (purple)
+        error: Color::Rgb(204, 36, 29),         // #CC241D (dark red)
+        special: Color::Rgb(142, 192, 124),     // #8EC07C (aqua)
+        warning: Color::Rgb(254, 128, 25),      // #FE8019 (orange)
+        selection: Color::Rgb(60, 56, 54),      // #3C3836 (bg1)
+    };
+
+    /// Monokai, after the Sublime Text classic.
+    pub const MONOKAI: Theme = Theme {
+        background: Color::Rgb(39, 40, 34),     // #272822
+        foreground: Color::Rgb(248, 248, 242),  // #F8F8F2
+        comment: Color::Rgb(117, 113, 94),      // #75715E
+        doc_comment: Color::Rgb(157, 152, 131), // #9D9883 (lighter comment)
+        keyword: Color::Rgb(249, 38, 114),      // #F92672 (pink)
+        types: Color::Rgb(102, 217, 239),       // #66D9EF (blue)
+        string: Color::Rgb(230, 219, 116),      // #E6DB74 (yellow)
+        number: Color::Rgb(174, 129, 255),      // #AE81FF (purple)
+        error: Color::Rgb(244, 71, 71),         // #F44747 (red)
+        special: Color::Rgb(166, 226, 46),      // #A6E22E (green)
+        warning: Color::Rgb(253, 151, 31),      // #FD971F (orange)
+        selection: Color::Rgb(73, 72, 62),      // #49483E
+    };
+
+    /// Nord (https://www.nordtheme.com).
+    pub const NORD: Theme = Theme {
+        background: Color::Rgb(46, 52, 64),     // #2E3440 (nord0)
+        foreground: Color::Rgb(216, 222, 233),  // #D8DEE9 (nord4)
+        comment: Color::Rgb(97, 110, 136),      // #616E88 (bright nord3)
+        doc_comment: Color::Rgb(123, 136, 161), // #7B88A1 (lighter comment)
+        keyword: Color::Rgb(129, 161, 193),     // #81A1C1 (nord9)
+        types: Color::Rgb(143, 188, 187),       // #8FBCBB (nord7)
+        string: Color::Rgb(163, 190, 140),      // #A3BE8C (nord14)
+        number: Color::Rgb(180, 142, 173),      // #B48EAD (nord15)
+        error: Color::Rgb(191, 97, 106),        // #BF616A (nord11)
+        special: Color::Rgb(136, 192, 208),     // #88C0D0 (nord8)
+        warning: Color::Rgb(235, 203, 139),     // #EBCB8B (nord13)
+        selection: Color::Rgb(59, 66, 82),      // #3B4252 (nord1)
+    };
+
+    /// Style given to URLs and Markdown links.
+    pub fn url_style(&self) -> Style {
+        Style::default().fg(self.types).underlined()
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::DRACULA
+    }
+}
+
+/// How many colors a terminal can show. The themes are written in 24-bit
+/// RGB; shallower terminals get the nearest color they have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorDepth {
+    TrueColor,
+    /// The xterm 256-color palette
+    Ansi256,
+    /// The 16 standard colors, bright variants included
+    Ansi16,
+    /// Only the 8 basic colors
+    Ansi8,
+}
+
+/// The 16 standard colors with xterm's default RGB values; the first 8
+/// are the basic ones.
+const ANSI_COLORS: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::Gray, (229, 229, 229)),
+    (Color::DarkGray, (127, 127, 127)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (92, 92, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// Channel values of the 6×6×6 color cube in the 256-color palette.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let channel = |a: u8, b: u8| (i32::from(a) - i32::from(b)).pow(2) as u32;
+    channel(r1, r2) + channel(g1, g2) + channel(b1, b2)
+}
+
+impl ColorDepth {
+    /// Works the depth out from `$COLORTERM` and `$TERM`. Without a `TERM`
+    /// (as on Windows consoles) truecolor is assumed.
+    ///
+    /// ```
+    /// use file_peek::ColorDepth;
+    ///
+    /// assert_eq!(ColorDepth::from_env(Some("truecolor"), Some("xterm")), ColorDepth::TrueColor);
+    /// assert_eq!(ColorDepth::from_env(None, Some("screen-256color")), ColorDepth::Ansi256);
+    /// assert_eq!(ColorDepth::from_env(None, Some("linux")), ColorDepth::Ansi16);
+    /// assert_eq!(ColorDepth::from_env(None, None), ColorDepth::TrueColor);
+    /// ```
+    pub fn from_env(colorterm: Option<&str>, term: Option<&str>) -> Self {
+        if colorterm.is_some_and(|value| matches!(value, "truecolor" | "24bit")) {
+            return Self::TrueColor;
+        }
+        match term {
+            None => Self::TrueColor,
+            Some(term) if term.ends_with("-direct") => Self::TrueColor,
+            Some(term) if term.contains("256color") => Self::Ansi256,
+            Some(_) => Self::Ansi16,
+        }
+    }
+
+    /// The nearest color to `color` this depth can show. Only RGB colors
+    /// change; named and indexed ones are left to the terminal.
+    ///
+    /// ```
+    /// use file_peek::ColorDepth;
+    /// use ratatui::style::Color;
+    ///
+    /// let to_256 = |r, g, b| ColorDepth::Ansi256.quantize(Color::Rgb(r, g, b));
+    /// assert_eq!(to_256(255, 0, 0), Color::Indexed(196));
+    /// assert_eq!(to_256(255, 121, 198), Color::Indexed(212)); // Dracula pink
+    /// assert_eq!(to_256(40, 42, 54), Color::Indexed(236)); // Dracula background
+    /// assert_eq!(to_256(128, 128, 128), Color::Indexed(244));
+    /// assert_eq!(to_256(0, 0, 0), Color::Indexed(16));
+    ///
+    /// assert_eq!(ColorDepth::Ansi16.quantize(Color::Rgb(250, 80, 80)), Color::LightRed);
+    /// assert_eq!(ColorDepth::Ansi8.quantize(Color::Rgb(250, 80, 80)), Color::Red);
+    /// assert_eq!(ColorDepth::TrueColor.quantize(Color::Rgb(1, 2, 3)), Color::Rgb(1, 2, 3));
+    /// ```
+    pub fn quantize(self, color: Color) -> Color {
+        let Color::Rgb(r, g, b) = color else {
+            return color;
+        };
+        let rgb = (r, g, b);
+        let nearest_named = |palette: &[(Color, (u8, u8, u8))]| {
+            palette
+                .iter()
+                .min_by_key(|(_, named)| distance(rgb, *named))
+                .map_or(color, |(named, _)| *named)
+        };
+        match self {
+            Self::TrueColor => color,
+            Self::Ansi16 => nearest_named(&ANSI_COLORS),
+            Self::Ansi8 => nearest_named(&ANSI_COLORS[..8]),
+            Self::Ansi256 => {
+                let level = |value: u8| {
+                    (0..CUBE_LEVELS.len())
+                        .min_by_key(|&index| CUBE_LEVELS[index].abs_diff(value))
+                        .unwrap_or(0)
+                };
+                let (ri, gi, bi) = (level(r), level(g), level(b));
+                let cube = (CUBE_LEVELS[ri], CUBE_LEVELS[gi], CUBE_LEVELS[bi]);
+                // The grey ramp runs 8, 18, ... 238 at indices 232-255
+                let average = (u32::from(r) + u32::from(g) + u32::from(b)) / 3;
+                let step = (average.saturating_sub(3) / 10).min(23) as u8;
+                let grey = 8 + 10 * step;
+                if distance(rgb, (grey, grey, grey)) < distance(rgb, cube) {
+                    Color::Indexed(232 + step)
+                } else {
+                    Color::Indexed(16 + 36 * ri as u8 + 6 * gi as u8 + bi as u8)
+                }
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for ColorDepth {
+    type Err = String;
+
+    /// `truecolor`, `256`, `16` or `ansi` (the 8 basic colors).
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "truecolor" | "24bit" => Ok(Self::TrueColor),
+            "256" => Ok(Self::Ansi256),
+            "16" => Ok(Self::Ansi16),
+            "ansi" | "8" => Ok(Self::Ansi8),
+            _ => Err(format!(
+                "unknown color depth `{name}` (expected truecolor, 256, 16 or ansi)"
+            )),
+        }
+    }
+}
+
+/// Colors brackets in a [`highlight`]ed line by how deeply they nest,
+/// cycling through the theme's main colors. A closer with no matching
+/// opener is colored as an error. Depth restarts at zero on every line.
+///
+/// ```
+/// use file_peek::{Language, Theme, highlight, rainbow_brackets};
+///
+/// let theme = Theme::default();
+/// let line = rainbow_brackets(highlight("((a)(b))", Language::Rust, &theme), &theme);
+/// let colors: Vec<_> = line.spans.iter().map(|span| span.style.fg).collect();
+///
+/// let (outer, inner) = (Some(theme.keyword), Some(theme.types));
+/// assert_eq!(colors[0], outer);
+/// assert_eq!(colors[1], inner); // ( of (a)
+/// assert_eq!(colors[3], inner); // ) of (a)
+/// assert_eq!(colors[4], inner); // ( of (b)
+/// assert_eq!(colors[7], outer);
+///
+/// let extra = rainbow_brackets(highlight("a)", Language::Rust, &theme), &theme);
+/// assert_eq!(extra.spans[1].style.fg, Some(theme.error));
+/// ```
+pub fn rainbow_brackets<'a>(mut line: Line<'a>, theme: &Theme) -> Line<'a> {
+    let palette = [
+        theme.keyword,
+        theme.types,
+        theme.string,
+        theme.number,
+        theme.special,
+    ];
+    let mut open = Vec::new();
+
+    // Punctuation is emitted one char per span, so strings and comments
+    // (always longer spans) never match here
+    for span in &mut line.spans {
+        let color = match span.content.as_ref() {
+            opener @ ("(" | "[" | "{") => {
+                open.push(opener.chars().next().unwrap_or_default());
+                palette[(open.len() - 1) % palette.len()]
+            }
+            closer @ (")" | "]" | "}") => {
+                let expected = match closer {
+                    ")" => '(',
+                    "]" => '[',
+                    _ => '{',
+                };
+                if open.last() == Some(&expected) {
+                    open.pop();
+                    palette[open.len() % palette.len()]
+                } else {
+                    theme.error
+                }
+            }
+            _ => continue,
+        };
+        span.style = span.style.fg(color);
+    }
+    line
+}
+
+/// Highlights one line of source as `language` using `theme`'s colors.
+///
+/// Highlighting is purely line-oriented: there is no state carried between
+/// lines, so a block comment or string spanning several lines is only
+/// colored on the line that opens it.
+///
+/// ```
+/// use file_peek::{Language, Theme, highlight};
+///
+/// let theme = Theme::default();
+/// let line = highlight("let x = \"hi\"; // greet", Language::Rust, &theme);
+///
+/// let keyword = &line.spans[0];
+/// assert_eq!(keyword.content, "let");
+/// assert_eq!(keyword.style.fg, Some(theme.keyword));
+///
+/// let string = line.spans.iter().find(|span| span.content == "\"hi\"").unwrap();
+/// assert_eq!(string.style.fg, Some(theme.string));
+/// ```
+///
+/// Rust doc comments get their own color, and block comments nest, so code
+/// after the outer `*/` is highlighted again:
+///
+/// ```
+/// use file_peek::{Language, Theme, highlight};
+///
+/// let theme = Theme::default();
+/// let doc = highlight("/// Docs", Language::Rust, &theme);
+/// assert_eq!(doc.spans[0].style.fg, Some(theme.doc_comment));
+///
+/// let nested = highlight("/* a /* b */ c */ fn", Language::Rust, &theme);
+/// assert_eq!(nested.spans[0].content, "/* a /* b */ c */");
+/// assert_eq!(nested.spans[0].style.fg, Some(theme.comment));
+/// assert_eq!(nested.spans.last().unwrap().style.fg, Some(theme.keyword));
+/// ```
+///
+/// Control characters other than tab come out in caret notation, so they
+/// can't ring the bell or move the cursor:
+///
+/// ```
+/// use file_peek::{Language, Theme, highlight};
+///
+/// let theme = Theme::default();
+/// let line = highlight("ding\x07 \x1b[31mred", Language::Plain, &theme);
+/// let text: String = line.spans.iter().map(|span| span.content.as_ref()).collect();
+/// assert_eq!(text, "ding^G ^[[31mred");
+///
+/// let bell = line.spans.iter().find(|span| span.content == "^G").unwrap();
+/// assert_eq!(bell.style.fg, Some(theme.error));
+/// ```
+///
+/// Diff lines are colored whole by their prefix:
+///
+/// ```
+/// use file_peek::{Language, Theme, highlight};
+///
+/// let theme = Theme::default();
+/// let patch = [
+///     ("diff --git a/greet.rs b/greet.rs", theme.comment),
+///     ("index 3b18e51..a9c2f4d 100644", theme.comment),
+///     ("--- a/greet.rs", theme.comment),
+///     ("+++ b/greet.rs", theme.comment),
+///     ("@@ -1,3 +1,3 @@ fn main() {", theme.types),
+///     (" fn main() {", theme.foreground),
+///     ("-    println!(\"hi\");", theme.error),
+///     ("+    println!(\"hello\");", theme.string),
+/// ];
+/// for (text, color) in patch {
+///     let line = highlight(text, Language::Diff, &theme);
+///     assert_eq!(line.spans.len(), 1, "{text}");
+///     assert_eq!(line.spans[0].style.fg, Some(color), "{text}");
+/// }
+/// ```
+///
+/// `'` opens a string only in languages that quote with it. In prose it is
+/// an apostrophe, and in Rust it starts a char literal or a lifetime:
+///
+/// ```
+/// use file_peek::{Language, Theme, highlight};
+///
+/// let theme = Theme::default();
+/// let strings = |line: &str, language| -> Vec<String> {
+///     let line = highlight(line, language, &theme);
+///     let spans = line.spans.iter().filter(|span| span.style.fg == Some(theme.string));
+///     spans.map(|span| span.content.to_string()).collect()
+/// };
+/// assert!(strings("don't stop here", Language::Plain).is_empty());
+/// assert!(strings("It's the user's choice", Language::Markdown).is_empty());
+/// assert_eq!(strings("x = 'a' + 'b'", Language::Python), ["'a'", "'b'"]);
+///
+/// assert_eq!(strings("let x = 'a';", Language::Rust), ["'a'"]);
+/// assert!(strings("fn f<'a>(x: &'a str)", Language::Rust).is_empty());
+/// ```
+pub fn highlight(line: &str, language: Language, theme: &Theme) -> Line<'static> {
+    highlight_line(line, language, theme, None)
+}
+
+/// [`highlight`], except that line comments start only at the given
+/// prefixes rather than the built-in `//`, `#` and `/*`. An escape hatch
+/// for languages the highlighter doesn't know, such as `;` for INI or Lisp
+/// and `--` for SQL or Lua.
+///
+/// ```
+/// use file_peek::{Language, Theme, highlight, highlight_with_comments};
+///
+/// let theme = Theme::default();
+/// let line = highlight_with_comments("key = 1 ; note", Language::Plain, &theme, &[";"]);
+/// let comment = line.spans.last().unwrap();
+/// assert_eq!(comment.content, "; note");
+/// assert_eq!(comment.style.fg, Some(theme.comment));
+///
+/// let plain = highlight("key = 1 ; note", Language::Plain, &theme);
+/// assert!(plain.spans.iter().all(|span| span.style.fg != Some(theme.comment)));
+///
+/// // The built-in prefixes no longer apply
+/// let sql = highlight_with_comments("SELECT '#1' -- first", Language::Plain, &theme, &["--"]);
+/// assert_eq!(sql.spans.last().unwrap().content, "-- first");
+/// let hash = highlight_with_comments("x # y", Language::Plain, &theme, &["--"]);
+/// assert!(hash.spans.iter().all(|span| span.style.fg != Some(theme.comment)));
+/// ```
+pub fn highlight_with_comments<S: AsRef<str>>(
+    line: &str,
+    language: Language,
+    theme: &Theme,
+    comment_prefixes: &[S],
+) -> Line<'static> {
+    let prefixes: Vec<&str> = comment_prefixes
+        .iter()
+        .map(AsRef::as_ref)
+        .filter(|prefix| !prefix.is_empty())
+        .collect();
+    highlight_line(line, language, theme, Some(&prefixes))
+}
+
+/// Shared by [`highlight`] and [`highlight_with_comments`]; `None` for the
+/// built-in comment syntax.
+fn highlight_line(
+    line: &str,
+    language: Language,
+    theme: &Theme,
+    comment_prefixes: Option<&[&str]>,
+) -> Line<'static> {
+    if language == Language::Diff {
+        let span = Span::styled(line.to_string(), diff_style(line, theme));
+        return Line::from(join_clusters(show_controls(vec![span], theme)));
+    }
+
+    let mut spans = Vec::new();
+    let chars: Vec<char> = line.chars().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        // --- URLs (checked first so `://` isn't taken for a comment) ---
+        if let Some(len) = url_len(&chars[i..]) {
+            spans.push(Span::styled(
+                chars[i..i + len].iter().collect::<String>(),
+                theme.url_style(),
+            ));
+            i += len;
+            continue;
+        }
+
+        // --- Lifetimes and char literals (Rust) ---
+        if language == Language::Rust && c == '\'' {
+            if let Some(len) = char_literal_len(&chars[i..]) {
+                spans.push(Span::styled(
+                    chars[i..i + len].iter().collect::<String>(),
+                    Style::default().fg(theme.string),
+                ));
+                i += len;
+                continue;
+            }
+            if let Some(len) = lifetime_len(&chars[i..]) {
+                spans.push(Span::styled(
+                    chars[i..i + len].iter().collect::<String>(),
+                    Style::default().fg(theme.special).italic(),
+                ));
+                i += len;
+                continue;
+            }
+        }
+
+        // --- Strings ---
+        if is_string_delim(c, language) {
+            let len = string_literal_len(&chars[i..]);
+            spans.push(Span::styled(
+                chars[i..i + len].iter().collect::<String>(),
+                Style::default().fg(theme.string),
+            ));
+            i += len;
+            continue;
+        }
+
+        // --- Comments ---
+        if let Some(prefixes) = comment_prefixes {
+            let starts_here = |prefix: &&str| {
+                prefix
+                    .chars()
+                    .enumerate()
+                    .all(|(offset, p)| chars.get(i + offset) == Some(&p))
+            };
+            if prefixes.iter().any(starts_here) {
+                let style = Style::default().fg(theme.comment).italic();
+                push_comment(&mut spans, &chars[i..], style, theme);
+                break;
+            }
+        } else if c == '/' && chars.get(i + 1) == Some(&'*') {
+            // Code may follow the closing `*/` on the same line
+            let len = block_comment_len(&chars[i..], language == Language::Rust);
+            let style = comment_style(&chars[i..], language, theme);
+            push_comment(&mut spans, &chars[i..i + len], style, theme);
+            i += len;
+            continue;
+        }
+        if comment_prefixes.is_none() && ((c == '/' && chars.get(i + 1) == Some(&'/')) || c == '#')
+        {
+            let style = comment_style(&chars[i..], language, theme);
+            push_comment(&mut spans, &chars[i..], style, theme);
+            break;
+        }
+
+        if c.is_whitespace() {
+            spans.push(Span::raw(c.to_string()));
+            i += 1;
+            continue;
+        }
+
+        if is_word_start(c) {
+            let start = i;
+            i += 1;
+
+            while i < chars.len() && is_word_char(chars[i]) {
+                i += 1;
+            }
+
+            let word: String = chars[start..i].iter().collect();
+
+            let mut style = Style::default().fg(theme.foreground);
+
+            if is_keyword(&word) {
+                style = style.fg(theme.keyword).bold();
+            }
+
+            if is_type(&word) {
+                style = style.fg(theme.types);
+            }
+
+            if word.parse::<f64>().is_ok() {
+                style = style.fg(theme.number);
+            }
+
+            spans.push(Span::styled(word, style));
+            continue;
+        }
+
+        // --- Symbols / punctuation ---
+        spans.push(Span::styled(
+            c.to_string(),
+            Style::default().fg(theme.foreground),
+        ));
+        i += 1;
+    }
+
+    if spans.is_empty() {
+        Line::from(line.to_string())
+    } else {
+        Line::from(join_clusters(show_controls(spans, theme)))
+    }
+}
+
+/// Metadata lines that start a file's section of a unified diff.
+const DIFF_HEADERS: [&str; 10] = [
+    "diff ",
+    "index ",
+    "--- ",
+    "+++ ",
+    "new file mode",
+    "deleted file mode",
+    "old mode",
+    "new mode",
+    "similarity index",
+    "rename ",
+];
+
+/// Color for a whole diff line, chosen by its prefix.
+fn diff_style(line: &str, theme: &Theme) -> Style {
+    let color = if DIFF_HEADERS.iter().any(|header| line.starts_with(header)) {
+        theme.comment
+    } else if line.starts_with("@@") {
+        theme.types
+    } else if line.starts_with('+') {
+        theme.string
+    } else if line.starts_with('-') {
+        theme.error
+    } else {
+        theme.foreground
+    };
+    Style::default().fg(color)
+}
+
+/// Lines sampled by [`has_ansi_colors`]; enough to judge, cheap to rescan.
+const ANSI_SAMPLE_LINES: usize = 500;
+
+/// Length of the SGR (color and style) sequence at the start of `text`,
+/// `ESC [ params m` with only digits and `;` for params.
+fn sgr_len(text: &str) -> Option<usize> {
+    let params = text.strip_prefix("\x1b[")?;
+    let end = params.find(|c: char| !c.is_ascii_digit() && c != ';')?;
+    (params.as_bytes()[end] == b'm').then_some(2 + end + 1)
+}
+
+/// Length of any escape sequence at the start of `text`: CSI (`ESC [`)
+/// up to its final byte, OSC (`ESC ]`) up to BEL or `ESC \`, or a bare
+/// two-character escape.
+fn escape_len(text: &str) -> Option<usize> {
+    let rest = text.strip_prefix('\x1b')?;
+    if let Some(csi) = rest.strip_prefix('[') {
+        let end = csi.find(|c: char| ('\x40'..='\x7e').contains(&c))?;
+        Some(2 + end + 1)
+    } else if let Some(osc) = rest.strip_prefix(']') {
+        let end = osc.find(['\x07', '\x1b'])?;
+        let terminator = if osc[end..].starts_with("\x1b\\") {
+            2
+        } else {
+            1
+        };
+        Some(2 + end + terminator)
+    } else {
+        rest.chars().next().map(|c| 1 + c.len_utf8())
+    }
+}
+
+/// Whether `lines` look like colored program output rather than text that
+/// merely mentions escape codes: at least one line in ten of the first few
+/// hundred non-blank ones holds a well-formed SGR sequence with a real
+/// escape byte.
+///
+/// ```
+/// use file_peek::has_ansi_colors;
+///
+/// let output = ["\x1b[1;32m   Compiling\x1b[0m peek", "\x1b[31merror\x1b[0m: oops", "done"];
+/// assert!(has_ansi_colors(&output));
+///
+/// // Written out as text, the codes are just characters
+/// assert!(!has_ansi_colors(&[r"echo -e '\x1b[31mred\x1b[0m'"]));
+///
+/// // One colored line in a long file is a stray, not a log
+/// let mut notes = vec!["plain prose"; 40];
+/// notes.push("\x1b[31mred\x1b[0m");
+/// assert!(!has_ansi_colors(&notes));
+/// ```
+pub fn has_ansi_colors<S: AsRef<str>>(lines: &[S]) -> bool {
+    let (mut sampled, mut colored) = (0, 0);
+    for line in lines
+        .iter()
+        .map(AsRef::as_ref)
+        .filter(|line| !line.trim().is_empty())
+        .take(ANSI_SAMPLE_LINES)
+    {
+        sampled += 1;
+        if line
+            .match_indices('\x1b')
+            .any(|(start, _)| sgr_len(&line[start..]).is_some())
+        {
+            colored += 1;
+        }
+    }
+    colored > 0 && colored * 10 >= sampled
+}
+
+/// The 8 standard colors in SGR order, then their bright variants.
+const SGR_COLORS: [Color; 16] = [
+    Color::Black,
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::Gray,
+    Color::DarkGray,
+    Color::LightRed,
+    Color::LightGreen,
+    Color::LightYellow,
+    Color::LightBlue,
+    Color::LightMagenta,
+    Color::LightCyan,
+    Color::White,
+];
+
+/// An extended `38;5;n` or `38;2;r;g;b` color, taking its arguments from
+/// `params`.
+fn extended_color(params: &mut impl Iterator<Item = u16>) -> Option<Color> {
+    let channel = |value: Option<u16>| value.and_then(|value| u8::try_from(value).ok());
+    match params.next()? {
+        5 => channel(params.next()).map(Color::Indexed),
+        2 => {
+            let (r, g, b) = (params.next(), params.next(), params.next());
+            Some(Color::Rgb(channel(r)?, channel(g)?, channel(b)?))
+        }
+        _ => None,
+    }
+}
+
+/// Applies the SGR parameters `params` (the part between `ESC [` and `m`)
+/// to `style`.
+fn apply_sgr(style: Style, params: &str) -> Style {
+    let mut codes = params
+        .split(';')
+        .map(|code| code.parse::<u16>().unwrap_or(0));
+    let mut style = style;
+    while let Some(code) = codes.next() {
+        style = match code {
+            0 => Style::default(),
+            1 => style.add_modifier(Modifier::BOLD),
+            2 => style.add_modifier(Modifier::DIM),
+            3 => style.add_modifier(Modifier::ITALIC),
+            4 => style.add_modifier(Modifier::UNDERLINED),
+            7 => style.add_modifier(Modifier::REVERSED),
+            9 => style.add_modifier(Modifier::CROSSED_OUT),
+            22 => style.remove_modifier(Modifier::BOLD | Modifier::DIM),
+            23 => style.remove_modifier(Modifier::ITALIC),
+            24 => style.remove_modifier(Modifier::UNDERLINED),
+            27 => style.remove_modifier(Modifier::REVERSED),
+            29 => style.remove_modifier(Modifier::CROSSED_OUT),
+            30..=37 => style.fg(SGR_COLORS[usize::from(code - 30)]),
+            90..=97 => style.fg(SGR_COLORS[usize::from(code - 82)]),
+            40..=47 => style.bg(SGR_COLORS[usize::from(code - 40)]),
+            100..=107 => style.bg(SGR_COLORS[usize::from(code - 92)]),
+            38 => extended_color(&mut codes).map_or(style, |color| style.fg(color)),
+            48 => extended_color(&mut codes).map_or(style, |color| style.bg(color)),
+            39 => style.fg(Color::Reset),
+            49 => style.bg(Color::Reset),
+            _ => style,
+        };
+    }
+    style
+}
+
+/// A line of colored program output, styled by its SGR sequences instead
+/// of highlighted. Other escape sequences are dropped, and the remaining
+/// control characters come out in caret notation as in [`highlight`].
+///
+/// ```
+/// use file_peek::{Theme, render_ansi};
+/// use ratatui::style::{Color, Modifier};
+///
+/// let line = render_ansi("\x1b[1;31merror\x1b[0m: \x1b[38;5;208mhot\x1b[m\x1b[K", &Theme::default());
+/// let text: String = line.spans.iter().map(|span| span.content.as_ref()).collect();
+/// assert_eq!(text, "error: hot");
+///
+/// assert_eq!(line.spans[0].content, "error");
+/// assert_eq!(line.spans[0].style.fg, Some(Color::Red));
+/// assert!(line.spans[0].style.add_modifier.contains(Modifier::BOLD));
+/// assert_eq!(line.spans[1].style.fg, None);
+/// assert_eq!(line.spans[2].style.fg, Some(Color::Indexed(208)));
+/// ```
+pub fn render_ansi(line: &str, theme: &Theme) -> Line<'static> {
+    let mut spans = Vec::new();
+    let mut style = Style::default();
+    let mut text = String::new();
+    let mut rest = line;
+    while let Some(start) = rest.find('\x1b') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(len) = escape_len(rest) else {
+            // A cut-off sequence at the end of the line shows as `^[`
+            text.push_str(rest);
+            rest = "";
+            break;
+        };
+        if let Some(sgr) = sgr_len(rest) {
+            if !text.is_empty() {
+                spans.push(Span::styled(std::mem::take(&mut text), style));
+            }
+            style = apply_sgr(style, &rest[2..sgr - 1]);
+        }
+        rest = &rest[len..];
+    }
+    text.push_str(rest);
+    if !text.is_empty() {
+        spans.push(Span::styled(text, style));
+    }
+    Line::from(join_clusters(show_controls(spans, theme)))
+}
+
+/// `line` as text with SGR escapes for its colors and attributes, the way
+/// [`render_ansi`] reads them, with colors as near as `depth` allows. Only
+/// styled text gets escapes, so a plain line comes back unchanged.
+///
+/// ```
+/// use file_peek::{ColorDepth, Language, Theme, highlight, render_ansi, to_ansi};
+/// use ratatui::{style::Color, text::Line};
+///
+/// let theme = Theme::default();
+/// let line = highlight("let x = \"one\"; // 1", Language::Rust, &theme);
+/// let escaped = to_ansi(&line, ColorDepth::TrueColor);
+/// assert!(escaped.ends_with("\x1b[0m"));
+///
+/// // Reading the escapes back gives the same text in the same colors
+/// let colors = |line: &Line| -> Vec<(char, Option<Color>)> {
+///     let spans = line.spans.iter();
+///     spans.flat_map(|span| span.content.chars().map(|c| (c, span.style.fg))).collect()
+/// };
+/// assert_eq!(colors(&render_ansi(&escaped, &theme)), colors(&line));
+///
+/// assert_eq!(to_ansi(&Line::from("plain"), ColorDepth::Ansi16), "plain");
+/// let red = Line::styled("hot", Color::Rgb(255, 0, 0));
+/// assert_eq!(to_ansi(&red, ColorDepth::Ansi256), "\x1b[0;38;5;196mhot\x1b[0m");
+/// ```
+pub fn to_ansi(line: &Line, depth: ColorDepth) -> String {
+    const MODIFIERS: [(Modifier, u8); 6] = [
+        (Modifier::BOLD, 1),
+        (Modifier::DIM, 2),
+        (Modifier::ITALIC, 3),
+        (Modifier::UNDERLINED, 4),
+        (Modifier::REVERSED, 7),
+        (Modifier::CROSSED_OUT, 9),
+    ];
+    let mut escaped = String::new();
+    let mut styled = false;
+    for span in &line.spans {
+        let style = line.style.patch(span.style);
+        let mut codes: Vec<String> = MODIFIERS
+            .iter()
+            .filter(|(modifier, _)| style.add_modifier.contains(*modifier))
+            .map(|(_, code)| code.to_string())
+            .collect();
+        codes.extend(
+            style
+                .fg
+                .and_then(|color| sgr_color(depth.quantize(color), 30)),
+        );
+        codes.extend(
+            style
+                .bg
+                .and_then(|color| sgr_color(depth.quantize(color), 40)),
+        );
+
+        if !codes.is_empty() {
+            escaped.push_str(&format!("\x1b[0;{}m", codes.join(";")));
+        } else if styled {
+            escaped.push_str("\x1b[0m");
+        }
+        styled = !codes.is_empty();
+        escaped.push_str(&span.content);
+    }
+    if styled {
+        escaped.push_str("\x1b[0m");
+    }
+    escaped
+}
+
+/// The SGR parameters for `color` as a foreground (`base` 30) or
+/// background (`base` 40) color; `None` for the terminal's own.
+fn sgr_color(color: Color, base: u8) -> Option<String> {
+    match color {
+        Color::Reset => None,
+        Color::Indexed(index) => Some(format!("{};5;{index}", base + 8)),
+        Color::Rgb(r, g, b) => Some(format!("{};2;{r};{g};{b}", base + 8)),
+        named => {
+            let index = SGR_COLORS.iter().position(|&known| known == named)? as u8;
+            // The bright eight are 90-97 and 100-107
+            Some(match index {
+                0..8 => (base + index).to_string(),
+                _ => (base + 60 + index - 8).to_string(),
+            })
+        }
+    }
+}
+
+/// Replaces control characters other than tab with caret notation (`^G`,
+/// `^[`) in the error color, so a stray bell or escape shows up instead of
+/// ringing or moving the cursor.
+fn show_controls(spans: Vec<Span<'static>>, theme: &Theme) -> Vec<Span<'static>> {
+    let is_shown_control = |c: char| c.is_ascii_control() && c != '\t';
+    if !spans
+        .iter()
+        .any(|span| span.content.contains(is_shown_control))
+    {
+        return spans;
+    }
+    let control_style = Style::default().fg(theme.error);
+    let mut shown = Vec::with_capacity(spans.len());
+    for span in spans {
+        let mut text = String::new();
+        for c in span.content.chars() {
+            if !is_shown_control(c) {
+                text.push(c);
+                continue;
+            }
+            if !text.is_empty() {
+                shown.push(Span::styled(std::mem::take(&mut text), span.style));
+            }
+            // DEL is ^?, the rest of C0 is the character 64 places up
+            let caret = if c == '\x7f' {
+                '?'
+            } else {
+                (c as u8 + 0x40) as char
+            };
+            shown.push(Span::styled(format!("^{caret}"), control_style));
+        }
+        if !text.is_empty() {
+            shown.push(Span::styled(text, span.style));
+        }
+    }
+    shown
+}
+
+/// Moves the start of any span that continues the previous span's last
+/// grapheme cluster back onto that span. Highlighting works on `char`s, so
+/// combining marks, ZWJ emoji and flags would otherwise be split across
+/// spans and drawn a cell at a time.
+fn join_clusters(spans: Vec<Span<'static>>) -> Vec<Span<'static>> {
+    let mut joined: Vec<Span<'static>> = Vec::with_capacity(spans.len());
+    for mut span in spans {
+        if let Some(previous) = joined.last_mut()
+            && let Some(tail) = previous.content.graphemes(true).next_back()
+        {
+            let combined = format!("{tail}{}", span.content);
+            let cluster = combined.graphemes(true).next().unwrap_or_default();
+            let overlap = cluster.len().saturating_sub(tail.len());
+            if overlap > 0 {
+                let (head, rest) = span.content.split_at(overlap);
+                let (head, rest) = (head.to_string(), rest.to_string());
+                previous.content.to_mut().push_str(&head);
+                if rest.is_empty() {
+                    continue;
+                }
+                span.content = rest.into();
+            }
+        }
+        joined.push(span);
+    }
+    joined
+}
+
+/// Cells `text` takes on screen, counted per grapheme cluster the way the
+/// terminal (and ratatui) draws it rather than per `char`.
+///
+/// ```
+/// use file_peek::display_width;
+///
+/// assert_eq!(display_width("👨‍👩‍👧"), 2);
+/// assert_eq!(display_width("🇰🇪"), 2);
+/// assert_eq!(display_width("cafe\u{301}"), 4);
+/// assert_eq!(display_width("a👨‍👩‍👧b"), 4);
+/// ```
+///
+/// Highlighting keeps each cluster in a single span, so emoji sequences
+/// render whole:
+///
+/// ```
+/// use file_peek::{Language, Theme, highlight};
+///
+/// let line = highlight("x = \"👨‍👩‍👧\" + 👨‍👩‍👧;", Language::Rust, &Theme::default());
+/// assert!(line.spans.iter().any(|span| span.content == "👨‍👩‍👧"));
+/// ```
+pub fn display_width(text: &str) -> usize {
+    text.graphemes(true).map(UnicodeWidthStr::width).sum()
+}
+
+/// A byte count in binary units with one decimal, e.g. `1.2 MiB`. Under
+/// a KiB the exact count is shown.
+///
+/// ```
+/// use file_peek::human_size;
+///
+/// assert_eq!(human_size(0), "0 B");
+/// assert_eq!(human_size(1023), "1023 B");
+/// assert_eq!(human_size(1024), "1.0 KiB");
+/// assert_eq!(human_size(1536), "1.5 KiB");
+/// assert_eq!(human_size(1024 * 1024 - 1), "1.0 MiB");
+/// assert_eq!(human_size(1_258_291), "1.2 MiB");
+/// assert_eq!(human_size(5 * 1024 * 1024 * 1024), "5.0 GiB");
+/// ```
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    // Step up before the value would round to 1024.0 of the smaller unit
+    while value >= 1023.95 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+/// `count` with commas between each group of three digits.
+///
+/// ```
+/// use file_peek::group_digits;
+///
+/// assert_eq!(group_digits(0), "0");
+/// assert_eq!(group_digits(999), "999");
+/// assert_eq!(group_digits(1000), "1,000");
+/// assert_eq!(group_digits(1_234_567), "1,234,567");
+/// ```
+pub fn group_digits(count: u64) -> String {
+    let digits = count.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+fn is_word_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || c.is_ascii_digit()
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Display columns where words start on `line`, using the same identifier
+/// boundaries as [`highlight`].
+///
+/// ```
+/// use file_peek::word_columns;
+///
+/// assert_eq!(word_columns("id,name , 42"), vec![0, 3, 10]);
+/// assert_eq!(word_columns("→ 日本 x"), vec![2, 7]);
+/// ```
+pub fn word_columns(line: &str) -> Vec<usize> {
+    let mut columns = Vec::new();
+    let mut column = 0;
+    let mut in_word = false;
+    for cluster in line.graphemes(true) {
+        let c = cluster.chars().next().unwrap_or_default();
+        if in_word {
+            in_word = is_word_char(c);
+        } else if is_word_start(c) {
+            columns.push(column);
+            in_word = true;
+        }
+        column += cluster.width();
+    }
+    columns
+}
+
+/// Whether `word` occurs in `line` with no identifier characters right
+/// before or after it. An edge of `word` that is itself punctuation needs
+/// no boundary there, so `::new` matches in `Vec::new()`.
+///
+/// ```
+/// use file_peek::contains_word;
+///
+/// assert!(contains_word("a log here", "log"));
+/// assert!(contains_word("log", "log"));
+/// assert!(contains_word("(log).", "log"));
+/// assert!(!contains_word("logger", "log"));
+/// assert!(!contains_word("catalog", "log"));
+/// assert!(contains_word("catalog log_x log", "log"));
+/// assert!(contains_word("Vec::new()", "::new"));
+/// assert!(!contains_word("Vec::newest()", "::new"));
+/// ```
+pub fn contains_word(line: &str, word: &str) -> bool {
+    !find_matches(line, word, true).is_empty()
+}
+
+/// Byte ranges of each occurrence of `query` in `line`, left to right and
+/// not overlapping; with `whole_word`, only those [`contains_word`] would
+/// count.
+///
+/// ```
+/// use file_peek::find_matches;
+///
+/// assert_eq!(find_matches("log logger log", "log", false), [0..3, 4..7, 11..14]);
+/// assert_eq!(find_matches("log logger log", "log", true), [0..3, 11..14]);
+/// assert!(find_matches("anything", "", false).is_empty());
+/// ```
+pub fn find_matches(line: &str, query: &str, whole_word: bool) -> Vec<Range<usize>> {
+    if query.is_empty() {
+        return Vec::new();
+    }
+    let starts_word = query.chars().next().is_some_and(is_word_char);
+    let ends_word = query.chars().next_back().is_some_and(is_word_char);
+    line.match_indices(query)
+        .map(|(start, _)| start..start + query.len())
+        .filter(|range| {
+            if !whole_word {
+                return true;
+            }
+            let joined_before = starts_word
+                && line[..range.start]
+                    .chars()
+                    .next_back()
+                    .is_some_and(is_word_char);
+            let joined_after =
+                ends_word && line[range.end..].chars().next().is_some_and(is_word_char);
+            !joined_before && !joined_after
+        })
+        .collect()
+}
+
+/// Restyles each occurrence of `query` in an already highlighted line with
+/// `style` patched over its colors, splitting spans where a match starts or
+/// ends inside one.
+///
+/// ```
+/// use file_peek::{Language, Theme, highlight, mark_matches};
+/// use ratatui::style::{Color, Style};
+///
+/// let theme = Theme::default();
+/// let found = Style::default().bg(Color::Yellow);
+/// let line = mark_matches(highlight("let total = subtotal;", Language::Rust, &theme), "total", true, found);
+/// let marked: Vec<_> = line
+///     .spans
+///     .iter()
+///     .filter(|span| span.style.bg == Some(Color::Yellow))
+///     .map(|span| span.content.as_ref())
+///     .collect();
+/// assert_eq!(marked, ["total"]);
+///
+/// let line = mark_matches(highlight("let total = subtotal;", Language::Rust, &theme), "t t", false, found);
+/// let marked: String = line
+///     .spans
+///     .iter()
+///     .filter(|span| span.style.bg.is_some())
+///     .map(|span| span.content.as_ref())
+///     .collect();
+/// // Across the spans of `let`, the space and `total`, with `le` left alone
+/// assert_eq!(marked, "t t");
+/// assert_eq!(line.spans[0].content, "le");
+/// ```
+pub fn mark_matches<'a>(line: Line<'a>, query: &str, whole_word: bool, style: Style) -> Line<'a> {
+    let text: String = line
+        .spans
+        .iter()
+        .map(|span| span.content.as_ref())
+        .collect();
+    let ranges = find_matches(&text, query, whole_word);
+    mark_ranges(line, &ranges, style)
+}
+
+/// [`mark_matches`] for matches found some other way, such as by a regex:
+/// `ranges` are sorted, non-overlapping byte ranges of the line's text.
+///
+/// ```
+/// use file_peek::mark_ranges;
+/// use ratatui::{style::{Color, Style}, text::Line};
+///
+/// let line = mark_ranges(Line::from("v1.2.3"), &[1..2, 5..6], Style::default().fg(Color::Red));
+/// let contents: Vec<_> = line.spans.iter().map(|span| span.content.as_ref()).collect();
+/// assert_eq!(contents, ["v", "1", ".2.", "3"]);
+/// assert_eq!(line.spans[3].style.fg, Some(Color::Red));
+/// ```
+pub fn mark_ranges<'a>(line: Line<'a>, ranges: &[Range<usize>], style: Style) -> Line<'a> {
+    if ranges.is_empty() {
+        return line;
+    }
+    let mut marked = Vec::with_capacity(line.spans.len() + ranges.len() * 2);
+    let mut offset = 0;
+    for span in line.spans {
+        let end = offset + span.content.len();
+        // Cut the span wherever a match starts or ends inside it
+        let mut cuts: Vec<usize> = ranges
+            .iter()
+            .flat_map(|range| [range.start, range.end])
+            .filter(|&cut| cut > offset && cut < end)
+            .collect();
+        cuts.push(end);
+        let mut start = offset;
+        for cut in cuts {
+            let piece = span.content[start - offset..cut - offset].to_string();
+            let inside = ranges
+                .iter()
+                .any(|range| range.start <= start && start < range.end);
+            let piece_style = match inside {
+                true => span.style.patch(style),
+                false => span.style,
+            };
+            marked.push(Span::styled(piece, piece_style));
+            start = cut;
+        }
+        offset = end;
+    }
+    Line {
+        spans: marked,
+        ..line
+    }
+}
+
+/// First non-keyword identifier on `line`, such as the word under a
+/// "find references" command.
+pub fn first_identifier(line: &str) -> Option<String> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        if !is_word_start(chars[i]) {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < chars.len() && is_word_char(chars[i]) {
+            i += 1;
+        }
+        let word: String = chars[start..i].iter().collect();
+        if !is_keyword(&word) && word.parse::<f64>().is_err() {
+            return Some(word);
+        }
+    }
+
+    None
+}
+
+/// Style for the comment starting at `chars`: doc comments stand out from
+/// ordinary ones in Rust.
+fn comment_style(chars: &[char], language: Language, theme: &Theme) -> Style {
+    let starts_with = |prefix: &str| chars.iter().copied().take(prefix.len()).eq(prefix.chars());
+    let doc = language == Language::Rust
+        && ((starts_with("///") && !starts_with("////"))
+            || starts_with("//!")
+            || (starts_with("/**") && !starts_with("/**/") && !starts_with("/***"))
+            || starts_with("/*!"));
+    let color = if doc {
+        theme.doc_comment
+    } else {
+        theme.comment
+    };
+    Style::default().fg(color).italic()
+}
+
+/// Length of the `/* ... */` comment at `chars`, or the rest of the line if
+/// it doesn't close. With `nested` (Rust), each inner `/*` needs its own
+/// `*/`, so depth is counted rather than stopping at the first close.
+fn block_comment_len(chars: &[char], nested: bool) -> usize {
+    let mut depth = 1;
+    let mut i = 2;
+    while i < chars.len() {
+        match (chars[i], chars.get(i + 1)) {
+            ('*', Some('/')) => {
+                depth -= 1;
+                i += 2;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            ('/', Some('*')) if nested => {
+                depth += 1;
+                i += 2;
+            }
+            _ => i += 1,
+        }
+    }
+    chars.len()
+}
+
+/// Pushes `chars` as a comment, keeping any URLs in it clickable-looking.
+fn push_comment(spans: &mut Vec<Span<'_>>, chars: &[char], comment_style: Style, theme: &Theme) {
+    let mut text = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        if let Some(len) = url_len(&chars[i..]) {
+            if !text.is_empty() {
+                spans.push(Span::styled(std::mem::take(&mut text), comment_style));
+            }
+            spans.push(Span::styled(
+                chars[i..i + len].iter().collect::<String>(),
+                theme.url_style(),
+            ));
+            i += len;
+            continue;
+        }
+        text.push(chars[i]);
+        i += 1;
+    }
+
+    if !text.is_empty() {
+        spans.push(Span::styled(text, comment_style));
+    }
+}
+
+/// Length in chars of the `http://` or `https://` URL starting at `chars`.
+/// Trailing sentence punctuation and closing brackets are left out.
+fn url_len(chars: &[char]) -> Option<usize> {
+    let scheme = ["https://", "http://"]
+        .into_iter()
+        .find(|scheme| chars.iter().take(scheme.len()).copied().eq(scheme.chars()))?;
+
+    let mut len = chars
+        .iter()
+        .position(|&c| c.is_whitespace() || matches!(c, '"' | '\'' | '<' | '>' | '`'))
+        .unwrap_or(chars.len());
+    while len > scheme.len()
+        && matches!(
+            chars[len - 1],
+            '.' | ',' | ';' | ':' | '!' | '?' | ')' | ']' | '}'
+        )
+    {
+        len -= 1;
+    }
+
+    (len > scheme.len()).then_some(len)
+}
+
+/// All URLs on `line`, left to right.
+pub fn find_urls(line: &str) -> Vec<String> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut urls = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        match url_len(&chars[i..]) {
+            Some(len) => {
+                urls.push(chars[i..i + len].iter().collect());
+                i += len;
+            }
+            None => i += 1,
+        }
+    }
+
+    urls
+}
+
+fn is_keyword(word: &str) -> bool {
+    matches!(
+        word,
+        "fn" | "let"
+            | "mut"
+            | "const"
+            | "struct"
+            | "enum"
+            | "impl"
+            | "trait"
+            | "pub"
+            | "use"
+            | "if"
+            | "else"
+            | "match"
+            | "for"
+            | "while"
+            | "loop"
+            | "return"
+            | "break"
+            | "continue"
+            | "true"
+            | "false"
+            | "None"
+            | "Some"
+            | "Ok"
+            | "Err"
+            | "self"
+            | "macro_rules"
+    )
+}
+
+fn is_type(word: &str) -> bool {
+    matches!(
+        word,
+        "String"
+            | "Vec"
+            | "Option"
+            | "Result"
+            | "i32"
+            | "u64"
+            | "f64"
+            | "bool"
+            | "char"
+            | "usize"
+            | "PathBuf"
+            | "Cell"
+            | "Arc"
+            | "Rc"
+    )
+}
+
+/// Whether `c` opens a string in `language`. Prose, JSON and diffs only
+/// quote with `"`, and Rust's `'` is handled as a char literal or lifetime,
+/// so an apostrophe there doesn't color the rest of the line.
+fn is_string_delim(c: char, language: Language) -> bool {
+    match c {
+        '"' => true,
+        '\'' => !matches!(
+            language,
+            Language::Rust | Language::Json | Language::Markdown | Language::Diff | Language::Plain
+        ),
+        _ => false,
+    }
+}
+
+/// Length of the string literal opened by `chars[0]`, including both quotes.
+/// An unterminated string runs to the end of the line.
+fn string_literal_len(chars: &[char]) -> usize {
+    let delim = chars[0];
+    let mut i = 1;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 2,
+            c if c == delim => return i + 1,
+            _ => i += 1,
+        }
+    }
+    chars.len()
+}
+
+/// Length of a Rust char literal (`'x'`, `'\n'`, `'\u{1F600}'`) at `chars[0]`.
+fn char_literal_len(chars: &[char]) -> Option<usize> {
+    match chars.get(1)? {
+        '\\' => {
+            // Longest escape is `\u{10FFFF}`
+            let close = chars.iter().skip(2).take(10).position(|&c| c == '\'')?;
+            Some(close + 3)
+        }
+        '\'' => None,
+        _ => (chars.get(2) == Some(&'\'')).then_some(3),
+    }
+}
+
+/// Length of a Rust lifetime or label (`'a`, `'static`) at `chars[0]`:
+/// an apostrophe followed by an identifier with no closing quote.
+fn lifetime_len(chars: &[char]) -> Option<usize> {
+    let first = *chars.get(1)?;
+    if !(first.is_alphabetic() || first == '_') {
+        return None;
+    }
+    let ident = chars[1..].iter().take_while(|&&c| is_word_char(c)).count();
+    Some(ident + 1)
+}
+
+/// Renders highlighted `lines` as a standalone HTML page titled `title`,
+/// with each span's style as inline CSS on the theme's background.
+///
+/// ```
+/// use file_peek::{Theme, to_html};
+/// use ratatui::text::Line;
+///
+/// let lines = [Line::from("a < b && c > d")];
+/// let html = to_html("<demo>", &lines, &Theme::default(), true);
+///
+/// assert!(html.contains("<title>&lt;demo&gt;</title>"));
+/// assert!(html.contains("a &lt; b &amp;&amp; c &gt; d"));
+///
+/// // 256-color palette entries keep their color too
+/// use ratatui::style::{Color, Stylize};
+/// let lines = [Line::from("warm".fg(Color::Indexed(208))), Line::from("gray".fg(Color::Indexed(244)))];
+/// let html = to_html("palette", &lines, &Theme::default(), false);
+///
+/// assert!(html.contains("<span style=\"color: #ff8700\">warm</span>"));
+/// assert!(html.contains("<span style=\"color: #808080\">gray</span>"));
+/// ```
+pub fn to_html(title: &str, lines: &[Line<'_>], theme: &Theme, line_numbers: bool) -> String {
+    let css = |color| css_color(color).unwrap_or_else(|| "inherit".to_string());
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         <style>body {{ margin: 0; background: {}; color: {}; }} \
+         pre {{ margin: 0; padding: 1em; font-family: monospace; }} \
+         .ln {{ color: {}; user-select: none; }}</style>\n</head>\n<body>\n<pre>",
+        escape_html(title),
+        css(theme.background),
+        css(theme.foreground),
+        css(theme.comment),
+    );
+
+    let width = lines.len().to_string().len();
+    for (index, line) in lines.iter().enumerate() {
+        if line_numbers {
+            html.push_str(&format!("<span class=\"ln\">{:>width$} </span>", index + 1));
+        }
+        for span in &line.spans {
+            let style = span_css(line.style.patch(span.style));
+            let text = escape_html(&span.content);
+            if style.is_empty() {
+                html.push_str(&text);
+            } else {
+                html.push_str(&format!("<span style=\"{style}\">{text}</span>"));
+            }
+        }
+        html.push('\n');
+    }
+
+    html.push_str("</pre>\n</body>\n</html>\n");
+    html
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn span_css(style: Style) -> String {
+    let mut css = Vec::new();
+    if let Some(color) = style.fg.and_then(css_color) {
+        css.push(format!("color: {color}"));
+    }
+    if let Some(color) = style.bg.and_then(css_color) {
+        css.push(format!("background: {color}"));
+    }
+    if style.add_modifier.contains(Modifier::BOLD) {
+        css.push("font-weight: bold".to_string());
+    }
+    if style.add_modifier.contains(Modifier::ITALIC) {
+        css.push("font-style: italic".to_string());
+    }
+    if style.add_modifier.contains(Modifier::UNDERLINED) {
+        css.push("text-decoration: underline".to_string());
+    }
+    if style.add_modifier.contains(Modifier::DIM) {
+        css.push("opacity: 0.6".to_string());
+    }
+    css.join("; ")
+}
+
+/// CSS for a terminal color; `None` for the terminal's own default.
+fn css_color(color: Color) -> Option<String> {
+    let named = match color {
+        Color::Reset => return None,
+        Color::Rgb(..) | Color::Indexed(_) => {
+            let (r, g, b) = color_rgb(color)?;
+            return Some(format!("#{r:02x}{g:02x}{b:02x}"));
+        }
+        Color::Black => "black",
+        Color::Red => "darkred",
+        Color::Green => "green",
+        Color::Yellow => "olive",
+        Color::Blue => "navy",
+        Color::Magenta => "purple",
+        Color::Cyan => "teal",
+        Color::Gray => "silver",
+        Color::DarkGray => "gray",
+        Color::LightRed => "red",
+        Color::LightGreen => "lime",
+        Color::LightYellow => "yellow",
+        Color::LightBlue => "blue",
+        Color::LightMagenta => "fuchsia",
+        Color::LightCyan => "aqua",
+        Color::White => "white",
+    };
+    Some(named.to_string())
+}
+
+/// The RGB a terminal color stands for, taking the 16 named colors and the
+/// 256-color palette as xterm draws them; `None` for the terminal's own
+/// default.
+fn color_rgb(color: Color) -> Option<(u8, u8, u8)> {
+    const NAMED: [(u8, u8, u8); 16] = [
+        (0x00, 0x00, 0x00),
+        (0xcd, 0x00, 0x00),
+        (0x00, 0xcd, 0x00),
+        (0xcd, 0xcd, 0x00),
+        (0x00, 0x00, 0xee),
+        (0xcd, 0x00, 0xcd),
+        (0x00, 0xcd, 0xcd),
+        (0xe5, 0xe5, 0xe5),
+        (0x7f, 0x7f, 0x7f),
+        (0xff, 0x00, 0x00),
+        (0x00, 0xff, 0x00),
+        (0xff, 0xff, 0x00),
+        (0x5c, 0x5c, 0xff),
+        (0xff, 0x00, 0xff),
+        (0x00, 0xff, 0xff),
+        (0xff, 0xff, 0xff),
+    ];
+    let index = match color {
+        Color::Reset => return None,
+        Color::Rgb(r, g, b) => return Some((r, g, b)),
+        Color::Indexed(index) => index,
+        Color::Black => 0,
+        Color::Red => 1,
+        Color::Green => 2,
+        Color::Yellow => 3,
+        Color::Blue => 4,
+        Color::Magenta => 5,
+        Color::Cyan => 6,
+        Color::Gray => 7,
+        Color::DarkGray => 8,
+        Color::LightRed => 9,
+        Color::LightGreen => 10,
+        Color::LightYellow => 11,
+        Color::LightBlue => 12,
+        Color::LightMagenta => 13,
+        Color::LightCyan => 14,
+        Color::White => 15,
+    };
+    Some(match index {
+        0..16 => NAMED[usize::from(index)],
+        // A 6x6x6 color cube, then a ramp of 24 grays
+        16..232 => {
+            let level = |step: u8| if step == 0 { 0 } else { 55 + step * 40 };
+            let cube = index - 16;
+            (level(cube / 36), level(cube / 6 % 6), level(cube % 6))
+        }
+        232.. => {
+            let gray = 8 + (index - 232) * 10;
+            (gray, gray, gray)
+        }
+    })
+}
@@ -0,0 +1,158 @@
+//! Highlighting from whole-file syntax trees through [tree-sitter], behind
+//! the `tree-sitter` feature. Only Rust, Python and JavaScript, but every
+//! line is colored by where it sits in the parsed file, so block comments,
+//! raw strings and nested generics come out right however they span lines.
+//!
+//! [tree-sitter]: https://tree-sitter.github.io
+
+use crate::{Language, Theme, join_clusters, show_controls};
+use ratatui::prelude::*;
+use std::sync::OnceLock;
+use tree_sitter_highlight::{HighlightConfiguration, HighlightEvent, Highlighter};
+
+/// Capture names from the grammars' highlight queries that get a color, in
+/// the order `capture_style` matches them. A capture such as
+/// `function.macro` falls back to the longest listed name it starts with.
+const CAPTURES: &[&str] = &[
+    "comment",
+    "comment.documentation",
+    "string",
+    "escape",
+    "number",
+    "constant.builtin",
+    "keyword",
+    "type",
+    "constructor",
+    "constant",
+    "label",
+    "attribute",
+    "variable.builtin",
+];
+
+/// Style for `CAPTURES[index]`, with the colors [`highlight`](crate::highlight)
+/// gives the same kinds of token.
+fn capture_style(index: usize, theme: &Theme) -> Style {
+    let color = match CAPTURES[index] {
+        "comment" => theme.comment,
+        "comment.documentation" => theme.doc_comment,
+        "string" | "escape" => theme.string,
+        "number" | "constant.builtin" => theme.number,
+        "keyword" => return Style::default().fg(theme.keyword).bold(),
+        "type" | "constructor" => theme.types,
+        _ => theme.special,
+    };
+    Style::default().fg(color)
+}
+
+/// The highlight configuration for `language`, built on first use. `None`
+/// for languages without a bundled grammar.
+fn configuration(language: Language) -> Option<&'static HighlightConfiguration> {
+    static RUST: OnceLock<Option<HighlightConfiguration>> = OnceLock::new();
+    static PYTHON: OnceLock<Option<HighlightConfiguration>> = OnceLock::new();
+    static JAVASCRIPT: OnceLock<Option<HighlightConfiguration>> = OnceLock::new();
+    let build = |grammar: tree_sitter::Language, name: &str, highlights: &str| {
+        let mut configuration =
+            HighlightConfiguration::new(grammar, name, highlights, "", "").ok()?;
+        configuration.configure(CAPTURES);
+        Some(configuration)
+    };
+    let configuration = match language {
+        Language::Rust => RUST.get_or_init(|| {
+            build(
+                tree_sitter_rust::LANGUAGE.into(),
+                "rust",
+                tree_sitter_rust::HIGHLIGHTS_QUERY,
+            )
+        }),
+        Language::Python => PYTHON.get_or_init(|| {
+            build(
+                tree_sitter_python::LANGUAGE.into(),
+                "python",
+                tree_sitter_python::HIGHLIGHTS_QUERY,
+            )
+        }),
+        Language::JavaScript => JAVASCRIPT.get_or_init(|| {
+            build(
+                tree_sitter_javascript::LANGUAGE.into(),
+                "javascript",
+                tree_sitter_javascript::HIGHLIGHT_QUERY,
+            )
+        }),
+        _ => return None,
+    };
+    configuration.as_ref()
+}
+
+/// Highlights a whole file at once from its syntax tree.
+///
+/// ```
+/// use file_peek::{Language, Theme, TreeHighlighter};
+///
+/// let theme = Theme::default();
+/// let highlighter = TreeHighlighter::for_language(Language::Rust, &theme).unwrap();
+/// let source = ["/* starts here", "fn still_comment() {}", "*/", "fn code() {}"];
+/// let lines = highlighter.highlight(&source.map(String::from));
+/// assert_eq!(lines.len(), 4);
+/// assert_eq!(lines[1].spans[0].style.fg, Some(theme.comment));
+/// let keyword = lines[3].spans.iter().find(|span| span.content == "fn").unwrap();
+/// assert_eq!(keyword.style.fg, Some(theme.keyword));
+///
+/// assert!(TreeHighlighter::for_language(Language::Go, &theme).is_none());
+/// ```
+pub struct TreeHighlighter {
+    configuration: &'static HighlightConfiguration,
+    colors: Theme,
+}
+
+impl TreeHighlighter {
+    /// A highlighter for files in `language`, or `None` when no bundled
+    /// grammar covers it.
+    pub fn for_language(language: Language, theme: &Theme) -> Option<Self> {
+        Some(Self {
+            configuration: configuration(language)?,
+            colors: *theme,
+        })
+    }
+
+    /// Every line of the file, highlighted. A file the parser gives up on
+    /// comes back unstyled rather than half colored.
+    pub fn highlight(&self, lines: &[String]) -> Vec<Line<'static>> {
+        let source = lines.join("\n");
+        let plain = || lines.iter().map(|line| Line::from(line.clone())).collect();
+        let mut highlighter = Highlighter::new();
+        let Ok(events) =
+            highlighter.highlight(self.configuration, source.as_bytes(), None, None, |_| None)
+        else {
+            return plain();
+        };
+
+        let mut styles = vec![Style::default().fg(self.colors.foreground)];
+        let mut rows: Vec<Vec<Span<'static>>> = vec![Vec::new()];
+        for event in events {
+            match event {
+                Ok(HighlightEvent::HighlightStart(capture)) => {
+                    styles.push(capture_style(capture.0, &self.colors));
+                }
+                Ok(HighlightEvent::HighlightEnd) => {
+                    styles.pop();
+                }
+                Ok(HighlightEvent::Source { start, end }) => {
+                    let style = *styles.last().unwrap_or(&Style::default());
+                    for (index, piece) in source[start..end].split('\n').enumerate() {
+                        if index > 0 {
+                            rows.push(Vec::new());
+                        }
+                        if let Some(row) = rows.last_mut().filter(|_| !piece.is_empty()) {
+                            row.push(Span::styled(piece.to_string(), style));
+                        }
+                    }
+                }
+                Err(_) => return plain(),
+            }
+        }
+        rows.resize_with(lines.len(), Vec::new);
+        rows.into_iter()
+            .map(|spans| Line::from(join_clusters(show_controls(spans, &self.colors))))
+            .collect()
+    }
+}
@@ -0,0 +1,389 @@
+//! A scrolling, highlighted file view that other ratatui apps can embed.
+//!
+//! This is an API for embedding, separate from the `fp` binary's panes,
+//! which add wrapping, filters, search marks and gutters on top and keep
+//! their own state. The two share [`highlight`], [`max_scroll`] and the
+//! [`scrollbar`] look, so an embedded view matches `fp` without drawing
+//! through it.
+
+use crate::{Language, Theme, highlight};
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Paragraph, Scrollbar, ScrollbarOrientation, ScrollbarState};
+
+/// Largest `scroll` that still fills the viewport with file content.
+///
+/// ```
+/// use file_peek::max_scroll;
+///
+/// assert_eq!(max_scroll(100, 20), 80);
+/// assert_eq!(max_scroll(5, 20), 0);
+/// ```
+pub fn max_scroll(total_lines: usize, visible: usize) -> usize {
+    total_lines.saturating_sub(visible)
+}
+
+/// The vertical scrollbar `fp` and [`FileView`] draw over a right border,
+/// in `theme`'s colors.
+pub fn scrollbar(theme: &Theme) -> Scrollbar<'static> {
+    Scrollbar::default()
+        .orientation(ScrollbarOrientation::VerticalRight)
+        .track_style(Style::default().fg(theme.selection))
+        .thumb_style(Style::default().fg(theme.keyword))
+}
+
+/// Lines a page moves, keeping `overlap` lines of the old page in view.
+/// Always at least one, so an overlap as large as the page can't get stuck.
+fn page_step(visible: usize, overlap: usize) -> usize {
+    visible.saturating_sub(overlap).max(1)
+}
+
+/// Scroll a page below `scroll`, keeping `overlap` lines of the old page
+/// and stopping where the last line reaches the bottom.
+///
+/// ```
+/// use file_peek::page_down;
+///
+/// assert_eq!(page_down(0, 100, 20, 0), 20);
+/// assert_eq!(page_down(0, 100, 20, 2), 18);
+/// assert_eq!(page_down(75, 100, 20, 0), 80);
+/// // An overlap as tall as the page still moves a line
+/// assert_eq!(page_down(0, 100, 20, 20), 1);
+/// ```
+pub fn page_down(scroll: usize, total_lines: usize, visible: usize, overlap: usize) -> usize {
+    scroll
+        .saturating_add(page_step(visible, overlap))
+        .min(max_scroll(total_lines, visible))
+}
+
+/// Scroll a page above `scroll`, keeping `overlap` lines of the old page.
+///
+/// ```
+/// use file_peek::page_up;
+///
+/// assert_eq!(page_up(50, 20, 0), 30);
+/// assert_eq!(page_up(10, 20, 2), 0);
+/// ```
+pub fn page_up(scroll: usize, visible: usize, overlap: usize) -> usize {
+    scroll.saturating_sub(page_step(visible, overlap))
+}
+
+/// Scroll position and selected line of a [`FileView`], kept between
+/// frames. How far a page moves comes from the area last rendered into.
+#[derive(Debug, Clone, Default)]
+pub struct FileViewState {
+    scroll: usize,
+    selected: Option<usize>,
+    /// Lines of the old page kept in view by `page_down` and `page_up`
+    page_overlap: usize,
+    /// Rows and lines at the last render, for clamping between frames
+    visible: usize,
+    total_lines: usize,
+}
+
+impl FileViewState {
+    /// Keeps `overlap` lines of the old page in view when paging.
+    pub fn with_page_overlap(mut self, overlap: usize) -> Self {
+        self.page_overlap = overlap;
+        self
+    }
+
+    /// Index of the line at the top of the view.
+    pub fn scroll(&self) -> usize {
+        self.scroll
+    }
+
+    pub fn selected(&self) -> Option<usize> {
+        self.selected
+    }
+
+    /// Puts line `line` at the top, as far as the end of the file allows.
+    pub fn set_scroll(&mut self, line: usize) {
+        self.scroll = line.min(max_scroll(self.total_lines, self.visible));
+    }
+
+    pub fn scroll_down(&mut self, lines: usize) {
+        self.set_scroll(self.scroll.saturating_add(lines));
+    }
+
+    pub fn scroll_up(&mut self, lines: usize) {
+        self.scroll = self.scroll.saturating_sub(lines);
+    }
+
+    pub fn page_down(&mut self) {
+        self.scroll = page_down(
+            self.scroll,
+            self.total_lines,
+            self.visible,
+            self.page_overlap,
+        );
+    }
+
+    pub fn page_up(&mut self) {
+        self.scroll = page_up(self.scroll, self.visible, self.page_overlap);
+    }
+
+    pub fn top(&mut self) {
+        self.scroll = 0;
+    }
+
+    pub fn bottom(&mut self) {
+        self.scroll = max_scroll(self.total_lines, self.visible);
+    }
+
+    /// Selects line `line`, or clears the selection, scrolling just far
+    /// enough to bring it into view.
+    pub fn select(&mut self, line: Option<usize>) {
+        self.selected = line;
+        if let Some(line) = line {
+            self.scroll_to(line);
+        }
+    }
+
+    /// Moves the selection `count` lines down, starting from the top line
+    /// when nothing is selected.
+    pub fn select_next(&mut self, count: usize) {
+        let last = self.total_lines.saturating_sub(1);
+        let line = self
+            .selected
+            .map_or(self.scroll, |line| line.saturating_add(count));
+        self.select(Some(line.min(last)));
+    }
+
+    /// Moves the selection `count` lines up, starting from the top line
+    /// when nothing is selected.
+    pub fn select_previous(&mut self, count: usize) {
+        let line = self
+            .selected
+            .map_or(self.scroll, |line| line.saturating_sub(count));
+        self.select(Some(line));
+    }
+
+    fn scroll_to(&mut self, line: usize) {
+        if line < self.scroll {
+            self.scroll = line;
+        } else if self.visible > 0 && line >= self.scroll + self.visible {
+            self.scroll = line + 1 - self.visible;
+        }
+    }
+}
+
+/// Lines of text highlighted for their language, with a scrollbar on the
+/// right once they overflow, as `fp` shows a file without its extras.
+/// Position and selection live in a [`FileViewState`].
+///
+/// ```
+/// use file_peek::{FileView, FileViewState, Language};
+/// use ratatui::{Terminal, backend::TestBackend, widgets::Block};
+///
+/// let lines: Vec<String> = (1..=50).map(|n| format!("line {n}")).collect();
+/// let mut state = FileViewState::default();
+/// let mut terminal = Terminal::new(TestBackend::new(20, 7)).unwrap();
+/// let mut draw = |state: &mut FileViewState| {
+///     terminal
+///         .draw(|frame| {
+///             let view = FileView::new(&lines, Language::Plain).block(Block::bordered());
+///             frame.render_stateful_widget(view, frame.area(), state);
+///         })
+///         .unwrap();
+/// };
+/// draw(&mut state);
+///
+/// // Five rows inside the border
+/// state.page_down();
+/// assert_eq!(state.scroll(), 5);
+/// state.select_next(1);
+/// state.select_next(6);
+/// assert_eq!(state.selected(), Some(11));
+/// assert_eq!(state.scroll(), 7);
+///
+/// state.bottom();
+/// draw(&mut state);
+/// assert_eq!(state.scroll(), 45);
+/// ```
+#[derive(Debug, Clone)]
+pub struct FileView<'a> {
+    lines: &'a [String],
+    language: Language,
+    theme: Theme,
+    block: Option<Block<'a>>,
+}
+
+impl<'a> FileView<'a> {
+    pub fn new(lines: &'a [String], language: Language) -> Self {
+        Self {
+            lines,
+            language,
+            theme: Theme::default(),
+            block: None,
+        }
+    }
+
+    /// Colors lines with `theme` rather than the default dark palette.
+    pub fn theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Draws the view inside `block`.
+    pub fn block(mut self, block: Block<'a>) -> Self {
+        self.block = Some(block);
+        self
+    }
+}
+
+impl StatefulWidget for FileView<'_> {
+    type State = FileViewState;
+
+    fn render(self, area: Rect, buf: &mut Buffer, state: &mut FileViewState) {
+        let inner = match &self.block {
+            Some(block) => {
+                let inner = block.inner(area);
+                block.render(area, buf);
+                inner
+            }
+            None => area,
+        };
+        state.visible = usize::from(inner.height);
+        state.total_lines = self.lines.len();
+        state.set_scroll(state.scroll);
+
+        let overflows = state.total_lines > state.visible;
+        let text_area = Rect {
+            width: inner.width.saturating_sub(u16::from(overflows)),
+            ..inner
+        };
+        let selected_style = Style::default().bg(self.theme.selection);
+        let shown: Vec<Line<'static>> = self
+            .lines
+            .iter()
+            .enumerate()
+            .skip(state.scroll)
+            .take(state.visible)
+            .map(|(index, line)| {
+                let line = highlight(line, self.language, &self.theme);
+                match state.selected == Some(index) {
+                    true => line.style(selected_style),
+                    false => line,
+                }
+            })
+            .collect();
+        let text_style = Style::default().fg(self.theme.foreground);
+        Paragraph::new(shown)
+            .style(text_style)
+            .render(text_area, buf);
+
+        if overflows {
+            let mut scrollbar_state =
+                ScrollbarState::new(max_scroll(state.total_lines, state.visible))
+                    .position(state.scroll);
+            scrollbar(&self.theme).render(inner, buf, &mut scrollbar_state);
+        }
+    }
+}
+
+/// A [`FileView`] and its state in one value for apps that don't keep
+/// their own: lines, their language and a [`FileViewState`], scrolled a
+/// line or a page at a time. `fp` itself doesn't draw through it; see the
+/// [module docs](self).
+///
+/// ```
+/// use file_peek::{Language, PeekApp};
+/// use ratatui::{Terminal, backend::TestBackend};
+///
+/// let lines = (1..=100).map(|n| format!("let x{n} = {n};")).collect();
+/// let mut app = PeekApp::new(lines, Language::Rust);
+/// let mut terminal = Terminal::new(TestBackend::new(20, 10)).unwrap();
+/// terminal.draw(|frame| app.render(frame, frame.area())).unwrap();
+///
+/// app.page_down();
+/// assert_eq!(app.scroll(), 10);
+/// app.scroll_down(3);
+/// app.page_up();
+/// assert_eq!(app.scroll(), 3);
+///
+/// app.bottom();
+/// assert_eq!(app.scroll(), 90);
+/// terminal.draw(|frame| app.render(frame, frame.area())).unwrap();
+/// let row: String = (0..19)
+///     .map(|x| terminal.backend().buffer()[(x, 9)].symbol().to_string())
+///     .collect();
+/// assert_eq!(row.trim_end(), "let x100 = 100;");
+/// ```
+#[derive(Debug, Clone)]
+pub struct PeekApp {
+    lines: Vec<String>,
+    language: Language,
+    theme: Theme,
+    state: FileViewState,
+}
+
+impl PeekApp {
+    pub fn new(lines: Vec<String>, language: Language) -> Self {
+        Self {
+            lines,
+            language,
+            theme: Theme::default(),
+            state: FileViewState::default(),
+        }
+    }
+
+    /// Colors lines with `theme` rather than the default dark palette.
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Keeps `overlap` lines of the old page in view when paging.
+    pub fn with_page_overlap(mut self, overlap: usize) -> Self {
+        self.state = self.state.with_page_overlap(overlap);
+        self
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    pub fn state(&self) -> &FileViewState {
+        &self.state
+    }
+
+    pub fn state_mut(&mut self) -> &mut FileViewState {
+        &mut self.state
+    }
+
+    /// Index of the line at the top of the view.
+    pub fn scroll(&self) -> usize {
+        self.state.scroll()
+    }
+
+    pub fn scroll_down(&mut self, lines: usize) {
+        self.state.scroll_down(lines);
+    }
+
+    pub fn scroll_up(&mut self, lines: usize) {
+        self.state.scroll_up(lines);
+    }
+
+    pub fn page_down(&mut self) {
+        self.state.page_down();
+    }
+
+    pub fn page_up(&mut self) {
+        self.state.page_up();
+    }
+
+    pub fn top(&mut self) {
+        self.state.top();
+    }
+
+    pub fn bottom(&mut self) {
+        self.state.bottom();
+    }
+
+    /// Draws the lines on screen into `area`, which also sets how far a
+    /// page moves from now on.
+    pub fn render(&mut self, frame: &mut Frame, area: Rect) {
+        let view = FileView::new(&self.lines, self.language).theme(self.theme);
+        frame.render_stateful_widget(view, area, &mut self.state);
+    }
+}